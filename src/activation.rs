@@ -0,0 +1,215 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Socket activation: adopting sockets that a service manager created and
+//! passed to the process, instead of binding them.
+
+use std::env;
+#[cfg(all(target_os = "macos", feature = "launchd"))]
+use std::ffi::CString;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::process;
+
+use libc;
+
+use super::{local_addr, set_cloexec, FromSocketFd, SocketAddr};
+
+// The first descriptor systemd passes, after stdin, stdout and stderr.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket handed over by the service manager. Check it and wrap it in
+/// the socket type it should be with `into_socket()`.
+#[derive(Debug)]
+pub struct ActivatedSocket {
+    fd: OwnedFd,
+    name: Option<String>,
+}
+
+impl ActivatedSocket {
+    /// Returns the name the service manager gave the socket, such as from
+    /// systemd's `FileDescriptorName=`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        local_addr(self.fd.as_raw_fd())
+    }
+
+    /// Checks that the socket is bound to `path`, failing with
+    /// `ErrorKind::InvalidInput` if it is bound elsewhere, as when the
+    /// unit file and the service's configuration disagree.
+    pub fn expect_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let addr = self.local_addr()?;
+        if addr.as_pathname() != Some(path.as_ref()) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("socket is bound to {}, not {}",
+                                          addr, path.as_ref().display())));
+        }
+        Ok(())
+    }
+
+    /// Wraps the socket as `S`, after checking that it is an `AF_UNIX`
+    /// socket of the right type, so a listener must be listening and a
+    /// datagram socket must be `SOCK_DGRAM`.
+    pub fn into_socket<S: FromSocketFd>(self) -> Result<S> {
+        S::from_socket_fd(self.fd)
+    }
+}
+
+impl AsRawFd for ActivatedSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for ActivatedSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl From<ActivatedSocket> for OwnedFd {
+    fn from(sock: ActivatedSocket) -> OwnedFd {
+        sock.fd
+    }
+}
+
+/// Adopts the sockets passed by systemd socket activation, as described by
+/// `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`, in order. Returns no
+/// sockets if the process wasn't socket-activated, or the variables were
+/// meant for another process.
+///
+/// The variables are removed from the environment, so they aren't
+/// inherited by children, and a second call returns no sockets rather
+/// than taking ownership of the same descriptors again. The descriptors
+/// are made close-on-exec.
+pub fn from_systemd() -> Result<Vec<ActivatedSocket>> {
+    let pid = env::var("LISTEN_PID");
+    let fds = env::var("LISTEN_FDS");
+    let names = env::var("LISTEN_FDNAMES");
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let (pid, fds) = match (pid, fds) {
+        (Ok(pid), Ok(fds)) => (pid, fds),
+        _ => return Ok(Vec::new()),
+    };
+    let invalid = |var| {
+        Error::new(ErrorKind::InvalidData, format!("invalid {}", var))
+    };
+    let pid: u32 = pid.trim().parse().map_err(|_| invalid("LISTEN_PID"))?;
+    if pid != process::id() {
+        return Ok(Vec::new());
+    }
+    let count: RawFd = fds.trim().parse().map_err(|_| invalid("LISTEN_FDS"))?;
+    let end = match LISTEN_FDS_START.checked_add(count) {
+        Some(end) if count >= 0 => end,
+        _ => return Err(invalid("LISTEN_FDS")),
+    };
+    // Only descriptors that are open can be owned, so check them all
+    // before taking any. Once they are owned, an error closes them all
+    // rather than leaking the rest.
+    for fd in LISTEN_FDS_START..end {
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("LISTEN_FDS includes descriptor \
+                                           {}, which isn't open", fd)));
+        }
+    }
+    let fds: Vec<OwnedFd> = (LISTEN_FDS_START..end)
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect();
+
+    for fd in &fds {
+        set_cloexec(fd.as_raw_fd(), true)?;
+    }
+    let mut names = names.ok().map(|names| {
+        names.split(':').map(String::from).collect::<Vec<_>>().into_iter()
+    });
+    Ok(fds.into_iter().map(|fd| {
+        ActivatedSocket {
+            fd,
+            name: names.as_mut().and_then(Iterator::next),
+        }
+    }).collect())
+}
+
+#[cfg(all(target_os = "macos", feature = "launchd"))]
+extern "C" {
+    fn launch_activate_socket(name: *const libc::c_char,
+                              fds: *mut *mut libc::c_int,
+                              cnt: *mut libc::size_t)
+                              -> libc::c_int;
+}
+
+/// Adopts the sockets launchd created for the `Sockets` entry `name` in the
+/// job's property list. Each socket is named `name`.
+///
+/// launchd hands a socket set over only once, so a second call for the same
+/// name fails with `ErrorKind::AlreadyExists`. If the process isn't managed
+/// by launchd, or the job has no such entry, this fails with
+/// `ErrorKind::NotFound`.
+#[cfg(all(target_os = "macos", feature = "launchd"))]
+pub fn from_launchd(name: &str) -> Result<Vec<ActivatedSocket>> {
+    let c_name = CString::new(name).map_err(|_| {
+        Error::new(ErrorKind::InvalidInput, "name contains a nul byte")
+    })?;
+    let mut fds: *mut libc::c_int = ::std::ptr::null_mut();
+    let mut cnt: libc::size_t = 0;
+    let err = unsafe {
+        launch_activate_socket(c_name.as_ptr(), &mut fds, &mut cnt)
+    };
+    match err {
+        0 => {}
+        libc::ENOENT | libc::ESRCH => {
+            return Err(Error::new(ErrorKind::NotFound,
+                                  format!("no launchd socket named {}", name)))
+        }
+        libc::EALREADY => {
+            return Err(Error::new(ErrorKind::AlreadyExists,
+                                  format!("launchd socket {} was already \
+                                           activated",
+                                          name)))
+        }
+        err => return Err(Error::from_raw_os_error(err)),
+    }
+
+    if fds.is_null() {
+        return Ok(Vec::new());
+    }
+    let raw = unsafe { ::std::slice::from_raw_parts(fds, cnt) }.to_vec();
+    unsafe { libc::free(fds as *mut libc::c_void) };
+    let socks = raw.into_iter().map(|fd| {
+        ActivatedSocket {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            name: Some(name.to_owned()),
+        }
+    }).collect::<Vec<_>>();
+    for sock in &socks {
+        set_cloexec(sock.as_raw_fd(), true)?;
+    }
+    Ok(socks)
+}
@@ -0,0 +1,382 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Ancillary data (control messages) sent and received alongside socket
+//! data.
+
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libc;
+
+// Not yet in the libc crate; from <linux/socket.h>.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SCM_SECURITY: libc::c_int = 0x03;
+#[cfg(target_os = "linux")]
+const SCM_PIDFD: libc::c_int = 0x04;
+
+fn cmsg_len(len: usize) -> usize {
+    unsafe { libc::CMSG_LEN(len as libc::c_uint) as usize }
+}
+
+fn cmsg_space(len: usize) -> usize {
+    unsafe { libc::CMSG_SPACE(len as libc::c_uint) as usize }
+}
+
+fn system_time(secs: libc::time_t, nanos: u32) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::new(-(secs + 1) as u64 + 1, 0)
+            + Duration::new(0, nanos)
+    }
+}
+
+/// The credentials of a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UCred {
+    /// The process ID, where the platform provides it.
+    pub pid: Option<libc::pid_t>,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+/// A buffer for building control messages to pass to `send_msg()`.
+///
+/// Messages are appended to a caller-provided buffer, which needs
+/// `space_for_fds()` or `space_for_creds()` bytes for each message. The
+/// buffer needn't be aligned.
+#[derive(Debug)]
+pub struct SocketAncillary<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SocketAncillary<'a> {
+    pub fn new(buf: &'a mut [u8]) -> SocketAncillary<'a> {
+        SocketAncillary {
+            buf,
+            len: 0,
+        }
+    }
+
+    /// The buffer space needed for a message carrying `count` file
+    /// descriptors.
+    pub fn space_for_fds(count: usize) -> usize {
+        cmsg_space(count * mem::size_of::<RawFd>())
+    }
+
+    /// The buffer space needed for a message carrying `count` sets of
+    /// credentials.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn space_for_creds(count: usize) -> usize {
+        cmsg_space(count * mem::size_of::<libc::ucred>())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The number of bytes of control messages added so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The control messages, ready to pass to `send_msg()`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn messages(&self) -> ControlMessages<'_> {
+        ControlMessages::new(self.as_bytes())
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Appends an `SCM_RIGHTS` message passing `fds` to the peer.
+    pub fn add_fds(&mut self, fds: &[BorrowedFd]) -> Result<()> {
+        let raw: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        self.add(libc::SOL_SOCKET, libc::SCM_RIGHTS, &raw)
+    }
+
+    /// Appends an `SCM_CREDENTIALS` message. The kernel checks the
+    /// credentials against the sender's unless it is privileged. A `pid` of
+    /// `None` is sent as the current process's ID.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn add_creds(&mut self, creds: &[UCred]) -> Result<()> {
+        let raw: Vec<libc::ucred> = creds.iter().map(|cred| {
+            libc::ucred {
+                pid: cred.pid.unwrap_or_else(|| unsafe { libc::getpid() }),
+                uid: cred.uid,
+                gid: cred.gid,
+            }
+        }).collect();
+        self.add(libc::SOL_SOCKET, libc::SCM_CREDENTIALS, &raw)
+    }
+
+    fn add<T: Copy>(&mut self, level: libc::c_int, ty: libc::c_int,
+                    items: &[T]) -> Result<()> {
+        let data_len = mem::size_of_val(items);
+        let space = cmsg_space(data_len);
+        if space > self.buf.len() - self.len {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "ancillary buffer is too small"));
+        }
+
+        let msg = &mut self.buf[self.len..self.len + space];
+        msg.fill(0);
+
+        let mut hdr: libc::cmsghdr = unsafe { mem::zeroed() };
+        hdr.cmsg_len = cmsg_len(data_len) as _;
+        hdr.cmsg_level = level;
+        hdr.cmsg_type = ty;
+        unsafe {
+            ptr::write_unaligned(msg.as_mut_ptr() as *mut libc::cmsghdr, hdr);
+            ptr::copy_nonoverlapping(items.as_ptr() as *const u8,
+                                     msg.as_mut_ptr().add(cmsg_len(0)),
+                                     data_len);
+        }
+
+        self.len += space;
+        Ok(())
+    }
+}
+
+/// A single control message.
+#[derive(Clone, Copy, Debug)]
+pub struct ControlMessage<'a> {
+    level: libc::c_int,
+    ty: libc::c_int,
+    data: &'a [u8],
+}
+
+impl<'a> ControlMessage<'a> {
+    /// The protocol level of the message, such as `libc::SOL_SOCKET`.
+    pub fn level(&self) -> libc::c_int {
+        self.level
+    }
+
+    /// The type of the message, such as `libc::SCM_RIGHTS`.
+    pub fn ty(&self) -> libc::c_int {
+        self.ty
+    }
+
+    /// The payload of the message.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// If this is an `SCM_RIGHTS` message, returns the file descriptors it
+    /// carries. The descriptors are open in this process, and the caller
+    /// is responsible for closing them.
+    pub fn as_rights(&self) -> Option<ScmRights<'a>> {
+        if self.level == libc::SOL_SOCKET && self.ty == libc::SCM_RIGHTS {
+            Some(ScmRights {
+                data: self.data,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// If this is an `SCM_CREDENTIALS` message, returns the credentials it
+    /// carries. The receiving socket needs `set_passcred()` for the kernel to
+    /// deliver these.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn as_credentials(&self) -> Option<ScmCredentials<'a>> {
+        if self.level == libc::SOL_SOCKET
+            && self.ty == libc::SCM_CREDENTIALS {
+            Some(ScmCredentials {
+                data: self.data,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// If this is an `SCM_SECURITY` message, returns the sender's security
+    /// label, such as its SELinux context, as the raw bytes the kernel
+    /// reports. The receiving socket needs `set_passsec()` for the kernel to
+    /// deliver these.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn as_security_label(&self) -> Option<&'a [u8]> {
+        if self.level == libc::SOL_SOCKET && self.ty == SCM_SECURITY {
+            Some(self.data)
+        } else {
+            None
+        }
+    }
+
+    /// If this is an `SCM_TIMESTAMP` or `SCM_TIMESTAMPNS` message, returns
+    /// the time the kernel received the data. The receiving socket needs
+    /// `set_timestamping()` or `set_timestamping_ns()` for the kernel to
+    /// deliver these.
+    pub fn as_timestamp(&self) -> Option<SystemTime> {
+        if self.level != libc::SOL_SOCKET {
+            return None;
+        }
+
+        if self.ty == libc::SCM_TIMESTAMP
+            && self.data.len() >= mem::size_of::<libc::timeval>() {
+            let tv = unsafe {
+                ptr::read_unaligned(self.data.as_ptr() as *const libc::timeval)
+            };
+            return Some(system_time(tv.tv_sec, tv.tv_usec as u32 * 1000));
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            if self.ty == libc::SCM_TIMESTAMPNS
+                && self.data.len() >= mem::size_of::<libc::timespec>() {
+                let ts = unsafe {
+                    ptr::read_unaligned(
+                        self.data.as_ptr() as *const libc::timespec)
+                };
+                return Some(system_time(ts.tv_sec, ts.tv_nsec as u32));
+            }
+        }
+
+        None
+    }
+
+    /// If this is an `SCM_PIDFD` message, returns the pidfd of the sending
+    /// process. It is open in this process, and the caller is responsible
+    /// for closing it. The receiving socket needs `set_passpidfd()` for the
+    /// kernel to deliver these.
+    #[cfg(target_os = "linux")]
+    pub fn as_pidfd(&self) -> Option<RawFd> {
+        if self.level == libc::SOL_SOCKET && self.ty == SCM_PIDFD
+            && self.data.len() >= mem::size_of::<RawFd>() {
+            Some(unsafe {
+                ptr::read_unaligned(self.data.as_ptr() as *const RawFd)
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator over the file descriptors in an `SCM_RIGHTS` message.
+#[derive(Clone, Debug)]
+pub struct ScmRights<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for ScmRights<'a> {
+    type Item = RawFd;
+
+    fn next(&mut self) -> Option<RawFd> {
+        if self.data.len() < mem::size_of::<RawFd>() {
+            return None;
+        }
+
+        let fd = unsafe {
+            ptr::read_unaligned(self.data.as_ptr() as *const RawFd)
+        };
+        self.data = &self.data[mem::size_of::<RawFd>()..];
+        Some(fd)
+    }
+}
+
+/// An iterator over the credentials in an `SCM_CREDENTIALS` message.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug)]
+pub struct ScmCredentials<'a> {
+    data: &'a [u8],
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl<'a> Iterator for ScmCredentials<'a> {
+    type Item = UCred;
+
+    fn next(&mut self) -> Option<UCred> {
+        if self.data.len() < mem::size_of::<libc::ucred>() {
+            return None;
+        }
+
+        let cred = unsafe {
+            ptr::read_unaligned(self.data.as_ptr() as *const libc::ucred)
+        };
+        self.data = &self.data[mem::size_of::<libc::ucred>()..];
+        Some(UCred {
+            pid: Some(cred.pid),
+            uid: cred.uid,
+            gid: cred.gid,
+        })
+    }
+}
+
+/// An iterator over the control messages in a buffer filled in by
+/// `recvmsg(2)`.
+///
+/// The buffer needn't be aligned; headers and payloads are located by
+/// their offsets from the start of the buffer, as the kernel lays them out.
+#[derive(Clone, Debug)]
+pub struct ControlMessages<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ControlMessages<'a> {
+    /// Parses the control messages in `buf`.
+    pub fn new(buf: &'a [u8]) -> ControlMessages<'a> {
+        ControlMessages {
+            buf,
+            offset: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for ControlMessages<'a> {
+    type Item = ControlMessage<'a>;
+
+    fn next(&mut self) -> Option<ControlMessage<'a>> {
+        let rest = &self.buf[self.offset.min(self.buf.len())..];
+        if rest.len() < mem::size_of::<libc::cmsghdr>() {
+            return None;
+        }
+
+        let hdr: libc::cmsghdr = unsafe {
+            ptr::read_unaligned(rest.as_ptr() as *const libc::cmsghdr)
+        };
+        let len = hdr.cmsg_len as usize;
+        if len < cmsg_len(0) || len > rest.len() {
+            return None;
+        }
+
+        self.offset += cmsg_space(len - cmsg_len(0));
+        Some(ControlMessage {
+            level: hdr.cmsg_level,
+            ty: hdr.cmsg_type,
+            data: &rest[cmsg_len(0)..len],
+        })
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Support for `async_io::Async`, as used by smol and async-std, enabled
+//! with the `async-io` feature. Every socket type can be wrapped with
+//! `Async::new()`; `Async<UnixStream>` then implements `AsyncRead` and
+//! `AsyncWrite`, and the traits here add async send, receive and accept to
+//! the other types.
+
+use std::future::{self, Future};
+use std::io::{ErrorKind, Result};
+use std::os::unix::io::AsRawFd;
+use std::task::{ready, Context, Poll};
+
+use async_io::{Async, IoSafe};
+
+use super::{recv_packet, send_to, write, MsgFlags, SocketAddr};
+use super::{SeqpacketListener, UnixDatagram, UnixListener, UnixSeqpacket};
+use super::UnixStream;
+
+// None of the socket types close or replace their descriptor while
+// borrowed.
+unsafe impl IoSafe for UnixDatagram {}
+unsafe impl IoSafe for UnixStream {}
+unsafe impl IoSafe for UnixSeqpacket {}
+unsafe impl IoSafe for SeqpacketListener {}
+unsafe impl IoSafe for UnixListener {}
+
+// Runs `f` until it stops reporting `WouldBlock`, waiting for the socket
+// to become readable in between.
+fn poll_read_with<T, R, F>(io: &Async<T>, cx: &mut Context, mut f: F)
+                           -> Poll<Result<R>>
+    where F: FnMut(&T) -> Result<R>
+{
+    loop {
+        match f(io.get_ref()) {
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => (),
+            res => return Poll::Ready(res),
+        }
+        ready!(io.poll_readable(cx))?;
+    }
+}
+
+// Like `poll_read_with()`, but waits for the socket to become writable.
+fn poll_write_with<T, R, F>(io: &Async<T>, cx: &mut Context, mut f: F)
+                            -> Poll<Result<R>>
+    where F: FnMut(&T) -> Result<R>
+{
+    loop {
+        match f(io.get_ref()) {
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => (),
+            res => return Poll::Ready(res),
+        }
+        ready!(io.poll_writable(cx))?;
+    }
+}
+
+/// Async operations on an `Async<UnixDatagram>`.
+pub trait AsyncDatagramExt {
+    /// Sends `buf` to the connected peer.
+    fn send<'a>(&'a self, buf: &'a [u8]) -> impl Future<Output = Result<usize>>;
+
+    /// Receives a datagram from the connected peer. A datagram that doesn't
+    /// fit in `buf` is reported with a `MessageTruncated` error.
+    fn recv<'a>(&'a self, buf: &'a mut [u8])
+                -> impl Future<Output = Result<usize>>;
+
+    /// Sends `buf` to `dst`.
+    fn send_to<'a>(&'a self, buf: &'a [u8], dst: &'a SocketAddr)
+                   -> impl Future<Output = Result<usize>>;
+
+    /// Receives a datagram, along with the address of its sender.
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8])
+                     -> impl Future<Output = Result<(usize, SocketAddr)>>;
+}
+
+impl AsyncDatagramExt for Async<UnixDatagram> {
+    fn send<'a>(&'a self, buf: &'a [u8])
+                -> impl Future<Output = Result<usize>> {
+        future::poll_fn(move |cx| {
+            poll_write_with(self, cx, |sock| write(sock.as_raw_fd(), buf))
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8])
+                -> impl Future<Output = Result<usize>> {
+        future::poll_fn(move |cx| {
+            poll_read_with(self, cx, |sock| {
+                recv_packet(sock.as_raw_fd(), buf).map(|(n, _)| n)
+            })
+        })
+    }
+
+    fn send_to<'a>(&'a self, buf: &'a [u8], dst: &'a SocketAddr)
+                   -> impl Future<Output = Result<usize>> {
+        future::poll_fn(move |cx| {
+            poll_write_with(self, cx, |sock| {
+                send_to(sock.as_raw_fd(), buf, dst, MsgFlags::empty())
+            })
+        })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8])
+                     -> impl Future<Output = Result<(usize, SocketAddr)>> {
+        future::poll_fn(move |cx| {
+            poll_read_with(self, cx,
+                           |sock| recv_packet(sock.as_raw_fd(), buf))
+        })
+    }
+}
+
+/// Async operations on an `Async<UnixSeqpacket>`.
+pub trait AsyncSeqpacketExt {
+    /// Sends `buf` as a single packet.
+    fn send<'a>(&'a self, buf: &'a [u8]) -> impl Future<Output = Result<()>>;
+
+    /// Receives a packet. A packet that doesn't fit in `buf` is reported
+    /// with a `MessageTruncated` error.
+    fn recv<'a>(&'a self, buf: &'a mut [u8])
+                -> impl Future<Output = Result<usize>>;
+}
+
+impl AsyncSeqpacketExt for Async<UnixSeqpacket> {
+    fn send<'a>(&'a self, buf: &'a [u8]) -> impl Future<Output = Result<()>> {
+        future::poll_fn(move |cx| {
+            poll_write_with(self, cx, |sock| sock.send(buf))
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8])
+                -> impl Future<Output = Result<usize>> {
+        future::poll_fn(move |cx| {
+            poll_read_with(self, cx, |sock| sock.recv(buf))
+        })
+    }
+}
+
+/// Async accept on an `Async<UnixListener>` or `Async<SeqpacketListener>`.
+pub trait AsyncListenerExt {
+    /// The type of the accepted connections.
+    type Conn;
+
+    /// Waits for a new connection, returning it along with the peer's
+    /// address.
+    fn accept(&self)
+              -> impl Future<Output = Result<(Async<Self::Conn>, SocketAddr)>>;
+}
+
+impl AsyncListenerExt for Async<UnixListener> {
+    type Conn = UnixStream;
+
+    fn accept(&self)
+              -> impl Future<Output = Result<(Async<UnixStream>, SocketAddr)>> {
+        future::poll_fn(move |cx| {
+            let (conn, addr) =
+                ready!(poll_read_with(self, cx, |sock| sock.accept()))?;
+            Poll::Ready(Async::new(conn).map(|conn| (conn, addr)))
+        })
+    }
+}
+
+impl AsyncListenerExt for Async<SeqpacketListener> {
+    type Conn = UnixSeqpacket;
+
+    fn accept(&self)
+        -> impl Future<Output = Result<(Async<UnixSeqpacket>, SocketAddr)>> {
+        future::poll_fn(move |cx| {
+            let (conn, addr) =
+                ready!(poll_read_with(self, cx, |sock| sock.accept()))?;
+            Poll::Ready(Async::new(conn).map(|conn| (conn, addr)))
+        })
+    }
+}
@@ -0,0 +1,520 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Asynchronous sockets for the tokio runtime, enabled with the `tokio`
+//! feature. Each type wraps one of the crate's blocking sockets in
+//! non-blocking mode, registered with the runtime through `AsyncFd`.
+
+use std::future::{self, Future};
+use std::io::{Error, ErrorKind, IoSlice, Result};
+use std::net::Shutdown;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
+
+use super::{peek_len, read, recv_packet, send_to, send_vectored, write};
+use super::MsgFlags;
+use super::{SockType, SocketAddr, ToUnixAddr, UnixDatagram, UnixListener};
+use super::UnixStream;
+
+// Waits for `interest` on `fd` and runs `f`, waiting again whenever `f`
+// reports `WouldBlock`.
+fn poll_io<T, R, F>(fd: &AsyncFd<T>, cx: &mut Context, interest: Interest,
+                    mut f: F) -> Poll<Result<R>>
+    where T: AsRawFd,
+          F: FnMut(&T) -> Result<R>
+{
+    loop {
+        let mut guard = if interest.is_readable() {
+            ready!(fd.poll_read_ready(cx))?
+        } else {
+            ready!(fd.poll_write_ready(cx))?
+        };
+        match guard.try_io(|inner| f(inner.get_ref())) {
+            Ok(result) => return Poll::Ready(result),
+            Err(_) => continue,
+        }
+    }
+}
+
+/// An asynchronous `UnixStream`, implementing tokio's `AsyncRead` and
+/// `AsyncWrite`.
+#[derive(Debug)]
+pub struct AsyncUnixStream {
+    inner: AsyncFd<UnixStream>,
+}
+
+impl AsyncUnixStream {
+    /// Wraps `stream`, putting it in non-blocking mode. This must be called
+    /// from within a tokio runtime.
+    pub fn new(stream: UnixStream) -> Result<AsyncUnixStream> {
+        stream.set_nonblocking(true)?;
+        Ok(AsyncUnixStream {
+            inner: AsyncFd::new(stream)?,
+        })
+    }
+
+    /// Connects to the socket at `path`. Connecting to a local socket
+    /// completes immediately unless the listener's backlog is full, when
+    /// this blocks until there's room.
+    pub fn connect<A: ToUnixAddr>(addr: A) -> Result<AsyncUnixStream> {
+        AsyncUnixStream::new(UnixStream::connect(addr)?)
+    }
+
+    pub fn pair() -> Result<(AsyncUnixStream, AsyncUnixStream)> {
+        let (a, b) = UnixStream::pair_nonblocking()?;
+        Ok((AsyncUnixStream::new(a)?, AsyncUnixStream::new(b)?))
+    }
+
+    pub fn get_ref(&self) -> &UnixStream {
+        self.inner.get_ref()
+    }
+
+    /// Returns the blocking stream, deregistered from the runtime. It is
+    /// left in non-blocking mode.
+    pub fn into_inner(self) -> UnixStream {
+        self.inner.into_inner()
+    }
+
+    /// Waits until the stream is readable. The readiness may be spurious,
+    /// so follow it with `try_read()` and wait again on `WouldBlock`.
+    pub fn readable(&self) -> impl Future<Output = Result<()>> + '_ {
+        future::poll_fn(move |cx| self.poll_read_ready(cx))
+    }
+
+    /// Waits until the stream is writable, as `readable()` does.
+    pub fn writable(&self) -> impl Future<Output = Result<()>> + '_ {
+        future::poll_fn(move |cx| self.poll_write_ready(cx))
+    }
+
+    pub fn poll_read_ready(&self, cx: &mut Context) -> Poll<Result<()>> {
+        self.inner.poll_read_ready(cx).map_ok(|_| ())
+    }
+
+    pub fn poll_write_ready(&self, cx: &mut Context) -> Poll<Result<()>> {
+        self.inner.poll_write_ready(cx).map_ok(|_| ())
+    }
+
+    /// Reads without waiting, returning `WouldBlock` if no data is ready.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.try_io(Interest::READABLE,
+                          |stream| read(stream.as_raw_fd(), buf))
+    }
+
+    /// Writes without waiting, returning `WouldBlock` if the socket's
+    /// buffer is full.
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
+        self.inner.try_io(Interest::WRITABLE,
+                          |stream| write(stream.as_raw_fd(), buf))
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+
+    fn poll_read_priv(&self, cx: &mut Context, buf: &mut ReadBuf)
+                      -> Poll<Result<()>> {
+        let n = ready!(poll_io(&self.inner, cx, Interest::READABLE, |stream| {
+            read(stream.as_raw_fd(), buf.initialize_unfilled())
+        }))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_priv(&self, cx: &mut Context, buf: &[u8])
+                       -> Poll<Result<usize>> {
+        poll_io(&self.inner, cx, Interest::WRITABLE,
+                |stream| write(stream.as_raw_fd(), buf))
+    }
+
+    fn poll_write_vectored_priv(&self, cx: &mut Context, bufs: &[IoSlice])
+                                -> Poll<Result<usize>> {
+        poll_io(&self.inner, cx, Interest::WRITABLE,
+                |stream| send_vectored(stream.as_raw_fd(), bufs))
+    }
+}
+
+impl AsyncRead for AsyncUnixStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf)
+                 -> Poll<Result<()>> {
+        self.poll_read_priv(cx, buf)
+    }
+}
+
+impl AsyncWrite for AsyncUnixStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<Result<usize>> {
+        self.poll_write_priv(cx, buf)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context,
+                           bufs: &[IoSlice]) -> Poll<Result<usize>> {
+        self.poll_write_vectored_priv(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context)
+                  -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context)
+                     -> Poll<Result<()>> {
+        Poll::Ready(self.get_ref().shutdown(Shutdown::Write))
+    }
+}
+
+impl AsRawFd for AsyncUnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncUnixStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
+
+/// An asynchronous `UnixListener`.
+#[derive(Debug)]
+pub struct AsyncUnixListener {
+    inner: AsyncFd<UnixListener>,
+}
+
+impl AsyncUnixListener {
+    /// Wraps `listener`, putting it in non-blocking mode. This must be
+    /// called from within a tokio runtime.
+    pub fn new(listener: UnixListener) -> Result<AsyncUnixListener> {
+        listener.set_nonblocking(true)?;
+        Ok(AsyncUnixListener {
+            inner: AsyncFd::new(listener)?,
+        })
+    }
+
+    pub fn bind<A: ToUnixAddr>(addr: A) -> Result<AsyncUnixListener> {
+        AsyncUnixListener::new(UnixListener::bind_nonblocking(addr)?)
+    }
+
+    pub fn get_ref(&self) -> &UnixListener {
+        self.inner.get_ref()
+    }
+
+    /// Returns the blocking listener, deregistered from the runtime. It is
+    /// left in non-blocking mode.
+    pub fn into_inner(self) -> UnixListener {
+        self.inner.into_inner()
+    }
+
+    /// Waits for a new connection, returning it along with the peer's
+    /// address.
+    pub fn accept(&self)
+        -> impl Future<Output = Result<(AsyncUnixStream, SocketAddr)>> + '_ {
+        future::poll_fn(move |cx| self.poll_accept(cx))
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context)
+                       -> Poll<Result<(AsyncUnixStream, SocketAddr)>> {
+        let (stream, addr) = ready!(poll_io(&self.inner, cx,
+                                            Interest::READABLE,
+                                            |listener| listener.accept()))?;
+        Poll::Ready(AsyncUnixStream::new(stream).map(|stream| (stream, addr)))
+    }
+
+    /// Returns a `Stream` of incoming connections. The stream never ends.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+}
+
+impl AsRawFd for AsyncUnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncUnixListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
+
+/// A `Stream` of the connections accepted by an `AsyncUnixListener`.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a AsyncUnixListener,
+}
+
+impl<'a> Stream for Incoming<'a> {
+    type Item = Result<AsyncUnixStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Result<AsyncUnixStream>>> {
+        self.listener.poll_accept(cx).map(|res| Some(res.map(|(s, _)| s)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+/// An asynchronous `UnixDatagram`. Received datagrams that don't fit in the
+/// caller's buffer are reported with a `MessageTruncated` error, as with the
+/// blocking socket.
+///
+/// The socket is also a `Stream` of received datagrams and their senders'
+/// addresses, and a `Sink` of datagrams to send to a given address. The
+/// inherent `send()` shadows `SinkExt::send()`, so call the latter as
+/// `SinkExt::send(&mut socket, item)`.
+#[derive(Debug)]
+pub struct AsyncUnixDatagram {
+    inner: AsyncFd<UnixDatagram>,
+    // A datagram passed to `start_send()` that has yet to be sent.
+    outgoing: Option<(Bytes, SocketAddr)>,
+}
+
+impl AsyncUnixDatagram {
+    /// Wraps `socket`, putting it in non-blocking mode. This must be called
+    /// from within a tokio runtime.
+    pub fn new(socket: UnixDatagram) -> Result<AsyncUnixDatagram> {
+        socket.set_nonblocking(true)?;
+        Ok(AsyncUnixDatagram {
+            inner: AsyncFd::new(socket)?,
+            outgoing: None,
+        })
+    }
+
+    pub fn bind<A: ToUnixAddr>(addr: A) -> Result<AsyncUnixDatagram> {
+        AsyncUnixDatagram::new(UnixDatagram::bind_nonblocking(addr,
+                                                              SockType::Dgram)?)
+    }
+
+    pub fn unbound() -> Result<AsyncUnixDatagram> {
+        AsyncUnixDatagram::new(UnixDatagram::unbound()?)
+    }
+
+    pub fn pair() -> Result<(AsyncUnixDatagram, AsyncUnixDatagram)> {
+        let (a, b) = UnixDatagram::pair_nonblocking()?;
+        Ok((AsyncUnixDatagram::new(a)?, AsyncUnixDatagram::new(b)?))
+    }
+
+    /// Sets the peer used by `send()` and `recv()`.
+    pub fn connect_to<A: ToUnixAddr>(&mut self, addr: A) -> Result<()> {
+        self.inner.get_mut().connect_to(addr)
+    }
+
+    pub fn get_ref(&self) -> &UnixDatagram {
+        self.inner.get_ref()
+    }
+
+    /// Returns the blocking socket, deregistered from the runtime. It is
+    /// left in non-blocking mode.
+    pub fn into_inner(self) -> UnixDatagram {
+        self.inner.into_inner()
+    }
+
+    /// Sends `buf` to the connected peer.
+    pub fn send<'a>(&'a self, buf: &'a [u8])
+                    -> impl Future<Output = Result<usize>> + 'a {
+        future::poll_fn(move |cx| self.poll_send(cx, buf))
+    }
+
+    /// Receives a datagram from the connected peer.
+    pub fn recv<'a>(&'a self, buf: &'a mut [u8])
+                    -> impl Future<Output = Result<usize>> + 'a {
+        future::poll_fn(move |cx| self.poll_recv(cx, buf))
+    }
+
+    /// Sends `buf` to `dst`, which may be an address returned by
+    /// `recv_from()`.
+    pub fn send_to<'a>(&'a self, buf: &'a [u8], dst: &'a SocketAddr)
+                       -> impl Future<Output = Result<usize>> + 'a {
+        future::poll_fn(move |cx| self.poll_send_to(cx, buf, dst))
+    }
+
+    /// Receives a datagram, along with the address of its sender.
+    pub fn recv_from<'a>(&'a self, buf: &'a mut [u8])
+        -> impl Future<Output = Result<(usize, SocketAddr)>> + 'a {
+        future::poll_fn(move |cx| self.poll_recv_from(cx, buf))
+    }
+
+    pub fn poll_send(&self, cx: &mut Context, buf: &[u8])
+                     -> Poll<Result<usize>> {
+        poll_io(&self.inner, cx, Interest::WRITABLE,
+                |socket| write(socket.as_raw_fd(), buf))
+    }
+
+    pub fn poll_recv(&self, cx: &mut Context, buf: &mut [u8])
+                     -> Poll<Result<usize>> {
+        self.poll_recv_from(cx, buf).map_ok(|(n, _)| n)
+    }
+
+    pub fn poll_send_to(&self, cx: &mut Context, buf: &[u8],
+                        dst: &SocketAddr) -> Poll<Result<usize>> {
+        poll_io(&self.inner, cx, Interest::WRITABLE, |socket| {
+            send_to(socket.as_raw_fd(), buf, dst, MsgFlags::empty())
+        })
+    }
+
+    pub fn poll_recv_from(&self, cx: &mut Context, buf: &mut [u8])
+                          -> Poll<Result<(usize, SocketAddr)>> {
+        poll_io(&self.inner, cx, Interest::READABLE,
+                |socket| recv_packet(socket.as_raw_fd(), buf))
+    }
+
+    /// Sends without waiting, returning `WouldBlock` if the socket's
+    /// buffer is full.
+    pub fn try_send(&self, buf: &[u8]) -> Result<usize> {
+        self.inner.try_io(Interest::WRITABLE,
+                          |socket| write(socket.as_raw_fd(), buf))
+    }
+
+    /// Receives without waiting, returning `WouldBlock` if no datagram is
+    /// queued.
+    pub fn try_recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.try_recv_from(buf).map(|(n, _)| n)
+    }
+
+    pub fn try_send_to(&self, buf: &[u8], dst: &SocketAddr) -> Result<usize> {
+        self.inner.try_io(Interest::WRITABLE, |socket| {
+            send_to(socket.as_raw_fd(), buf, dst, MsgFlags::empty())
+        })
+    }
+
+    pub fn try_recv_from(&self, buf: &mut [u8])
+                         -> Result<(usize, SocketAddr)> {
+        self.inner.try_io(Interest::READABLE,
+                          |socket| recv_packet(socket.as_raw_fd(), buf))
+    }
+
+    /// See `AsyncUnixStream::readable()`.
+    pub fn readable(&self) -> impl Future<Output = Result<()>> + '_ {
+        future::poll_fn(move |cx| self.poll_read_ready(cx))
+    }
+
+    /// See `AsyncUnixStream::writable()`.
+    pub fn writable(&self) -> impl Future<Output = Result<()>> + '_ {
+        future::poll_fn(move |cx| self.poll_write_ready(cx))
+    }
+
+    pub fn poll_read_ready(&self, cx: &mut Context) -> Poll<Result<()>> {
+        self.inner.poll_read_ready(cx).map_ok(|_| ())
+    }
+
+    pub fn poll_write_ready(&self, cx: &mut Context) -> Poll<Result<()>> {
+        self.inner.poll_write_ready(cx).map_ok(|_| ())
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl Stream for AsyncUnixDatagram {
+    type Item = Result<(Vec<u8>, SocketAddr)>;
+
+    // Each datagram is received into a buffer sized to fit it.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context)
+                 -> Poll<Option<Result<(Vec<u8>, SocketAddr)>>> {
+        poll_io(&self.inner, cx, Interest::READABLE, |socket| {
+            let mut buf = vec![0; peek_len(socket.as_raw_fd())?];
+            let (n, addr) = recv_packet(socket.as_raw_fd(), &mut buf)?;
+            buf.truncate(n);
+            Ok((buf, addr))
+        }).map(Some)
+    }
+}
+
+impl Sink<(Bytes, SocketAddr)> for AsyncUnixDatagram {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: (Bytes, SocketAddr))
+                  -> Result<()> {
+        self.outgoing = Some(item);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<Result<()>> {
+        let res = match self.outgoing {
+            Some((ref buf, ref dst)) => {
+                ready!(self.poll_send_to(cx, buf, dst)).map(|n| n == buf.len())
+            }
+            None => return Poll::Ready(Ok(())),
+        };
+        self.outgoing = None;
+        match res {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                let err = Error::new(ErrorKind::InvalidInput,
+                                     "couldn't send entire packet at once");
+                Poll::Ready(Err(err))
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context)
+                  -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsRawFd for AsyncUnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncUnixDatagram {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
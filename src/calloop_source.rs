@@ -0,0 +1,200 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Event sources for calloop, enabled with the `calloop` feature.
+//!
+//! `ListenerSource` accepts connections as they arrive and passes each to
+//! the loop callback. `SocketSource` invokes the callback whenever a
+//! connected socket is ready, with the socket as the callback's metadata.
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::AsFd;
+
+use calloop::generic::{Generic, NoIoDrop};
+use calloop::{EventSource, Interest, Mode, Poll, PostAction, Readiness};
+use calloop::{Token, TokenFactory};
+
+use super::{SeqpacketListener, SocketAddr};
+use super::{UnixListener, UnixSeqpacket, UnixStream};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The listener types a `ListenerSource` can accept connections from.
+pub trait Listener: AsFd + private::Sealed {
+    /// The type of the accepted connections.
+    type Conn;
+
+    #[doc(hidden)]
+    fn accept_conn(&self) -> Result<(Self::Conn, SocketAddr)>;
+
+    #[doc(hidden)]
+    fn accepts_nonblocking(&self) -> Result<bool>;
+}
+
+impl private::Sealed for UnixListener {}
+
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn accept_conn(&self) -> Result<(UnixStream, SocketAddr)> {
+        self.accept()
+    }
+
+    fn accepts_nonblocking(&self) -> Result<bool> {
+        self.nonblocking()
+    }
+}
+
+impl private::Sealed for SeqpacketListener {}
+
+impl Listener for SeqpacketListener {
+    type Conn = UnixSeqpacket;
+
+    fn accept_conn(&self) -> Result<(UnixSeqpacket, SocketAddr)> {
+        self.accept()
+    }
+
+    fn accepts_nonblocking(&self) -> Result<bool> {
+        self.nonblocking()
+    }
+}
+
+/// An event source that accepts incoming connections, calling back with
+/// each new connection and its peer's address.
+#[derive(Debug)]
+pub struct ListenerSource<L: Listener> {
+    inner: Generic<L>,
+}
+
+impl<L: Listener> ListenerSource<L> {
+    /// Wraps `listener`. It should be non-blocking, so that all pending
+    /// connections can be accepted on each wakeup; a blocking listener
+    /// accepts one connection per wakeup instead.
+    pub fn new(listener: L) -> ListenerSource<L> {
+        ListenerSource {
+            inner: Generic::new(listener, Interest::READ, Mode::Level),
+        }
+    }
+
+    pub fn get_ref(&self) -> &L {
+        self.inner.get_ref()
+    }
+}
+
+impl<L: Listener> EventSource for ListenerSource<L> {
+    type Event = (L::Conn, SocketAddr);
+    type Metadata = ();
+    type Ret = ();
+    type Error = Error;
+
+    fn process_events<F>(&mut self, readiness: Readiness, token: Token,
+                         mut callback: F) -> Result<PostAction>
+        where F: FnMut((L::Conn, SocketAddr), &mut ())
+    {
+        self.inner.process_events(readiness, token, |_, listener| {
+            let nonblocking = listener.accepts_nonblocking()?;
+            loop {
+                match listener.accept_conn() {
+                    Ok(conn) => callback(conn, &mut ()),
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                }
+                if !nonblocking {
+                    break;
+                }
+            }
+            Ok(PostAction::Continue)
+        })
+    }
+
+    fn register(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+                -> calloop::Result<()> {
+        self.inner.register(poll, factory)
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+                  -> calloop::Result<()> {
+        self.inner.reregister(poll, factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.inner.unregister(poll)
+    }
+}
+
+/// An event source that calls back whenever a socket is ready, passing the
+/// readiness and the socket. The callback returns a `PostAction`, for
+/// instance `PostAction::Remove` once the peer has hung up.
+#[derive(Debug)]
+pub struct SocketSource<S: AsFd> {
+    inner: Generic<S>,
+}
+
+impl<S: AsFd> SocketSource<S> {
+    /// Wraps `sock`, calling back while it is readable.
+    pub fn new(sock: S) -> SocketSource<S> {
+        SocketSource::with_interest(sock, Interest::READ, Mode::Level)
+    }
+
+    pub fn with_interest(sock: S, interest: Interest, mode: Mode)
+                         -> SocketSource<S> {
+        SocketSource {
+            inner: Generic::new(sock, interest, mode),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+}
+
+impl<S: AsFd> EventSource for SocketSource<S> {
+    type Event = Readiness;
+    type Metadata = NoIoDrop<S>;
+    type Ret = Result<PostAction>;
+    type Error = Error;
+
+    fn process_events<F>(&mut self, readiness: Readiness, token: Token,
+                         callback: F) -> Result<PostAction>
+        where F: FnMut(Readiness, &mut NoIoDrop<S>) -> Result<PostAction>
+    {
+        self.inner.process_events(readiness, token, callback)
+    }
+
+    fn register(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+                -> calloop::Result<()> {
+        self.inner.register(poll, factory)
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, factory: &mut TokenFactory)
+                  -> calloop::Result<()> {
+        self.inner.reregister(poll, factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.inner.unregister(poll)
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::error;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use libc;
+
+use super::{last_error, poll_fds, retry};
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+use super::set_cloexec;
+
+/// The error wrapped by the `ErrorKind::Interrupted` error that a
+/// cancellable call returns once its `CancelHandle` is cancelled. It can be
+/// told apart from an ordinary interruption by downcasting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation cancelled")
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// A handle for cancelling blocked `*_cancellable()` calls from another
+/// thread. Clones share the same state.
+///
+/// Cancelling wakes every call waiting on the handle, and any later call
+/// returns at once, until the handle is dropped. It is built on a pipe:
+/// waiting calls also poll its read end, and `cancel()` writes to it.
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    cancelled: AtomicBool,
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl CancelHandle {
+    pub fn new() -> Result<CancelHandle> {
+        let (read, write) = pipe()?;
+        Ok(CancelHandle {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                read,
+                write,
+            }),
+        })
+    }
+
+    /// Cancels all current and future calls made with this handle.
+    pub fn cancel(&self) -> Result<()> {
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let fd = self.inner.write.as_raw_fd();
+        match retry(|| unsafe {
+            libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1)
+        }) {
+            -1 => Err(last_error()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A handle for stopping a listener from another thread, such as to end a
+/// server's accept loop. Get one from the listener's `shutdown_handle()`;
+/// clones share the same state.
+#[derive(Clone)]
+pub struct ListenerShutdown {
+    cancel: CancelHandle,
+    wakers: Arc<Mutex<Vec<Waker>>>,
+}
+
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+impl ListenerShutdown {
+    pub(crate) fn new() -> Result<ListenerShutdown> {
+        Ok(ListenerShutdown {
+            cancel: CancelHandle::new()?,
+            wakers: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// Makes pending and future `accept()` calls on the listener fail with
+    /// `ErrorKind::NotConnected`, and its `incoming()` iterator end.
+    pub fn shutdown(&self) -> Result<()> {
+        self.cancel.cancel()?;
+        for wake in self.wakers().iter() {
+            wake();
+        }
+        Ok(())
+    }
+
+    // Registers `wake` to be called on shutdown, for waits on something
+    // other than the listener, such as a free place under a connection
+    // limit.
+    pub(crate) fn on_shutdown<F>(&self, wake: F)
+        where F: Fn() + Send + Sync + 'static
+    {
+        self.wakers().push(Box::new(wake));
+    }
+
+    fn wakers(&self) -> MutexGuard<'_, Vec<Waker>> {
+        self.wakers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    // Runs `accept`, which must not block, on the listening socket `fd`,
+    // waiting for a connection or for the listener to be shut down. A
+    // listener its owner put in non-blocking mode isn't waited on, so it
+    // still fails with `WouldBlock` when there are no connections.
+    pub(crate) fn accept<R, F>(&self, fd: RawFd, nonblocking: bool,
+                               mut accept: F) -> Result<R>
+        where F: FnMut() -> Result<R>
+    {
+        let closed = || Error::new(ErrorKind::NotConnected, "listener closed");
+        if self.is_shutdown() {
+            return Err(closed());
+        }
+        if nonblocking {
+            return accept();
+        }
+
+        retry_until_cancelled(fd, &self.cancel, accept).map_err(|err| {
+            if err.kind() == ErrorKind::Interrupted && self.is_shutdown() {
+                closed()
+            } else {
+                err
+            }
+        })
+    }
+}
+
+impl fmt::Debug for ListenerShutdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ListenerShutdown")
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(last_error());
+    }
+    unsafe {
+        Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(last_error());
+    }
+    let (read, write) = unsafe {
+        (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+    };
+    set_cloexec(read.as_raw_fd(), true)?;
+    set_cloexec(write.as_raw_fd(), true)?;
+    Ok((read, write))
+}
+
+// Waits for `fd` to become readable, then runs `op`, an attempt at
+// receiving from or accepting on it, waiting again if `op` fails with
+// `WouldBlock`. Returns a `Cancelled` error as soon as `cancel` is
+// cancelled. `op` must not block: another thread or process may have taken
+// what woke us, and a blocked `op` couldn't be cancelled.
+pub(crate) fn retry_until_cancelled<R, F>(fd: RawFd, cancel: &CancelHandle,
+                                          mut op: F) -> Result<R>
+    where F: FnMut() -> Result<R>
+{
+    loop {
+        let mut fds = [
+            libc::pollfd { fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd {
+                fd: cancel.inner.read.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        if !cancel.is_cancelled() {
+            poll_fds(&mut fds, None)?;
+        }
+        if cancel.is_cancelled() {
+            return Err(Error::new(ErrorKind::Interrupted, Cancelled));
+        }
+
+        match op() {
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => (),
+            res => return res,
+        }
+    }
+}
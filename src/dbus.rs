@@ -0,0 +1,184 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::{percent_decode, unique_name, SocketAddr, ToUnixAddr};
+use super::{UnixListener, UnixStream};
+
+/// A `unix:` address in the format D-Bus uses, such as
+/// `unix:path=/run/dbus/system_bus_socket`. Values are percent-encoded as
+/// the D-Bus specification describes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbusAddr {
+    /// `unix:path=`, a socket in the filesystem.
+    Path(PathBuf),
+    /// `unix:abstract=`, a name in Linux's abstract namespace.
+    Abstract(Vec<u8>),
+    /// `unix:tmpdir=`, a directory for a listener to create a socket in.
+    Tmpdir(PathBuf),
+    /// `unix:dir=`, which is treated the same as `tmpdir`.
+    Dir(PathBuf),
+}
+
+impl DbusAddr {
+    /// Parses a single address. A `guid` key is accepted and ignored.
+    pub fn parse(addr: &str) -> Result<DbusAddr> {
+        let params = addr.strip_prefix("unix:").ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput,
+                       "D-Bus address must use the unix transport")
+        })?;
+
+        let mut found = None;
+        for param in params.split(',').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput,
+                           "D-Bus address parameter has no value")
+            })?;
+            let value = percent_decode(value)?;
+            let path = || PathBuf::from(OsStr::from_bytes(&value));
+            let addr = match key {
+                "path" => DbusAddr::Path(path()),
+                "tmpdir" => DbusAddr::Tmpdir(path()),
+                "dir" => DbusAddr::Dir(path()),
+                "abstract" => DbusAddr::Abstract(value),
+                "guid" => continue,
+                _ => {
+                    return Err(Error::new(ErrorKind::InvalidInput,
+                                          "unknown key in D-Bus address"));
+                }
+            };
+            if found.replace(addr).is_some() {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      "D-Bus address has more than one \
+                                       location"));
+            }
+        }
+
+        found.ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput,
+                       "D-Bus address has no location")
+        })
+    }
+
+    /// Parses a semicolon-separated list of addresses, as found in
+    /// `DBUS_SESSION_BUS_ADDRESS`, skipping transports other than `unix:`.
+    pub fn parse_list(addrs: &str) -> Result<Vec<DbusAddr>> {
+        addrs.split(';')
+            .filter(|addr| addr.starts_with("unix:"))
+            .map(DbusAddr::parse)
+            .collect()
+    }
+
+    /// Connects to the address. `tmpdir` and `dir` addresses can only be
+    /// listened on.
+    pub fn connect(&self) -> Result<UnixStream> {
+        UnixStream::connect(self)
+    }
+
+    /// Binds a listener at the address, returning it with the address
+    /// clients should connect to. For `tmpdir` and `dir`, a socket with a
+    /// unique name is created in the directory and the returned address
+    /// gives its path. As with any pathname socket, the file is unlinked
+    /// when the listener is dropped.
+    pub fn listen(&self) -> Result<(UnixListener, String)> {
+        let dir = match *self {
+            DbusAddr::Tmpdir(ref dir) | DbusAddr::Dir(ref dir) => dir,
+            _ => return Ok((UnixListener::bind(self)?, self.to_string())),
+        };
+
+        for _ in 0..100 {
+            let path = dir.join(unique_name("dbus"));
+            match UnixListener::bind(&path) {
+                Ok(listener) => {
+                    return Ok((listener, DbusAddr::Path(path).to_string()));
+                }
+                Err(ref err) if err.kind() == ErrorKind::AddrInUse => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Err(Error::new(ErrorKind::AddrInUse,
+                       "couldn't find an unused socket name"))
+    }
+}
+
+impl fmt::Display for DbusAddr {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let (key, value) = match *self {
+            DbusAddr::Path(ref path) => ("path", bytes(path)),
+            DbusAddr::Tmpdir(ref path) => ("tmpdir", bytes(path)),
+            DbusAddr::Dir(ref path) => ("dir", bytes(path)),
+            DbusAddr::Abstract(ref name) => ("abstract", &name[..]),
+        };
+
+        write!(fmt, "unix:{}=", key)?;
+        for &b in value {
+            // the bytes D-Bus allows to appear unescaped
+            if b.is_ascii_alphanumeric() || b"-_/.\\*".contains(&b) {
+                write!(fmt, "{}", b as char)?;
+            } else {
+                write!(fmt, "%{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn bytes(path: &Path) -> &[u8] {
+    path.as_os_str().as_bytes()
+}
+
+impl FromStr for DbusAddr {
+    type Err = Error;
+
+    fn from_str(addr: &str) -> Result<DbusAddr> {
+        DbusAddr::parse(addr)
+    }
+}
+
+impl ToUnixAddr for DbusAddr {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        match *self {
+            DbusAddr::Path(ref path) => SocketAddr::from_pathname(path),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            DbusAddr::Abstract(ref name) => {
+                SocketAddr::from_abstract_name(name)
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            DbusAddr::Abstract(_) => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               "abstract addresses are not supported on \
+                                this platform"))
+            }
+            DbusAddr::Tmpdir(_) | DbusAddr::Dir(_) => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               "tmpdir and dir addresses can only be \
+                                listened on"))
+            }
+        }
+    }
+}
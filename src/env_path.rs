@@ -0,0 +1,127 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::env;
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use super::{SocketAddr, ToUnixAddr};
+
+/// Expands environment variable references in a configured socket path.
+///
+/// `${VAR}` is replaced by the value of `VAR`, and fails with
+/// `ErrorKind::NotFound` if it isn't set. `${VAR:-fallback}` uses
+/// `fallback`, which may itself contain references, when `VAR` is unset or
+/// empty. `$$` gives a literal `$`, and any other `$` is left as is. For
+/// example, `${XDG_RUNTIME_DIR:-/tmp}/app.sock`.
+pub fn expand_path(template: &str) -> Result<PathBuf> {
+    let mut out = Vec::new();
+    expand_into(template, &mut out)?;
+    Ok(PathBuf::from(OsStr::from_bytes(&out)))
+}
+
+fn expand_into(template: &str, out: &mut Vec<u8>) -> Result<()> {
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        out.extend_from_slice(&rest.as_bytes()[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push(b'$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = closing_brace(after).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput,
+                           "unterminated ${ in socket path")
+            })?;
+            expand_var(&after[..end], out)?;
+            rest = &after[end + 1..];
+        } else {
+            out.push(b'$');
+        }
+    }
+    out.extend_from_slice(rest.as_bytes());
+    Ok(())
+}
+
+// Finds the `}` closing a reference, skipping over nested references in a
+// fallback. Only `${` opens one: a lone `{` is an ordinary character, and so
+// is the `{` after an escaped `$$`.
+fn closing_brace(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'$' if bytes.get(i + 1) == Some(&b'$') => i += 1,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn expand_var(reference: &str, out: &mut Vec<u8>) -> Result<()> {
+    let (name, fallback) = match reference.split_once(":-") {
+        Some((name, fallback)) => (name, Some(fallback)),
+        None => (reference, None),
+    };
+    if name.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "empty variable name in socket path"));
+    }
+
+    match env::var_os(name) {
+        Some(ref value) if !value.is_empty() || fallback.is_none() => {
+            out.extend_from_slice(value.as_bytes());
+            Ok(())
+        }
+        _ => match fallback {
+            Some(fallback) => expand_into(fallback, out),
+            None => {
+                Err(Error::new(ErrorKind::NotFound,
+                               format!("environment variable {} is not set",
+                                       name)))
+            }
+        },
+    }
+}
+
+/// A socket path containing environment variable references, for passing
+/// to functions that take a `ToUnixAddr`. It is expanded by `expand_path()`
+/// when used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EnvPath<'a>(pub &'a str);
+
+impl ToUnixAddr for EnvPath<'_> {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(expand_path(self.0)?)
+    }
+}
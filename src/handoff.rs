@@ -0,0 +1,268 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Handing a live listener over to another process, such as the new
+//! version of a daemon being upgraded, without closing it. Connections
+//! that arrive during the upgrade wait in the listener's backlog until the
+//! new process accepts them.
+//!
+//! A listener can also be shared with worker processes that accept from it
+//! alongside the sender. Workers that are forked after the listener is
+//! bound inherit it already, and those spawned with a new program can
+//! inherit it if close-on-exec is cleared with `set_cloexec(false)`.
+//! Others can be sent it with `share_listener()`. A listener this process
+//! has taken a shutdown handle for, or accepted from with
+//! `accept_cancellable()`, has a non-blocking socket: forked workers see it
+//! that way, while one sent with `share_listener()` keeps the mode chosen
+//! with `set_nonblocking()`.
+
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd};
+use std::path::{Path, PathBuf};
+
+use libc;
+
+use super::{listen, msg, BoundPath, FromSocketFd, ListenerMode};
+use super::{SeqpacketListener, UnixListener, UnixStream};
+
+const MAGIC: &[u8; 4] = b"AFUH";
+const VERSION: u8 = 1;
+// magic, version, flags, two reserved bytes, backlog, path length
+const HEADER_LEN: usize = 16;
+const HAS_PATH: u8 = 1;
+const SHARED: u8 = 2;
+// The listener's owner chose blocking mode, but its socket is non-blocking
+// so that accepts can be polled; see ListenerMode.
+const BLOCKING: u8 = 4;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The listener types that can be handed over with `send_listener()`.
+pub trait HandoffListener: FromSocketFd + AsFd + private::Sealed {
+    #[doc(hidden)]
+    fn take_path(&mut self) -> Option<PathBuf>;
+
+    #[doc(hidden)]
+    fn restore_path(&mut self, path: PathBuf);
+
+    #[doc(hidden)]
+    fn track_path(&mut self, path: &Path);
+
+    #[doc(hidden)]
+    fn emulated_blocking(&self) -> bool;
+
+    #[doc(hidden)]
+    fn set_emulated_blocking(&mut self);
+}
+
+impl private::Sealed for UnixListener {}
+
+impl HandoffListener for UnixListener {
+    fn take_path(&mut self) -> Option<PathBuf> {
+        self.bound.path.take()
+    }
+
+    fn restore_path(&mut self, path: PathBuf) {
+        self.bound.path = Some(path);
+    }
+
+    fn track_path(&mut self, path: &Path) {
+        self.bound = BoundPath::at(path);
+    }
+
+    fn emulated_blocking(&self) -> bool {
+        self.mode.emulated_blocking()
+    }
+
+    fn set_emulated_blocking(&mut self) {
+        self.mode = ListenerMode::polled(false);
+    }
+}
+
+impl private::Sealed for SeqpacketListener {}
+
+impl HandoffListener for SeqpacketListener {
+    fn take_path(&mut self) -> Option<PathBuf> {
+        self.bound.path.take()
+    }
+
+    fn restore_path(&mut self, path: PathBuf) {
+        self.bound.path = Some(path);
+    }
+
+    fn track_path(&mut self, path: &Path) {
+        self.bound = BoundPath::at(path);
+    }
+
+    fn emulated_blocking(&self) -> bool {
+        self.mode.emulated_blocking()
+    }
+
+    fn set_emulated_blocking(&mut self) {
+        self.mode = ListenerMode::polled(false);
+    }
+}
+
+/// What the receiving process is told about a listener handed over to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandoffInfo {
+    /// The socket file the sender would have unlinked when the listener
+    /// was dropped. The receiver takes over unlinking it. Always `None` for
+    /// a shared listener.
+    pub path: Option<PathBuf>,
+    /// The backlog the listener was created with, or `None` if it was
+    /// shared with `share_listener()` rather than handed over.
+    pub backlog: Option<i32>,
+}
+
+/// Sends `listener` over `control` to the process that will take it over,
+/// which receives it with `recv_listener()`. `backlog` is passed along, as
+/// it can't be read back from the socket.
+///
+/// The listener is not closed, only this process's handle to it, and the
+/// socket file is left in place for the receiver to unlink. If sending
+/// fails the listener is dropped as usual.
+pub fn send_listener<L: HandoffListener>(control: &UnixStream,
+                                         mut listener: L, backlog: i32)
+                                         -> Result<()> {
+    let path = listener.take_path();
+    let mut flags = if path.is_some() { HAS_PATH } else { 0 };
+    if listener.emulated_blocking() {
+        flags |= BLOCKING;
+    }
+    let msg = message(flags, backlog, path.as_ref());
+    match send_message(control, &listener, &msg) {
+        Err(err) => {
+            if let Some(path) = path {
+                listener.restore_path(path);
+            }
+            Err(err)
+        }
+        ok => ok,
+    }
+}
+
+/// Sends `listener` over `control` to a worker process that will accept
+/// from it alongside this one, and receives it with `recv_listener()`.
+/// This process keeps the listener, and goes on unlinking its socket file
+/// when it is dropped.
+pub fn share_listener<L: HandoffListener>(control: &UnixStream, listener: &L)
+                                          -> Result<()> {
+    let mut flags = SHARED;
+    if listener.emulated_blocking() {
+        flags |= BLOCKING;
+    }
+    send_message(control, listener, &message(flags, 0, None))
+}
+
+fn message(flags: u8, backlog: i32, path: Option<&PathBuf>) -> Vec<u8> {
+    let path = path.map_or(&[][..], |path| path.as_os_str().as_bytes());
+    let mut msg = Vec::with_capacity(HEADER_LEN + path.len());
+    msg.extend_from_slice(MAGIC);
+    msg.push(VERSION);
+    msg.push(flags);
+    msg.extend_from_slice(&[0, 0]);
+    msg.extend_from_slice(&backlog.to_le_bytes());
+    msg.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    msg.extend_from_slice(path);
+    msg
+}
+
+fn send_message<L: AsFd>(control: &UnixStream, listener: &L, msg: &[u8])
+                         -> Result<()> {
+    let sent = msg::send_fds(control.as_raw_fd(), &[listener.as_fd()], msg)?;
+    // the descriptor went with the first byte, so from here on the
+    // receiver has the listener
+    let mut control = control;
+    control.write_all(&msg[sent..])
+}
+
+/// Receives a listener sent with `send_listener()` or `share_listener()`,
+/// checking that it is of type `L`. The listener is ready to accept
+/// connections, including any that queued up during the handoff. A handed
+/// over listener's socket file is unlinked when it is dropped, as if this
+/// process had bound it.
+pub fn recv_listener<L: HandoffListener>(control: &UnixStream)
+                                         -> Result<(L, HandoffInfo)> {
+    let invalid = || {
+        Error::new(ErrorKind::InvalidData, "not a listener handoff message")
+    };
+
+    let mut header = [0; HEADER_LEN];
+    let mut fds = Vec::new();
+    let n = msg::recv_fds(control.as_raw_fd(), &mut header, &mut fds, true)?;
+    if n == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof,
+                              "control socket closed before the handoff"));
+    }
+    // any extra descriptors are closed as `fds` is dropped
+    if fds.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              "no listener was received"));
+    }
+    let fd = fds.swap_remove(0);
+
+    let mut control = control;
+    control.read_exact(&mut header[n..])?;
+    if &header[..4] != MAGIC {
+        return Err(invalid());
+    }
+    if header[4] != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              "unsupported listener handoff version"));
+    }
+    let backlog = i32::from_le_bytes([header[8], header[9], header[10],
+                                      header[11]]);
+    let len = u32::from_le_bytes([header[12], header[13], header[14],
+                                  header[15]]) as usize;
+    if len > libc::PATH_MAX as usize {
+        return Err(invalid());
+    }
+    let mut path = vec![0; len];
+    control.read_exact(&mut path)?;
+    let path = if header[5] & HAS_PATH != 0 {
+        Some(PathBuf::from(OsStr::from_bytes(&path)))
+    } else {
+        None
+    };
+    let backlog = if header[5] & SHARED != 0 {
+        None
+    } else {
+        Some(backlog)
+    };
+
+    let mut listener = L::from_socket_fd(fd)?;
+    if header[5] & BLOCKING != 0 {
+        listener.set_emulated_blocking();
+    }
+    if let Some(backlog) = backlog {
+        listen(listener.as_fd().as_raw_fd(), backlog)?;
+    }
+    if let Some(ref path) = path {
+        listener.track_path(path);
+    }
+    Ok((listener, HandoffInfo { path, backlog }))
+}
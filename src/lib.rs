@@ -22,32 +22,123 @@
 
 // Based on code from https://github.com/Geal/rust-syslog.
 
-#![feature(core)]
-
 extern crate libc;
 extern crate errno;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+extern crate io_uring;
+#[cfg(feature = "mio")]
+extern crate mio;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate bytes;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "tokio")]
+extern crate futures_sink;
+#[cfg(feature = "async-io")]
+extern crate async_io;
+#[cfg(feature = "calloop")]
+extern crate calloop;
+#[cfg(feature = "polling")]
+extern crate polling;
 
-use std::intrinsics;
+use std::cmp;
 use std::mem;
-use std::ffi::CString;
-use std::os::unix::io::RawFd;
-use std::io::{Error, ErrorKind};
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd};
+use std::io::{self, Error, ErrorKind, IoSlice, IoSliceMut, Read, Write};
 use std::io::Result;
+use std::net::Shutdown;
 use errno::{Errno, errno};
 
+pub use ancillary::{ControlMessage, ControlMessages, ScmRights};
+pub use ancillary::{SocketAncillary, UCred};
+pub use cancel::{CancelHandle, Cancelled, ListenerShutdown};
+pub use dbus::DbusAddr;
+pub use env_path::{expand_path, EnvPath};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use ancillary::ScmCredentials;
+pub use msg::{ControlTruncated, MessageTruncated, MsgFlags, RecvMsg};
+pub use pollset::{Events, Interest, PollEvent, PollSet};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use msg::{MsgBuffer, OutgoingMsg};
+
+pub mod activation;
+#[cfg(feature = "async-io")]
+pub mod async_io_ext;
+#[cfg(feature = "tokio")]
+pub mod async_tokio;
+#[cfg(feature = "calloop")]
+pub mod calloop_source;
+pub mod handoff;
+#[cfg(feature = "polling")]
+pub mod polling_ext;
+pub mod pool;
+#[cfg(all(feature = "reactor",
+          any(target_os = "linux", target_os = "android",
+              target_os = "macos", target_os = "ios",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+pub mod reactor;
+pub mod server;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+
+mod ancillary;
+mod cancel;
+mod dbus;
+mod env_path;
+#[cfg(feature = "mio")]
+mod mio_source;
+mod msg;
+mod pollset;
+mod splice;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SockType {
     Stream,
     Dgram,
     Seqpacket,
 }
 
+impl SockType {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            SockType::Stream => libc::SOCK_STREAM,
+            SockType::Dgram => libc::SOCK_DGRAM,
+            SockType::Seqpacket => libc::SOCK_SEQPACKET,
+        }
+    }
+}
+
+trait IsMinusOne {
+    fn is_minus_one(&self) -> bool;
+}
+
+impl IsMinusOne for libc::c_int {
+    fn is_minus_one(&self) -> bool { *self == -1 }
+}
+
+impl IsMinusOne for libc::ssize_t {
+    fn is_minus_one(&self) -> bool { *self == -1 }
+}
+
 #[inline]
-fn retry<F>(mut f: F) -> libc::c_int where F: FnMut() -> libc::c_int {
+fn retry<T, F>(mut f: F) -> T where T: IsMinusOne, F: FnMut() -> T {
     loop {
+        let n = f();
         let Errno(err) = errno();
-        match f() {
-            -1 if err == libc::EINTR => {}
-            n => return n,
+        if !n.is_minus_one() || err != libc::EINTR {
+            return n;
         }
     }
 }
@@ -56,184 +147,4202 @@ fn last_error() -> Error {
     Error::last_os_error()
 }
 
-fn addr_to_sockaddr_un(addr: &CString) -> Result<(libc::sockaddr_storage, usize)> {
-    // the sun_path length is limited to SUN_LEN (with null)
-    assert!(mem::size_of::<libc::sockaddr_storage>() >=
-            mem::size_of::<libc::sockaddr_un>());
-    let mut storage: libc::sockaddr_storage = unsafe { intrinsics::init() };
-    let s: &mut libc::sockaddr_un = unsafe { mem::transmute(&mut storage) };
+// Where the platform supports it, SOCK_CLOEXEC and SOCK_NONBLOCK are passed
+// when the descriptor is created, so it is never visible to a concurrent
+// fork/exec without them. Elsewhere they are set with fcntl() afterwards.
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn socket_flags(ty: libc::c_int, nonblocking: bool) -> libc::c_int {
+    let ty = ty | libc::SOCK_CLOEXEC;
+    if nonblocking { ty | libc::SOCK_NONBLOCK } else { ty }
+}
 
-    let len = addr.as_bytes().len();
-    if len > s.sun_path.len() - 1 {
-        return Err(Error::new(ErrorKind::InvalidInput,
-                              "path must be smaller than SUN_LEN"));
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn socket_flags(ty: libc::c_int, _nonblocking: bool) -> libc::c_int {
+    ty
+}
+
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn set_socket_flags(_fd: &OwnedFd, _nonblocking: bool) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn set_socket_flags(fd: &OwnedFd, nonblocking: bool) -> Result<()> {
+    let ret = unsafe {
+        libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC)
+    };
+    if ret == -1 {
+        return Err(last_error());
     }
-    s.sun_family = libc::AF_UNIX as libc::sa_family_t;
-    for (slot, value) in s.sun_path.iter_mut().zip(addr.as_bytes().iter()) {
-        *slot = *value as i8;
+    if nonblocking {
+        set_nonblocking(fd.as_raw_fd(), true)?;
     }
+    set_nosigpipe(fd)
+}
+
+// Writing to a socket whose peer has gone raises SIGPIPE, which kills the
+// process unless it is handled. Sends pass MSG_NOSIGNAL where it exists, so
+// the write fails with EPIPE instead; macOS has SO_NOSIGPIPE on the socket.
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+const MSG_NOSIGNAL: libc::c_int = libc::MSG_NOSIGNAL;
 
-    // count the null terminator
-    let len = mem::size_of::<libc::sa_family_t>() + len + 1;
-    return Ok((storage, len));
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+const MSG_NOSIGNAL: libc::c_int = 0;
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_nosigpipe(fd: &OwnedFd) -> Result<()> {
+    setsockopt(fd.as_raw_fd(), libc::SOL_SOCKET, libc::SO_NOSIGPIPE,
+               1 as libc::c_int)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd",
+              target_os = "macos", target_os = "ios")))]
+fn set_nosigpipe(_fd: &OwnedFd) -> Result<()> {
+    Ok(())
+}
+
+fn unix_socket(ty: libc::c_int, nonblocking: bool) -> Result<OwnedFd> {
+    let fd = match unsafe {
+        libc::socket(libc::AF_UNIX, socket_flags(ty, nonblocking), 0)
+    } {
+        -1 => return Err(last_error()),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) }
+    };
+    set_socket_flags(&fd, nonblocking)?;
+    Ok(fd)
 }
 
-fn unix_socket(ty: libc::c_int) -> Result<RawFd> {
-    match unsafe { libc::socket(libc::AF_UNIX, ty, 0) } {
+fn close(fd: RawFd) -> Result<()> {
+    match unsafe { libc::close(fd) } {
         -1 => Err(last_error()),
-        fd => Ok(fd)
+        _  => Ok(())
     }
 }
 
-fn connect(addr: &CString, ty: libc::c_int) -> Result<RawFd> {
-    let (addr, len) = try!(addr_to_sockaddr_un(addr));
-    let fd = try!(unix_socket(ty));
-    let addrp = &addr as *const libc::sockaddr_storage;
-    match retry(|| unsafe {
-        libc::connect(fd, addrp as *const libc::sockaddr,
-                      len as libc::socklen_t)
-    }) {
+fn set_nonblocking(fd: RawFd, nonblocking: bool) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(last_error());
+    }
+
+    let flags = if nonblocking {
+        flags | libc::O_NONBLOCK
+    } else {
+        flags & !libc::O_NONBLOCK
+    };
+
+    match unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } {
         -1 => Err(last_error()),
-        _  => Ok(fd)
+        _  => Ok(())
+    }
+}
+
+fn set_cloexec(fd: RawFd, cloexec: bool) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 {
+        return Err(last_error());
+    }
+
+    let flags = if cloexec {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+
+    match unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } {
+        -1 => Err(last_error()),
+        _  => Ok(())
+    }
+}
+
+fn cloexec(fd: RawFd) -> Result<bool> {
+    match unsafe { libc::fcntl(fd, libc::F_GETFD) } {
+        -1 => Err(last_error()),
+        flags => Ok(flags & libc::FD_CLOEXEC != 0)
     }
 }
 
-fn bind(addr: &CString, ty: libc::c_int) -> Result<RawFd> {
-    let (addr, len) = try!(addr_to_sockaddr_un(addr));
-    let fd = try!(unix_socket(ty));
-    let addrp = &addr as *const libc::sockaddr_storage;
+fn setsockopt<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: T)
+                 -> Result<()> {
     match unsafe {
-        libc::bind(fd, addrp as *const libc::sockaddr, len as libc::socklen_t)
+        libc::setsockopt(fd, level, name,
+                         &value as *const T as *const libc::c_void,
+                         mem::size_of::<T>() as libc::socklen_t)
     } {
         -1 => Err(last_error()),
-        _  => Ok(fd)
+        _  => Ok(())
     }
 }
 
-////////////////////////////////////////////////////////////////////////////////
-// Unix Datagram
-////////////////////////////////////////////////////////////////////////////////
+fn getsockopt<T: Copy>(fd: RawFd, level: libc::c_int, name: libc::c_int)
+                       -> Result<T> {
+    let mut value: T = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<T>() as libc::socklen_t;
+    match unsafe {
+        libc::getsockopt(fd, level, name,
+                         &mut value as *mut T as *mut libc::c_void,
+                         &mut len)
+    } {
+        -1 => Err(last_error()),
+        _  => Ok(value)
+    }
+}
 
-#[derive(Debug)]
-pub struct UnixDatagram {
-    fd: RawFd,
-    connected: bool,
+fn set_timeout(fd: RawFd, dur: Option<Duration>, kind: libc::c_int)
+               -> Result<()> {
+    let timeout = match dur {
+        Some(dur) => {
+            if dur.as_secs() == 0 && dur.subsec_nanos() == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      "cannot set a 0 duration timeout"));
+            }
+
+            let secs = if dur.as_secs() > libc::time_t::MAX as u64 {
+                libc::time_t::MAX
+            } else {
+                dur.as_secs() as libc::time_t
+            };
+            let mut timeout = libc::timeval {
+                tv_sec: secs,
+                tv_usec: dur.subsec_micros() as libc::suseconds_t,
+            };
+            // round up rather than letting a tiny timeout become "forever"
+            if timeout.tv_sec == 0 && timeout.tv_usec == 0 {
+                timeout.tv_usec = 1;
+            }
+            timeout
+        }
+        None => libc::timeval { tv_sec: 0, tv_usec: 0 },
+    };
+    setsockopt(fd, libc::SOL_SOCKET, kind, timeout)
 }
 
-impl UnixDatagram {
-    pub fn connect(addr: &str, ty: SockType) -> Result<UnixDatagram> {
-        let c_ty = match ty {
-            SockType::Stream => libc::SOCK_STREAM,
-            SockType::Dgram => libc::SOCK_DGRAM,
-            SockType::Seqpacket => 5, // FIXME
+fn timeout(fd: RawFd, kind: libc::c_int) -> Result<Option<Duration>> {
+    let timeout: libc::timeval = getsockopt(fd, libc::SOL_SOCKET, kind)?;
+    if timeout.tv_sec == 0 && timeout.tv_usec == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(Duration::new(timeout.tv_sec as u64,
+                              timeout.tv_usec as u32 * 1000)))
+    }
+}
+
+fn socketpair(ty: libc::c_int, nonblocking: bool)
+              -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    let (a, b) = match unsafe {
+        libc::socketpair(libc::AF_UNIX, socket_flags(ty, nonblocking), 0,
+                         fds.as_mut_ptr())
+    } {
+        -1 => return Err(last_error()),
+        _  => unsafe {
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        }
+    };
+    set_socket_flags(&a, nonblocking)?;
+    set_socket_flags(&b, nonblocking)?;
+    Ok((a, b))
+}
+
+fn duplicate(fd: BorrowedFd) -> Result<OwnedFd> {
+    match unsafe {
+        libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0)
+    } {
+        -1 => Err(last_error()),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+fn connect_fd(fd: RawFd, addr: &SocketAddr) -> Result<()> {
+    if addr.is_unnamed() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "can't connect to an unnamed address"));
+    }
+
+    match retry(|| unsafe {
+        libc::connect(fd, addr.as_ptr(), addr.len)
+    }) {
+        -1 => Err(last_error()),
+        _  => Ok(())
+    }
+}
+
+fn connect(addr: &SocketAddr, ty: libc::c_int) -> Result<OwnedFd> {
+    let fd = unix_socket(ty, false)?;
+    connect_fd(fd.as_raw_fd(), addr)?;
+    Ok(fd)
+}
+
+// Waits for `events` on `fd`, returning the events that occurred, or an empty
+// set if `timeout` passed first.
+fn poll(fd: RawFd, events: libc::c_short, timeout: Option<Duration>)
+        -> Result<libc::c_short> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    poll_fds(std::slice::from_mut(&mut pfd), timeout)?;
+    Ok(pfd.revents)
+}
+
+// Returns the milliseconds left until `deadline`, or -1 for none, as
+// poll(2) and epoll_wait(2) take them.
+fn timeout_ms(deadline: Option<Instant>) -> libc::c_int {
+    match deadline {
+        None => -1,
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // round up, so we don't spin on sub-millisecond remainders
+            let ms = remaining.as_nanos().div_ceil(1_000_000);
+            if ms > libc::c_int::MAX as u128 {
+                libc::c_int::MAX
+            } else {
+                ms as libc::c_int
+            }
+        }
+    }
+}
+
+// Waits for any of `fds` to become ready, returning how many did, or 0 if
+// `timeout` passed first. Interrupted calls are restarted with the time
+// that remains.
+fn poll_fds(fds: &mut [libc::pollfd], timeout: Option<Duration>)
+            -> Result<usize> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    loop {
+        let ms = timeout_ms(deadline);
+        let ret = unsafe {
+            libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms)
         };
+        match ret {
+            -1 => {
+                let err = last_error();
+                if err.kind() != ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            n => return Ok(n as usize),
+        }
+    }
+}
 
-        let c_addr = try!(CString::new(addr));
-        let fd = try!(connect(&c_addr, c_ty));
-        Ok(UnixDatagram{
-            fd: fd,
-            connected: true,
-        })
+// Waits for `fd` to become readable, then runs `op`, a non-blocking
+// receive, until it succeeds or fails with something other than
+// `WouldBlock`. Fails with `ErrorKind::TimedOut` once `deadline` passes.
+fn recv_until<R, F>(fd: RawFd, deadline: Instant, mut op: F) -> Result<R>
+    where F: FnMut() -> Result<R>
+{
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if poll(fd, libc::POLLIN, Some(remaining))? == 0 {
+            return Err(Error::new(ErrorKind::TimedOut, "receive timed out"));
+        }
+
+        match op() {
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => (),
+            res => return res,
+        }
+    }
+}
+
+fn take_error(fd: RawFd) -> Result<Option<Error>> {
+    let err: libc::c_int = getsockopt(fd, libc::SOL_SOCKET, libc::SO_ERROR)?;
+    match err {
+        0 => Ok(None),
+        err => Ok(Some(Error::from_raw_os_error(err))),
     }
+}
 
-    pub fn bind(addr: &str, ty: SockType) -> Result<UnixDatagram> {
-        let c_ty = match ty {
-            SockType::Stream => libc::SOCK_STREAM,
-            SockType::Dgram => libc::SOCK_DGRAM,
-            SockType::Seqpacket => 5, // FIXME
+fn bytes_available(fd: RawFd) -> Result<usize> {
+    let mut len: libc::c_int = 0;
+    match unsafe { libc::ioctl(fd, libc::FIONREAD, &mut len) } {
+        -1 => Err(last_error()),
+        _  => Ok(len as usize)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn outgoing_queue_len(fd: RawFd) -> Result<usize> {
+    let mut len: libc::c_int = 0;
+    match unsafe { libc::ioctl(fd, libc::TIOCOUTQ, &mut len) } {
+        -1 => Err(last_error()),
+        _  => Ok(len as usize)
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn outgoing_queue_len(fd: RawFd) -> Result<usize> {
+    let len: libc::c_int = getsockopt(fd, libc::SOL_SOCKET, libc::SO_NWRITE)?;
+    Ok(len as usize)
+}
+
+fn set_buffer_size(fd: RawFd, name: libc::c_int, size: usize) -> Result<()> {
+    let size = cmp::min(size, libc::c_int::MAX as usize) as libc::c_int;
+    setsockopt(fd, libc::SOL_SOCKET, name, size)
+}
+
+// Linux doubles the requested size to leave room for bookkeeping, and
+// reports the doubled value; halve it so the size set is the size read.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn buffer_size(fd: RawFd, name: libc::c_int) -> Result<usize> {
+    let size: libc::c_int = getsockopt(fd, libc::SOL_SOCKET, name)?;
+    Ok(size as usize / 2)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn buffer_size(fd: RawFd, name: libc::c_int) -> Result<usize> {
+    let size: libc::c_int = getsockopt(fd, libc::SOL_SOCKET, name)?;
+    Ok(size as usize)
+}
+
+// Reads a variable-length option, growing the buffer when the kernel says
+// it's too small (ERANGE) and reports the length it needs.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn getsockopt_bytes(fd: RawFd, level: libc::c_int, name: libc::c_int,
+                    mut capacity: usize) -> Result<Vec<u8>> {
+    loop {
+        let mut buf = vec![0u8; capacity];
+        let mut len = capacity as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(fd, level, name,
+                             buf.as_mut_ptr() as *mut libc::c_void, &mut len)
         };
+        if ret == 0 {
+            buf.truncate(len as usize);
+            return Ok(buf);
+        }
 
-        let c_addr = try!(CString::new(addr));
-        bind(&c_addr, c_ty).map(|fd| {
-            UnixDatagram {
-                fd: fd,
-                connected: false,
-            }
-        })
+        let err = last_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+        capacity = cmp::max(len as usize, capacity * 2);
     }
+}
 
-    fn fd(&self) -> RawFd { self.fd }
+#[cfg(target_os = "linux")]
+fn peer_groups(fd: RawFd) -> Result<Vec<libc::gid_t>> {
+    let size = mem::size_of::<libc::gid_t>();
+    let bytes = getsockopt_bytes(fd, libc::SOL_SOCKET, libc::SO_PEERGROUPS,
+                                 16 * size)?;
+    Ok(bytes.chunks_exact(size).map(|gid| unsafe {
+        std::ptr::read_unaligned(gid.as_ptr() as *const libc::gid_t)
+    }).collect())
+}
 
-    pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
-        if !self.connected {
-            return Err(Error::new(ErrorKind::InvalidInput,
-                                  "must call connect() before calling recv()"));
+#[cfg(target_os = "linux")]
+fn peer_pidfd(fd: RawFd) -> Result<OwnedFd> {
+    let pidfd: libc::c_int = getsockopt(fd, libc::SOL_SOCKET,
+                                        libc::SO_PEERPIDFD)?;
+    Ok(unsafe { OwnedFd::from_raw_fd(pidfd) })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_cred(fd: RawFd) -> Result<UCred> {
+    let cred: libc::ucred = getsockopt(fd, libc::SOL_SOCKET,
+                                       libc::SO_PEERCRED)?;
+    Ok(UCred { pid: Some(cred.pid), uid: cred.uid, gid: cred.gid })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn peer_cred(fd: RawFd) -> Result<UCred> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } == -1 {
+        return Err(last_error());
+    }
+    Ok(UCred { pid: peer_pid(fd), uid, gid })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn peer_pid(fd: RawFd) -> Option<libc::pid_t> {
+    getsockopt(fd, libc::SOL_LOCAL, libc::LOCAL_PEERPID).ok()
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn peer_pid(_fd: RawFd) -> Option<libc::pid_t> {
+    None
+}
+
+fn connect_timeout(addr: &SocketAddr, ty: libc::c_int, timeout: Duration)
+                   -> Result<OwnedFd> {
+    if timeout.as_secs() == 0 && timeout.subsec_nanos() == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "cannot set a 0 duration timeout"));
+    }
+
+    let deadline = Instant::now() + timeout;
+    let fd = unix_socket(ty, true)?;
+
+    loop {
+        if unsafe {
+            libc::connect(fd.as_raw_fd(), addr.as_ptr(), addr.len)
+        } == 0 {
+            break;
         }
 
-        let ret = retry(|| unsafe {
-            libc::recv(self.fd(),
-                       buf.as_ptr() as *mut libc::c_void,
-                       buf.len() as libc::size_t,
-                       0) as libc::c_int
-        });
+        let err = last_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => {}
+            Some(libc::EISCONN) => break,
+            Some(libc::EINPROGRESS) | Some(libc::EALREADY) => {
+                let now = Instant::now();
+                let remaining = deadline.saturating_duration_since(now);
+                if poll(fd.as_raw_fd(), libc::POLLOUT, Some(remaining))? == 0 {
+                    return Err(Error::new(ErrorKind::TimedOut,
+                                          "connection timed out"));
+                }
+                if let Some(err) = take_error(fd.as_raw_fd())? {
+                    return Err(err);
+                }
+                break;
+            }
+            // A full listen backlog is reported as EAGAIN rather than
+            // EINPROGRESS, and the attempt isn't queued, so try again.
+            Some(libc::EAGAIN) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::new(ErrorKind::TimedOut,
+                                          "connection timed out"));
+                }
+                thread::sleep(cmp::min(deadline - now,
+                                       Duration::from_millis(1)));
+            }
+            _ => return Err(err),
+        }
+    }
 
-        if ret < 0 { return Err(last_error()) }
+    set_nonblocking(fd.as_raw_fd(), false)?;
+    Ok(fd)
+}
 
-        Ok(ret as usize)
+// Binding to an unnamed address autobinds on Linux, and isn't possible
+// elsewhere.
+fn bind(addr: &SocketAddr, ty: libc::c_int, nonblocking: bool)
+        -> Result<OwnedFd> {
+    if cfg!(not(any(target_os = "linux", target_os = "android")))
+        && addr.is_unnamed() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "can't bind to an unnamed address"));
     }
 
-    pub fn recvfrom(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let mut storage: libc::sockaddr_storage = unsafe { intrinsics::init() };
-        let storagep = &mut storage as *mut libc::sockaddr_storage;
-        let mut addrlen: libc::socklen_t =
-            mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let fd = unix_socket(ty, nonblocking)?;
+    match unsafe {
+        libc::bind(fd.as_raw_fd(), addr.as_ptr(), addr.len)
+    } {
+        -1 => Err(last_error()),
+        _  => Ok(fd)
+    }
+}
 
-        let ret = retry(|| unsafe {
-            libc::recvfrom(self.fd(),
-                           buf.as_ptr() as *mut libc::c_void,
-                           buf.len() as libc::size_t,
-                           0,
-                           storagep as *mut libc::sockaddr,
-                           &mut addrlen) as libc::c_int
-        });
+// Removes the socket file at `addr` after a bind to it failed with
+// EADDRINUSE, if it was left behind by a socket that no longer exists. A
+// test connection tells the two apart: only a dead socket refuses it.
+//
+// The file is only removed if it is still the one that refused, going by
+// its device and inode, so one that another process bound in its place
+// meanwhile is left alone. That narrows, but can't close, the window: a
+// replacement made between the last check and the unlink is still removed.
+fn reclaim_stale(addr: &SocketAddr, ty: libc::c_int) -> Result<()> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
 
-        if ret < 0 { return Err(last_error()) }
+    let in_use = || {
+        Error::new(ErrorKind::AddrInUse, "socket is still in use")
+    };
+    let path = addr.as_pathname().ok_or_else(in_use)?;
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.file_type().is_socket() {
+        return Err(Error::new(ErrorKind::AddrInUse,
+                              "path exists and is not a socket"));
+    }
 
-        Ok(ret as usize)
+    // non-blocking, so a listener with a full backlog doesn't stall us
+    let fd = unix_socket(ty, true)?;
+    match connect_fd(fd.as_raw_fd(), addr) {
+        Ok(()) => Err(in_use()),
+        Err(err) => match err.raw_os_error() {
+            Some(libc::ECONNREFUSED) => {
+                let now = match std::fs::symlink_metadata(path) {
+                    Ok(now) => now,
+                    Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                        return Ok(());
+                    }
+                    Err(err) => return Err(err),
+                };
+                if now.dev() != meta.dev() || now.ino() != meta.ino() {
+                    return Err(in_use());
+                }
+                match std::fs::remove_file(path) {
+                    Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                        Ok(())
+                    }
+                    result => result,
+                }
+            }
+            // something is listening, if not with room or the same type
+            Some(libc::EAGAIN) | Some(libc::EINPROGRESS)
+                | Some(libc::EPROTOTYPE) => Err(in_use()),
+            _ => Err(err),
+        },
     }
+}
 
-    pub fn send(&mut self, buf: &[u8]) -> Result<()> {
-        if !self.connected {
+// Binds a socket at a unique temporary name beside `path`, lets `configure`
+// finish setting it up, then moves it into place, so that clients never see
+// the socket half-configured. As with bind(2), anything already at `path`
+// is left alone and this fails with EADDRINUSE. If any step fails, dropping
+// the socket removes the temporary file.
+fn publish<S, B, F>(path: &Path, bind: B, configure: F) -> Result<S>
+    where B: FnOnce(&SocketAddr) -> Result<S>,
+          F: FnOnce(&S) -> Result<()>
+{
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}", unique_name("tmp")));
+    let tmp = PathBuf::from(tmp);
+
+    let sock = bind(&SocketAddr::from_pathname(&tmp)?)?;
+    configure(&sock)?;
+    rename_noreplace(&tmp, path)?;
+    Ok(sock)
+}
+
+// Moves `from` to `to` unless something is already at `to`. Linux can do
+// that in one step; elsewhere, or on filesystems without renameat2(2), a
+// hard link is made and `from` unlinked, which is not atomic but never
+// replaces anything either.
+fn rename_noreplace(from: &Path, to: &Path) -> Result<()> {
+    let in_use = || Error::from_raw_os_error(libc::EADDRINUSE);
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::ffi::CString;
+
+        let c_path = |path: &Path| {
+            CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+                Error::new(ErrorKind::InvalidInput,
+                           "path contains a nul byte")
+            })
+        };
+        let (c_from, c_to) = (c_path(from)?, c_path(to)?);
+        let ret = unsafe {
+            libc::syscall(libc::SYS_renameat2, libc::AT_FDCWD,
+                          c_from.as_ptr(), libc::AT_FDCWD, c_to.as_ptr(),
+                          libc::RENAME_NOREPLACE as libc::c_uint)
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+        let err = last_error();
+        match err.raw_os_error() {
+            Some(libc::EEXIST) => return Err(in_use()),
+            Some(libc::ENOSYS) | Some(libc::EINVAL) => {}
+            _ => return Err(err),
+        }
+    }
+
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from),
+        Err(ref err) if err.kind() == ErrorKind::AlreadyExists => {
+            Err(in_use())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Sets the permissions of the socket file bound at `addr`.
+fn set_mode(addr: &SocketAddr, mode: u32) -> Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    match addr.as_pathname() {
+        Some(path) => {
+            std::fs::set_permissions(path, Permissions::from_mode(mode))
+        }
+        None => Ok(()),
+    }
+}
+
+// Changes the owner of the file a socket is bound to, preferring the path
+// it is tracked at in `bound`, which follows renames, to its bound name.
+fn set_owner(fd: RawFd, bound: &BoundPath, uid: Option<libc::uid_t>,
+             gid: Option<libc::gid_t>) -> Result<()> {
+    let path = match bound.path {
+        Some(ref path) => path.clone(),
+        None => local_addr(fd)?.as_pathname().map(Path::to_path_buf)
+            .ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput,
+                           "socket is not bound to a pathname")
+            })?,
+    };
+
+    std::os::unix::fs::chown(&path, uid, gid).map_err(|err| {
+        match err.raw_os_error() {
+            Some(libc::EPERM) => {
+                Error::new(ErrorKind::PermissionDenied,
+                           format!("not permitted to change the owner of \
+                                    {}", path.display()))
+            }
+            _ => err,
+        }
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+extern "C" {
+    fn bindat(fd: libc::c_int, s: libc::c_int, addr: *const libc::sockaddr,
+              addrlen: libc::socklen_t) -> libc::c_int;
+}
+
+// Binds a new socket to `name` in the directory open as `dir`, without
+// looking up the directory's path again. Linux has no bindat(2), so the
+// directory is reached through /proc/self/fd instead.
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd"))]
+fn bind_at(dir: RawFd, name: &Path, ty: libc::c_int) -> Result<OwnedFd> {
+    use std::path::Component;
+
+    let mut components = name.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => {
             return Err(Error::new(ErrorKind::InvalidInput,
-                                  "must call connect() before calling send()"));
+                                  "name must be a single path component"));
         }
-        let ret = retry(|| unsafe {
-            libc::send(self.fd(),
-                       buf.as_ptr() as *const libc::c_void,
-                       buf.len() as libc::size_t,
-                       0) as libc::c_int
-        });
+    }
 
-        match ret {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let path = Path::new(&format!("/proc/self/fd/{}", dir)).join(name);
+        bind(&SocketAddr::from_pathname(path)?, ty, false)
+    }
+    #[cfg(target_os = "freebsd")]
+    {
+        let addr = SocketAddr::from_pathname(name)?;
+        let fd = unix_socket(ty, false)?;
+        match unsafe {
+            bindat(dir, fd.as_raw_fd(), addr.as_ptr(), addr.len)
+        } {
             -1 => Err(last_error()),
-            n if n as usize != buf.len() => {
-                Err(Error::new(ErrorKind::InvalidInput,
-                               "couldn't send entire packet at once"))
-            }
-            _ => Ok(())
+            _  => Ok(fd)
         }
     }
+}
 
-    pub fn sendto(&mut self, buf: &[u8], dst: &str) -> Result<()> {
-        let c_dst = try!(CString::new(dst));
-        let (dst, len) = try!(addr_to_sockaddr_un(&c_dst));
-        let dstp = &dst as *const libc::sockaddr_storage;
-        let ret = retry(|| unsafe {
-            libc::sendto(self.fd(),
-                         buf.as_ptr() as *const libc::c_void,
-                         buf.len() as libc::size_t,
-                         0,
-                         dstp as *const libc::sockaddr,
-                         len as libc::socklen_t) as libc::c_int
-        });
+fn is_connected(fd: RawFd) -> bool {
+    peer_addr(fd).is_ok()
+}
 
-        match ret {
-            -1 => Err(last_error()),
-            n if n as usize != buf.len() => {
-                Err(Error::new(ErrorKind::InvalidInput,
-                               "couldn't send entire packet at once"))
-            }
-            _ => Ok(())
+fn listen(fd: RawFd, backlog: libc::c_int) -> Result<()> {
+    match unsafe { libc::listen(fd, backlog) } {
+        -1 => Err(last_error()),
+        _  => Ok(())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn accept_raw(fd: RawFd, addr: *mut libc::sockaddr,
+              len: *mut libc::socklen_t) -> Result<OwnedFd> {
+    match retry(|| unsafe {
+        libc::accept4(fd, addr, len, libc::SOCK_CLOEXEC)
+    }) {
+        -1 => Err(last_error()),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn accept_raw(fd: RawFd, addr: *mut libc::sockaddr,
+              len: *mut libc::socklen_t) -> Result<OwnedFd> {
+    let fd = match retry(|| unsafe { libc::accept(fd, addr, len) }) {
+        -1 => return Err(last_error()),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) }
+    };
+    set_socket_flags(&fd, false)?;
+    Ok(fd)
+}
+
+// A connection that was reset while still on the accept queue is reported as
+// ECONNABORTED (or EPROTO on some systems). That is no fault of the listener,
+// so move on to the next connection rather than returning an error. When the
+// queue is empty, a non-blocking listener returns EAGAIN, which surfaces as
+// ErrorKind::WouldBlock.
+fn accept(fd: RawFd) -> Result<(OwnedFd, SocketAddr)> {
+    loop {
+        let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+        match accept_raw(fd, &mut addr as *mut _ as *mut libc::sockaddr,
+                         &mut len) {
+            Ok(fd) => return Ok((fd, SocketAddr::from_parts(addr, len)?)),
+            Err(ref e) if e.raw_os_error() == Some(libc::ECONNABORTED) ||
+                          e.raw_os_error() == Some(libc::EPROTO) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Accepts on a listener that ListenerMode keeps non-blocking. accept(2)
+// passes O_NONBLOCK on to the connection, unlike accept4(2), so it is
+// cleared again to give the blocking connection the owner expects.
+fn accept_polled(fd: RawFd) -> Result<(OwnedFd, SocketAddr)> {
+    let (conn, addr) = accept(fd)?;
+    #[cfg(not(any(target_os = "linux", target_os = "android",
+                  target_os = "freebsd", target_os = "dragonfly",
+                  target_os = "netbsd", target_os = "openbsd")))]
+    set_nonblocking(conn.as_raw_fd(), false)?;
+    Ok((conn, addr))
+}
+
+// A listener's blocking mode. Until a cancellable or shutdown-aware accept
+// polls the listener, this is just the socket's O_NONBLOCK flag. After
+// poll(2) wakes such an accept, another thread or process may take the
+// connection first, so the accept(2) that follows must not block. The flag
+// belongs to the open file description, which forked workers and duplicated
+// or received descriptors share, so rather than being set around each
+// accept it is set for good the first time, and the mode the owner chose is
+// kept here instead, with a blocking accept waiting in poll(2). Clones made
+// with try_clone() share the mode.
+#[derive(Debug, Clone, Default)]
+struct ListenerMode {
+    // the owner's mode, once the socket is non-blocking for our sake
+    polled: Arc<Mutex<Option<bool>>>,
+}
+
+impl ListenerMode {
+    fn polled(nonblocking: bool) -> ListenerMode {
+        ListenerMode {
+            polled: Arc::new(Mutex::new(Some(nonblocking))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<bool>> {
+        self.polled.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn nonblocking(&self, fd: RawFd) -> Result<bool> {
+        let polled = *self.lock();
+        match polled {
+            Some(nonblocking) => Ok(nonblocking),
+            None => is_nonblocking(fd),
+        }
+    }
+
+    fn set_nonblocking(&self, fd: RawFd, nonblocking: bool) -> Result<()> {
+        let mut polled = self.lock();
+        match *polled {
+            Some(_) => {
+                *polled = Some(nonblocking);
+                Ok(())
+            }
+            None => set_nonblocking(fd, nonblocking),
+        }
+    }
+
+    // Leaves the socket non-blocking for good, so it can be polled, and
+    // returns the owner's mode.
+    fn poll_mode(&self, fd: RawFd) -> Result<bool> {
+        let mut polled = self.lock();
+        if let Some(nonblocking) = *polled {
+            return Ok(nonblocking);
+        }
+        let nonblocking = is_nonblocking(fd)?;
+        if !nonblocking {
+            set_nonblocking(fd, true)?;
+        }
+        *polled = Some(nonblocking);
+        Ok(nonblocking)
+    }
+
+    // Whether the socket is non-blocking although its owner asked for
+    // blocking mode.
+    fn emulated_blocking(&self) -> bool {
+        *self.lock() == Some(false)
+    }
+
+    fn accept(&self, fd: RawFd) -> Result<(OwnedFd, SocketAddr)> {
+        let polled = *self.lock();
+        match polled {
+            None => accept(fd),
+            Some(true) => accept_polled(fd),
+            Some(false) => loop {
+                poll(fd, libc::POLLIN, None)?;
+                match accept_polled(fd) {
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => {}
+                    res => return res,
+                }
+            },
+        }
+    }
+}
+
+fn shutdown(fd: RawFd, how: Shutdown) -> Result<()> {
+    let how = match how {
+        Shutdown::Read => libc::SHUT_RD,
+        Shutdown::Write => libc::SHUT_WR,
+        Shutdown::Both => libc::SHUT_RDWR,
+    };
+    match unsafe { libc::shutdown(fd, how) } {
+        -1 => Err(last_error()),
+        _  => Ok(())
+    }
+}
+
+fn read(fd: RawFd, buf: &mut [u8]) -> Result<usize> {
+    recv(fd, buf, MsgFlags::empty())
+}
+
+fn write(fd: RawFd, buf: &[u8]) -> Result<usize> {
+    send(fd, buf, MsgFlags::empty())
+}
+
+fn recv(fd: RawFd, buf: &mut [u8], flags: MsgFlags) -> Result<usize> {
+    let ret = retry(|| unsafe {
+        libc::recv(fd,
+                   buf.as_mut_ptr() as *mut libc::c_void,
+                   buf.len() as libc::size_t,
+                   flags.bits())
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+fn send(fd: RawFd, buf: &[u8], flags: MsgFlags) -> Result<usize> {
+    let ret = retry(|| unsafe {
+        libc::send(fd,
+                   buf.as_ptr() as *const libc::c_void,
+                   buf.len() as libc::size_t,
+                   flags.bits() | MSG_NOSIGNAL)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+fn recv_from(fd: RawFd, buf: &mut [u8], flags: MsgFlags)
+             -> Result<(usize, SocketAddr)> {
+    let mut count = 0;
+    let addr = SocketAddr::new(|addr, len| {
+        count = retry(|| unsafe {
+            libc::recvfrom(fd,
+                           buf.as_mut_ptr() as *mut libc::c_void,
+                           buf.len() as libc::size_t,
+                           flags.bits(),
+                           addr,
+                           len)
+        });
+        if count < 0 { -1 } else { 0 }
+    })?;
+
+    Ok((count as usize, addr))
+}
+
+fn send_to(fd: RawFd, buf: &[u8], dst: &SocketAddr, flags: MsgFlags)
+           -> Result<usize> {
+    if dst.is_unnamed() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "can't send to an unnamed address"));
+    }
+
+    let ret = retry(|| unsafe {
+        libc::sendto(fd,
+                     buf.as_ptr() as *const libc::c_void,
+                     buf.len() as libc::size_t,
+                     flags.bits() | MSG_NOSIGNAL,
+                     dst.as_ptr(),
+                     dst.len)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+// IOV_MAX on Linux, macOS and the BSDs
+const MAX_IOV: usize = 1024;
+
+fn read_vectored(fd: RawFd, bufs: &mut [IoSliceMut]) -> Result<usize> {
+    let ret = retry(|| unsafe {
+        libc::readv(fd,
+                    bufs.as_mut_ptr() as *mut libc::iovec as *const libc::iovec,
+                    cmp::min(bufs.len(), MAX_IOV) as libc::c_int)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+fn is_nonblocking(fd: RawFd) -> Result<bool> {
+    match unsafe { libc::fcntl(fd, libc::F_GETFL) } {
+        -1 => Err(last_error()),
+        flags => Ok(flags & libc::O_NONBLOCK != 0)
+    }
+}
+
+// Keeps writing until all of `buf` has been sent. A non-blocking socket is
+// waited on until it is writable again; the write timeout, if any, bounds how
+// long we wait without making progress, as it does for a blocking socket.
+fn write_all(fd: RawFd, mut buf: &[u8]) -> Result<()> {
+    let timeout = timeout(fd, libc::SO_SNDTIMEO)?;
+
+    while !buf.is_empty() {
+        match write(fd, buf) {
+            Ok(0) => {
+                return Err(Error::new(ErrorKind::WriteZero,
+                                      "failed to write whole buffer"));
+            }
+            Ok(n) => buf = &buf[n..],
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                if !is_nonblocking(fd)? ||
+                   poll(fd, libc::POLLOUT, timeout)? == 0 {
+                    return Err(Error::new(ErrorKind::TimedOut,
+                                          "timed out writing whole buffer"));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+// Linux reports the real length of a datagram when asked with MSG_TRUNC,
+// even into an empty buffer. Elsewhere MSG_TRUNC is only an output flag, so
+// peek with a growing buffer until the whole datagram fits.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peek_len(fd: RawFd) -> Result<usize> {
+    recv(fd, &mut [], MsgFlags::PEEK | MsgFlags::TRUNC)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peek_len(fd: RawFd) -> Result<usize> {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len() as libc::size_t,
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+
+        let ret = retry(|| unsafe {
+            libc::recvmsg(fd, &mut msg, libc::MSG_PEEK)
+        });
+
+        if ret < 0 { return Err(last_error()) }
+
+        if msg.msg_flags & libc::MSG_TRUNC == 0 {
+            return Ok(ret as usize);
+        }
+        let len = buf.len() * 2;
+        buf.resize(len, 0);
+    }
+}
+
+fn send_packet(fd: RawFd, buf: &[u8]) -> Result<()> {
+    match write(fd, buf)? {
+        n if n != buf.len() => {
+            Err(Error::new(ErrorKind::InvalidInput,
+                           "couldn't send entire packet at once"))
+        }
+        _ => Ok(())
+    }
+}
+
+// Linux returns the full length of a truncated datagram when MSG_TRUNC is
+// passed in; stream sockets ignore it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MSG_TRUNC_LEN: libc::c_int = libc::MSG_TRUNC;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MSG_TRUNC_LEN: libc::c_int = 0;
+
+// Receives one datagram or packet, returning a `MessageTruncated` error if
+// it didn't fit in `buf`.
+fn recv_packet(fd: RawFd, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+    recv_packet_with(fd, buf, MsgFlags::empty())
+}
+
+fn recv_packet_with(fd: RawFd, buf: &mut [u8], flags: MsgFlags)
+                    -> Result<(usize, SocketAddr)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len() as libc::size_t,
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    let mut count = 0;
+    let addr = SocketAddr::new(|addr, len| {
+        msg.msg_name = addr as *mut libc::c_void;
+        msg.msg_namelen = unsafe { *len };
+        count = retry(|| unsafe {
+            libc::recvmsg(fd, &mut msg, MSG_TRUNC_LEN | flags.bits())
+        });
+        unsafe { *len = msg.msg_namelen };
+        if count < 0 { -1 } else { 0 }
+    })?;
+
+    let count = count as usize;
+    if msg.msg_flags & libc::MSG_TRUNC != 0 || count > buf.len() {
+        let len = if MSG_TRUNC_LEN != 0 { Some(count) } else { None };
+        let err = MessageTruncated::new(cmp::min(count, buf.len()), len);
+        return Err(Error::new(ErrorKind::InvalidData, err));
+    }
+
+    Ok((count, addr))
+}
+
+fn send_vectored(fd: RawFd, bufs: &[IoSlice]) -> Result<usize> {
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = cmp::min(bufs.len(), MAX_IOV) as _;
+
+    let ret = retry(|| unsafe { libc::sendmsg(fd, &msg, MSG_NOSIGNAL) });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+fn recv_vectored(fd: RawFd, bufs: &mut [IoSliceMut]) -> Result<usize> {
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = bufs.as_mut_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = cmp::min(bufs.len(), MAX_IOV) as _;
+
+    let ret = retry(|| unsafe { libc::recvmsg(fd, &mut msg, 0) });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Socket Address
+////////////////////////////////////////////////////////////////////////////////
+
+fn sun_path_offset() -> usize {
+    mem::offset_of!(libc::sockaddr_un, sun_path)
+}
+
+enum AddressKind<'a> {
+    Unnamed,
+    Pathname(&'a Path),
+    Abstract(&'a [u8]),
+}
+
+/// The address of a Unix socket.
+#[derive(Clone, Copy)]
+pub struct SocketAddr {
+    addr: libc::sockaddr_un,
+    len: libc::socklen_t,
+}
+
+impl SocketAddr {
+    fn new<F>(f: F) -> Result<SocketAddr>
+        where F: FnOnce(*mut libc::sockaddr, *mut libc::socklen_t) -> libc::c_int
+    {
+        let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+        match f(&mut addr as *mut _ as *mut libc::sockaddr, &mut len) {
+            -1 => Err(last_error()),
+            _  => SocketAddr::from_parts(addr, len),
+        }
+    }
+
+    fn from_parts(addr: libc::sockaddr_un, mut len: libc::socklen_t)
+                  -> Result<SocketAddr> {
+        if len == 0 {
+            // Some platforms return a zero length for unnamed peers
+            len = sun_path_offset() as libc::socklen_t;
+        } else if addr.sun_family != libc::AF_UNIX as libc::sa_family_t {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "address is not an AF_UNIX address"));
+        }
+
+        Ok(SocketAddr {
+            addr,
+            len,
+        })
+    }
+
+    /// Creates an address referring to a path in the filesystem. The path
+    /// need not be UTF-8, but it can't contain null bytes.
+    pub fn from_pathname<P: AsRef<Path>>(path: P) -> Result<SocketAddr> {
+        let bytes = path.as_ref().as_os_str().as_bytes();
+        if bytes.contains(&0) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "path must not contain null bytes"));
+        }
+
+        let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+        // the sun_path length is limited to SUN_LEN (with null)
+        if bytes.len() > addr.sun_path.len() - 1 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "path must be smaller than SUN_LEN"));
+        }
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        for (slot, value) in addr.sun_path.iter_mut().zip(bytes.iter()) {
+            *slot = *value as libc::c_char;
+        }
+
+        // count the null terminator
+        let len = sun_path_offset() + bytes.len() + 1;
+        Ok(SocketAddr {
+            addr,
+            len: len as libc::socklen_t,
+        })
+    }
+
+    /// Creates an address in Linux's abstract namespace, which has no
+    /// presence in the filesystem. `name` is given without the leading null
+    /// byte, and may itself contain null bytes.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn from_abstract_name<N: AsRef<[u8]>>(name: N) -> Result<SocketAddr> {
+        let name = name.as_ref();
+        let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+        // the leading null takes one byte of sun_path
+        if name.len() > addr.sun_path.len() - 1 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "name must be smaller than SUN_LEN"));
+        }
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        for (slot, value) in addr.sun_path[1..].iter_mut().zip(name.iter()) {
+            *slot = *value as libc::c_char;
+        }
+
+        // no null terminator: every byte of the length is part of the name
+        let len = sun_path_offset() + 1 + name.len();
+        Ok(SocketAddr {
+            addr,
+            len: len as libc::socklen_t,
+        })
+    }
+
+    /// Parses a `unix://` URI, as found in configuration files. The part
+    /// after the scheme is a path, as in `unix:///run/app.sock`, or on Linux
+    /// an abstract name following an `@`, as in `unix://@app`. Either may
+    /// contain percent-encoded bytes.
+    pub fn from_uri(uri: &str) -> Result<SocketAddr> {
+        let scheme = "unix://";
+        if uri.len() < scheme.len()
+            || !uri[..scheme.len()].eq_ignore_ascii_case(scheme) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "URI must start with unix://"));
+        }
+
+        let rest = &uri[scheme.len()..];
+        if let Some(name) = rest.strip_prefix('@') {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            return SocketAddr::from_abstract_name(percent_decode(name)?);
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            {
+                let _ = name;
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      "abstract addresses are not supported \
+                                       on this platform"));
+            }
+        }
+
+        if rest.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "URI has no path"));
+        }
+        let path = percent_decode(rest)?;
+        SocketAddr::from_pathname(OsStr::from_bytes(&path))
+    }
+
+    // An address consisting of only the family, which bind(2) takes as a
+    // request to autobind.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn autobind() -> SocketAddr {
+        let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        SocketAddr {
+            addr,
+            len: sun_path_offset() as libc::socklen_t,
+        }
+    }
+
+    fn as_ptr(&self) -> *const libc::sockaddr {
+        &self.addr as *const _ as *const libc::sockaddr
+    }
+
+    /// Returns true if the address is unnamed, as for sockets created by
+    /// `pair()` or sockets that were never bound.
+    pub fn is_unnamed(&self) -> bool {
+        matches!(self.address(), AddressKind::Unnamed)
+    }
+
+    /// Returns the filesystem path of the address, if it has one.
+    pub fn as_pathname(&self) -> Option<&Path> {
+        match self.address() {
+            AddressKind::Pathname(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the address in the abstract namespace, without
+    /// the leading null byte, if it has one.
+    pub fn as_abstract_name(&self) -> Option<&[u8]> {
+        match self.address() {
+            AddressKind::Abstract(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> AddressKind<'_> {
+        let len = self.len as usize - sun_path_offset();
+        let path = unsafe {
+            &*(&self.addr.sun_path as *const [libc::c_char] as *const [u8])
+        };
+
+        if len == 0 {
+            AddressKind::Unnamed
+        } else if path[0] == 0 {
+            AddressKind::Abstract(&path[1..len])
+        } else {
+            let end = path[..len].iter().position(|&b| b == 0).unwrap_or(len);
+            AddressKind::Pathname(Path::new(OsStr::from_bytes(&path[..end])))
+        }
+    }
+}
+
+// Writes bytes as printable ASCII, escaping everything else (and quotes and
+// backslashes) so that distinct names always render differently.
+struct Escaped<'a>(&'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for &b in self.0 {
+            write!(fmt, "{}", std::ascii::escape_default(b))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SocketAddr {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.address() {
+            AddressKind::Unnamed => write!(fmt, "(unnamed)"),
+            AddressKind::Abstract(name) => {
+                write!(fmt, "\"{}\" (abstract)", Escaped(name))
+            }
+            AddressKind::Pathname(path) => write!(fmt, "{:?} (pathname)", path),
+        }
+    }
+}
+
+/// Pathnames are shown as paths, abstract names with a leading `@` and
+/// non-printable bytes escaped, and unnamed addresses as `(unnamed)`.
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.address() {
+            AddressKind::Unnamed => write!(fmt, "(unnamed)"),
+            AddressKind::Abstract(name) => write!(fmt, "@{}", Escaped(name)),
+            AddressKind::Pathname(path) => write!(fmt, "{}", path.display()),
+        }
+    }
+}
+
+// Decodes `%XX` escapes in `s`.
+fn percent_decode(s: &str) -> Result<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let byte = s.get(i + 1..i + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput,
+                           "invalid percent-encoding")
+            })?;
+        out.push(byte);
+        i += 3;
+    }
+    Ok(out)
+}
+
+/// Types that can be converted to a Unix socket address: paths, given as
+/// `&str`, `Path`, `OsStr` and their owned forms, `SocketAddr` itself, and
+/// on Linux `AbstractName`.
+pub trait ToUnixAddr {
+    fn to_unix_addr(&self) -> Result<SocketAddr>;
+}
+
+impl ToUnixAddr for SocketAddr {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        Ok(*self)
+    }
+}
+
+impl ToUnixAddr for str {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl ToUnixAddr for String {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl ToUnixAddr for Path {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl ToUnixAddr for PathBuf {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl ToUnixAddr for OsStr {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl ToUnixAddr for OsString {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_pathname(self)
+    }
+}
+
+impl<T: ToUnixAddr + ?Sized> ToUnixAddr for &T {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        (**self).to_unix_addr()
+    }
+}
+
+/// A name in the abstract namespace, for passing to functions that take a
+/// `ToUnixAddr`. See `SocketAddr::from_abstract_name()`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AbstractName<'a>(pub &'a [u8]);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ToUnixAddr for AbstractName<'_> {
+    fn to_unix_addr(&self) -> Result<SocketAddr> {
+        SocketAddr::from_abstract_name(self.0)
+    }
+}
+
+// Runs `f` with an address for `path`. A path too long for sun_path is
+// reached through /proc/self/fd and a descriptor for its parent directory,
+// which stays open until `f` returns.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn with_long_path<T, F>(path: &Path, f: F) -> Result<T>
+    where F: FnOnce(&SocketAddr) -> Result<T>
+{
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if let Ok(addr) = SocketAddr::from_pathname(path) {
+        return f(&addr);
+    }
+
+    let name = path.file_name().ok_or_else(|| {
+        Error::new(ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+        .open(parent)?;
+
+    let mut short = PathBuf::from(format!("/proc/self/fd/{}",
+                                          dir.as_raw_fd()));
+    short.push(name);
+    f(&SocketAddr::from_pathname(&short)?)
+}
+
+// A name unlikely to be in use already, from the pid, the time and a
+// counter.
+fn unique_name(prefix: &str) -> String {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+    format!("{}-{:x}-{:x}-{:x}", prefix, std::process::id(), nanos,
+            COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+// Binds a socket inside a new directory under the system's temporary
+// directory, which only the current user can reach, returning it along
+// with the directory.
+fn bind_in_tempdir<S, B>(bind: B) -> Result<(S, PathBuf)>
+    where B: FnOnce(&SocketAddr) -> Result<S>
+{
+    use std::fs::DirBuilder;
+    use std::os::unix::fs::DirBuilderExt;
+
+    let base = std::env::temp_dir();
+    let mut dir = None;
+    for _ in 0..100 {
+        let path = base.join(unique_name("af_unix"));
+        match DirBuilder::new().mode(0o700).create(&path) {
+            Ok(()) => {
+                dir = Some(path);
+                break;
+            }
+            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let dir = dir.ok_or_else(|| {
+        Error::new(ErrorKind::AlreadyExists,
+                   "couldn't find an unused directory name")
+    })?;
+
+    match SocketAddr::from_pathname(dir.join("sock")).and_then(|addr| {
+        bind(&addr)
+    }) {
+        Ok(sock) => Ok((sock, dir)),
+        Err(err) => {
+            let _ = std::fs::remove_dir(&dir);
+            Err(err)
+        }
+    }
+}
+
+// The filesystem path a socket was bound to, which is unlinked when the
+// socket is dropped. The file's device and inode are recorded at bind time,
+// and it is only removed if it is still the same file, so a socket that has
+// since been replaced at the path is left alone. A directory created to
+// hold the socket is removed after it. Only the process that bound the
+// socket unlinks it, so forked workers sharing a listener leave it alone
+// when they exit.
+#[derive(Debug, Default)]
+struct BoundPath {
+    path: Option<PathBuf>,
+    dev: u64,
+    ino: u64,
+    dir: Option<PathBuf>,
+    pid: u32,
+    cleanup: bool,
+    // The address clients reach the socket at, if it was moved after
+    // binding. The kernel only knows the name it was bound with.
+    published: Option<SocketAddr>,
+}
+
+impl BoundPath {
+    fn new(addr: &SocketAddr) -> BoundPath {
+        match addr.as_pathname() {
+            Some(path) => BoundPath::at(path),
+            None => BoundPath::default(),
+        }
+    }
+
+    fn at(path: &Path) -> BoundPath {
+        use std::os::unix::fs::MetadataExt;
+
+        let path = std::path::absolute(path)
+            .unwrap_or_else(|_| path.to_path_buf());
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) => BoundPath {
+                path: Some(path),
+                dev: meta.dev(),
+                ino: meta.ino(),
+                dir: None,
+                pid: std::process::id(),
+                cleanup: true,
+                published: None,
+            },
+            Err(_) => BoundPath::default(),
+        }
+    }
+
+    // Records that the file is now reached by `path`, such as after it has
+    // been renamed.
+    fn retarget(&mut self, path: &Path) {
+        if self.path.is_some() {
+            self.path = Some(std::path::absolute(path)
+                             .unwrap_or_else(|_| path.to_path_buf()));
+        }
+    }
+
+    // Records that the socket was moved to `path` by publish().
+    fn publish(&mut self, path: &Path) -> Result<()> {
+        self.retarget(path);
+        self.published = Some(SocketAddr::from_pathname(path)?);
+        Ok(())
+    }
+
+    // Returns what `local_addr()` should report for the socket `fd`.
+    fn local_addr(&self, fd: RawFd) -> Result<SocketAddr> {
+        match self.published {
+            Some(addr) => Ok(addr),
+            None => local_addr(fd),
+        }
+    }
+
+    // Returns the state for a new handle to the same socket, which reports
+    // the same address but never unlinks.
+    fn for_clone(&self) -> BoundPath {
+        let mut bound = BoundPath::default();
+        bound.published = self.published;
+        bound
+    }
+
+    fn unlink(&mut self) {
+        use std::os::unix::fs::MetadataExt;
+
+        let path = match self.path.take() {
+            Some(path) if self.cleanup && self.pid == std::process::id() => {
+                path
+            }
+            _ => return,
+        };
+        if let Ok(meta) = std::fs::symlink_metadata(&path) {
+            if meta.dev() == self.dev && meta.ino() == self.ino {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        if let Some(dir) = self.dir.take() {
+            let _ = std::fs::remove_dir(dir);
+        }
+    }
+}
+
+impl Drop for BoundPath {
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
+
+fn local_addr(fd: RawFd) -> Result<SocketAddr> {
+    SocketAddr::new(|addr, len| unsafe { libc::getsockname(fd, addr, len) })
+}
+
+fn peer_addr(fd: RawFd) -> Result<SocketAddr> {
+    SocketAddr::new(|addr, len| unsafe { libc::getpeername(fd, addr, len) })
+}
+
+/// How `connect_with_retry()` retries a connection that fails, such as
+/// when a client starts before the server has created its socket.
+///
+/// By default up to 10 attempts are made, retrying on
+/// `ErrorKind::NotFound` (`ENOENT`: no socket file yet) and
+/// `ErrorKind::ConnectionRefused` (`ECONNREFUSED`: a file but no listener
+/// yet). The delay before the first retry is 10ms, and doubles after each
+/// one up to a second.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    retryable: Vec<ErrorKind>,
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 10,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            retryable: vec![ErrorKind::NotFound, ErrorKind::ConnectionRefused],
+        }
+    }
+
+    /// Sets the number of attempts made in all, including the first. With
+    /// 1 there are no retries.
+    pub fn attempts(mut self, attempts: u32) -> RetryPolicy {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles after each
+    /// retry until it reaches `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration)
+                   -> RetryPolicy {
+        self.initial_delay = initial;
+        self.max_delay = max;
+        self
+    }
+
+    /// Replaces the kinds of error that are retried. Any other error is
+    /// returned straight away.
+    pub fn retry_on(mut self, kinds: &[ErrorKind]) -> RetryPolicy {
+        self.retryable = kinds.to_vec();
+        self
+    }
+
+    fn run<T, F: FnMut() -> Result<T>>(&self, mut f: F) -> Result<T> {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(ref err) if attempt < self.attempts
+                    && self.retryable.contains(&err.kind()) => {}
+                res => return res,
+            }
+            thread::sleep(delay);
+            delay = cmp::min(delay.saturating_mul(2), self.max_delay);
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+/// What `probe()` found at an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeStatus {
+    /// A socket is accepting connections there, though perhaps not of the
+    /// type probed for, or too busy to take one right now.
+    Listening,
+    /// Nothing is listening, as with a socket file left behind by a server
+    /// that has exited, a file that isn't a socket, or an abstract name
+    /// that isn't bound.
+    RefusingConnections,
+    /// There is no socket file, or no directory leading to it.
+    PathMissing,
+    /// The socket file, or a directory on the way to it, can't be accessed.
+    PermissionDenied,
+}
+
+/// Checks whether a server is listening at `addr`, by connecting to it and
+/// closing the connection straight away. The server sees a connection that
+/// closes without sending anything.
+///
+/// The probe connects as a stream. A live seqpacket or datagram socket
+/// refuses that by type, which counts as `Listening`, so
+/// `RefusingConnections` means that no socket of any type is bound there.
+///
+/// The connection is made without blocking; where it can't complete at
+/// once, `timeout` bounds the wait, after which the probe fails with
+/// `ErrorKind::TimedOut`. Errors other than those that `ProbeStatus`
+/// describes are returned as they are.
+pub fn probe<A: ToUnixAddr>(addr: A, timeout: Duration)
+                            -> Result<ProbeStatus> {
+    let addr = addr.to_unix_addr()?;
+    let fd = unix_socket(libc::SOCK_STREAM, true)?;
+    let err = match connect_fd(fd.as_raw_fd(), &addr) {
+        Ok(()) => return Ok(ProbeStatus::Listening),
+        Err(err) => err,
+    };
+    let err = if err.raw_os_error() == Some(libc::EINPROGRESS) {
+        if poll(fd.as_raw_fd(), libc::POLLOUT, Some(timeout))? == 0 {
+            return Err(Error::new(ErrorKind::TimedOut, "probe timed out"));
+        }
+        match take_error(fd.as_raw_fd())? {
+            None => return Ok(ProbeStatus::Listening),
+            Some(err) => err,
+        }
+    } else {
+        err
+    };
+
+    match err.raw_os_error() {
+        // EAGAIN is a full backlog, and EPROTOTYPE a socket of another type
+        Some(libc::EAGAIN) | Some(libc::EPROTOTYPE) => {
+            Ok(ProbeStatus::Listening)
+        }
+        Some(libc::ECONNREFUSED) => Ok(ProbeStatus::RefusingConnections),
+        Some(libc::ENOENT) | Some(libc::ENOTDIR) => {
+            Ok(ProbeStatus::PathMissing)
+        }
+        Some(libc::EACCES) | Some(libc::EPERM) => {
+            Ok(ProbeStatus::PermissionDenied)
+        }
+        _ => Err(err),
+    }
+}
+
+// The options and descriptor flags every socket type has, written once here
+// rather than in each impl block.
+macro_rules! impl_socket_options {
+    ($ty:ty) => {
+        impl $ty {
+            /// Sets an arbitrary socket option with `setsockopt(2)`, for
+            /// options this crate doesn't wrap.
+            ///
+            /// # Safety
+            ///
+            /// `T` must have the size and layout the kernel expects for the
+            /// option.
+            pub unsafe fn set_option<T: Copy>(&self, level: libc::c_int,
+                                              name: libc::c_int, value: T)
+                                              -> Result<()> {
+                setsockopt(self.as_raw_fd(), level, name, value)
+            }
+
+            /// Reads an arbitrary socket option with `getsockopt(2)`.
+            ///
+            /// # Safety
+            ///
+            /// `T` must have the size and layout the kernel uses for the
+            /// option, and be valid for any bit pattern the kernel might write.
+            pub unsafe fn get_option<T: Copy>(&self, level: libc::c_int,
+                                              name: libc::c_int) -> Result<T> {
+                getsockopt(self.as_raw_fd(), level, name)
+            }
+
+            /// Sets or clears close-on-exec. Sockets this crate creates or
+            /// receives are close-on-exec; clear it to pass one to a child
+            /// process.
+            pub fn set_cloexec(&self, cloexec: bool) -> Result<()> {
+                set_cloexec(self.as_raw_fd(), cloexec)
+            }
+
+            /// Returns whether close-on-exec is set.
+            pub fn cloexec(&self) -> Result<bool> {
+                cloexec(self.as_raw_fd())
+            }
+        }
+    }
+}
+
+impl_socket_options!(UnixDatagram);
+impl_socket_options!(UnixStream);
+impl_socket_options!(UnixSeqpacket);
+impl_socket_options!(SeqpacketListener);
+impl_socket_options!(UnixListener);
+
+// The options of the socket types that carry data, which the listeners
+// don't share.
+macro_rules! impl_connection_options {
+    ($ty:ty) => {
+        impl $ty {
+            /// Sets `SO_PASSCRED`, which makes the kernel attach the sender's
+            /// credentials to each received message as an `SCM_CREDENTIALS`
+            /// control message.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn set_passcred(&self, passcred: bool) -> Result<()> {
+                setsockopt(self.as_raw_fd(), libc::SOL_SOCKET,
+                           libc::SO_PASSCRED, passcred as libc::c_int)
+            }
+
+            /// Returns whether `SO_PASSCRED` is set.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn passcred(&self) -> Result<bool> {
+                getsockopt::<libc::c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                          libc::SO_PASSCRED).map(|v| v != 0)
+            }
+
+            /// Returns the credentials of the peer process, as they were when
+            /// it connected or created the socket pair. The pid is only
+            /// available on Linux, Android and macOS.
+            #[cfg(any(target_os = "linux", target_os = "android",
+                      target_os = "macos", target_os = "ios",
+                      target_os = "freebsd", target_os = "dragonfly",
+                      target_os = "netbsd", target_os = "openbsd"))]
+            pub fn peer_cred(&self) -> Result<UCred> {
+                peer_cred(self.as_raw_fd())
+            }
+
+            /// Returns the peer's security context (`SO_PEERSEC`), such as its
+            /// SELinux label, as the raw bytes the kernel reports.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn peer_sec_context(&self) -> Result<Vec<u8>> {
+                getsockopt_bytes(self.as_raw_fd(), libc::SOL_SOCKET,
+                                 libc::SO_PEERSEC, 256)
+            }
+
+            /// Returns the peer's supplementary groups (`SO_PEERGROUPS`), as
+            /// they were when it connected or created the socket pair.
+            #[cfg(target_os = "linux")]
+            pub fn peer_groups(&self) -> Result<Vec<libc::gid_t>> {
+                peer_groups(self.as_raw_fd())
+            }
+
+            /// Returns a pidfd for the peer process (`SO_PEERPIDFD`). Unlike
+            /// the pid from `peer_cred()`, it can't be recycled for another
+            /// process. Needs Linux 6.5 or later.
+            #[cfg(target_os = "linux")]
+            pub fn peer_pidfd(&self) -> Result<OwnedFd> {
+                peer_pidfd(self.as_raw_fd())
+            }
+
+            /// Sets `SO_PASSPIDFD`, which makes the kernel attach a pidfd for
+            /// the sender to each received message as an `SCM_PIDFD` control
+            /// message. Needs Linux 6.5 or later.
+            #[cfg(target_os = "linux")]
+            pub fn set_passpidfd(&self, passpidfd: bool) -> Result<()> {
+                setsockopt(self.as_raw_fd(), libc::SOL_SOCKET,
+                           libc::SO_PASSPIDFD, passpidfd as libc::c_int)
+            }
+
+            /// Returns whether `SO_PASSPIDFD` is set.
+            #[cfg(target_os = "linux")]
+            pub fn passpidfd(&self) -> Result<bool> {
+                getsockopt::<libc::c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                          libc::SO_PASSPIDFD).map(|v| v != 0)
+            }
+
+            /// Sets `SO_PASSSEC`, which makes the kernel attach the sender's
+            /// security label to each received message as an `SCM_SECURITY`
+            /// control message.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn set_passsec(&self, passsec: bool) -> Result<()> {
+                setsockopt(self.as_raw_fd(), libc::SOL_SOCKET,
+                           libc::SO_PASSSEC, passsec as libc::c_int)
+            }
+
+            /// Returns whether `SO_PASSSEC` is set.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn passsec(&self) -> Result<bool> {
+                getsockopt::<libc::c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                          libc::SO_PASSSEC).map(|v| v != 0)
+            }
+
+            /// Sets `SO_TIMESTAMP`, which makes the kernel attach the time it
+            /// received each message as an `SCM_TIMESTAMP` control message. The
+            /// time is available from `RecvMsg::timestamp()`.
+            pub fn set_timestamping(&self, timestamping: bool) -> Result<()> {
+                setsockopt(self.as_raw_fd(), libc::SOL_SOCKET,
+                           libc::SO_TIMESTAMP, timestamping as libc::c_int)
+            }
+
+            /// Returns whether `SO_TIMESTAMP` is set.
+            pub fn timestamping(&self) -> Result<bool> {
+                getsockopt::<libc::c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                          libc::SO_TIMESTAMP).map(|v| v != 0)
+            }
+
+            /// Sets `SO_TIMESTAMPNS`, like `set_timestamping()` but with
+            /// nanosecond rather than microsecond precision.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn set_timestamping_ns(&self, timestamping: bool)
+                                       -> Result<()> {
+                setsockopt(self.as_raw_fd(), libc::SOL_SOCKET,
+                           libc::SO_TIMESTAMPNS, timestamping as libc::c_int)
+            }
+
+            /// Returns whether `SO_TIMESTAMPNS` is set.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn timestamping_ns(&self) -> Result<bool> {
+                getsockopt::<libc::c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                          libc::SO_TIMESTAMPNS).map(|v| v != 0)
+            }
+
+            /// Sets the size of the socket's send buffer (`SO_SNDBUF`). The
+            /// kernel may clamp it to its limits.
+            pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+                set_buffer_size(self.as_raw_fd(), libc::SO_SNDBUF, size)
+            }
+
+            /// Returns the size of the send buffer. On Linux, which doubles the
+            /// requested size for its own bookkeeping, this is the usable half.
+            pub fn send_buffer_size(&self) -> Result<usize> {
+                buffer_size(self.as_raw_fd(), libc::SO_SNDBUF)
+            }
+
+            /// Sets the size of the socket's receive buffer (`SO_RCVBUF`). The
+            /// kernel may clamp it to its limits.
+            pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+                set_buffer_size(self.as_raw_fd(), libc::SO_RCVBUF, size)
+            }
+
+            /// Returns the size of the receive buffer, adjusted as for
+            /// `send_buffer_size()`.
+            pub fn recv_buffer_size(&self) -> Result<usize> {
+                buffer_size(self.as_raw_fd(), libc::SO_RCVBUF)
+            }
+
+            /// Returns how much sent data the peer has yet to receive
+            /// (`SIOCOUTQ`, or `SO_NWRITE` on macOS). Linux counts the memory
+            /// the queued data occupies, which is more than its payload, so
+            /// treat the value as a measure of backlog rather than an exact
+            /// byte count.
+            #[cfg(any(target_os = "linux", target_os = "android",
+                      target_os = "macos", target_os = "ios"))]
+            pub fn outgoing_queue_len(&self) -> Result<usize> {
+                outgoing_queue_len(self.as_raw_fd())
+            }
+        }
+    }
+}
+
+impl_connection_options!(UnixDatagram);
+impl_connection_options!(UnixStream);
+impl_connection_options!(UnixSeqpacket);
+
+////////////////////////////////////////////////////////////////////////////////
+// Unix Datagram
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UnixDatagram {
+    fd: OwnedFd,
+    connected: bool,
+    bound: BoundPath,
+}
+
+impl UnixDatagram {
+    pub fn connect<A: ToUnixAddr>(addr: A, ty: SockType)
+                                  -> Result<UnixDatagram> {
+        let addr = addr.to_unix_addr()?;
+        let fd = connect(&addr, ty.as_raw())?;
+        Ok(UnixDatagram{
+            fd,
+            connected: true,
+            bound: BoundPath::default(),
+        })
+    }
+
+    pub fn bind<A: ToUnixAddr>(addr: A, ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(&addr.to_unix_addr()?, ty, false)
+    }
+
+    /// Binds to a uniquely named socket in a new private directory, such as
+    /// for tests that run concurrently. The directory is created under
+    /// `std::env::temp_dir()` with mode 0700, and is removed along with the
+    /// socket file when the socket is dropped. Use `local_addr()` to find
+    /// the path to connect to.
+    pub fn bind_in_tempdir(ty: SockType) -> Result<UnixDatagram> {
+        let (mut sock, dir) = bind_in_tempdir(|addr| {
+            UnixDatagram::bind_with(addr, ty, false)
+        })?;
+        sock.bound.dir = Some(dir);
+        Ok(sock)
+    }
+
+    /// Binds to `name` in the directory open as `dir`, so that no
+    /// component of the directory's path can be swapped for another while
+    /// binding. `name` must be a single path component. The socket file
+    /// isn't tracked for unlinking on drop, as the socket has no hold on
+    /// the directory.
+    #[cfg(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd"))]
+    pub fn bind_at<D: AsFd, N: AsRef<Path>>(dir: &D, name: N, ty: SockType)
+                                            -> Result<UnixDatagram> {
+        let fd = bind_at(dir.as_fd().as_raw_fd(), name.as_ref(),
+                         ty.as_raw())?;
+        Ok(UnixDatagram {
+            fd,
+            connected: false,
+            bound: BoundPath::default(),
+        })
+    }
+
+    /// Like `bind()`, but the socket file's permissions are set to `mode`
+    /// before it is moved into place at `path`. As with `bind()`, this
+    /// fails with `ErrorKind::AddrInUse` if something is already there. See
+    /// `UnixListener::bind_with_permissions()`.
+    pub fn bind_with_permissions<P: AsRef<Path>>(path: P, mode: u32,
+                                                 ty: SockType)
+                                                 -> Result<UnixDatagram> {
+        let path = path.as_ref();
+        let mut sock = publish(path, |addr| {
+            let sock = UnixDatagram::bind_with(addr, ty, false)?;
+            set_mode(addr, mode)?;
+            Ok(sock)
+        }, |_| Ok(()))?;
+        sock.bound.publish(path)?;
+        Ok(sock)
+    }
+
+    /// Like `bind()`, but if a socket file left behind by a process that
+    /// has exited is in the way, it is removed and the bind retried. The
+    /// file is judged stale if a test connection to it is refused; if
+    /// something answers, or the path isn't a socket, this fails with
+    /// `ErrorKind::AddrInUse`. The file is only removed if it is still the
+    /// one that refused, but another process replacing it in the moment
+    /// between that check and the removal can still lose its socket, so
+    /// processes racing to bind the same path should coordinate some
+    /// other way, such as with a lock file.
+    pub fn bind_reusing<A: ToUnixAddr>(addr: A, ty: SockType)
+                                       -> Result<UnixDatagram> {
+        let addr = addr.to_unix_addr()?;
+        match UnixDatagram::bind_with(&addr, ty, false) {
+            Err(ref err) if err.kind() == ErrorKind::AddrInUse => {
+                reclaim_stale(&addr, ty.as_raw())?;
+                UnixDatagram::bind_with(&addr, ty, false)
+            }
+            result => result,
+        }
+    }
+
+    /// Binds to an address already in hand, such as one returned by
+    /// `local_addr()` or `recv_from()`. Pathname and abstract addresses are
+    /// bound as given; an unnamed address autobinds on Linux, and fails with
+    /// `ErrorKind::InvalidInput` elsewhere.
+    pub fn bind_addr(addr: &SocketAddr, ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(addr, ty, false)
+    }
+
+    /// Binds to the address given by a `unix://` URI. See
+    /// `SocketAddr::from_uri()`.
+    pub fn bind_uri(uri: &str, ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(&SocketAddr::from_uri(uri)?, ty, false)
+    }
+
+    /// Connects to the address given by a `unix://` URI.
+    pub fn connect_uri(uri: &str, ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::connect(SocketAddr::from_uri(uri)?, ty)
+    }
+
+    /// Like `bind()`, but `path` may be longer than `sun_path` allows. A
+    /// long path is bound through `/proc/self/fd` and a descriptor for its
+    /// parent directory, so `local_addr()`, and the peer address seen by
+    /// others, is that short `/proc` path rather than `path`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_long_path<P: AsRef<Path>>(path: P, ty: SockType)
+                                          -> Result<UnixDatagram> {
+        let path = path.as_ref();
+        let mut sock = with_long_path(path, |addr| {
+            UnixDatagram::bind_with(addr, ty, false)
+        })?;
+        sock.bound.retarget(path);
+        Ok(sock)
+    }
+
+    /// Like `connect()`, but `path` may be longer than `sun_path` allows.
+    /// See `bind_long_path()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_long_path<P: AsRef<Path>>(path: P, ty: SockType)
+                                             -> Result<UnixDatagram> {
+        with_long_path(path.as_ref(), |addr| UnixDatagram::connect(addr, ty))
+    }
+
+    /// Connects to an address already in hand. Pathname and abstract
+    /// addresses are connected to as given; an unnamed address can't be
+    /// connected to, and fails with `ErrorKind::InvalidInput`.
+    pub fn connect_addr(addr: &SocketAddr, ty: SockType)
+                        -> Result<UnixDatagram> {
+        UnixDatagram::connect(addr, ty)
+    }
+
+    /// Like `connect()`, but retries failed attempts as `policy` says, and
+    /// returns the last error if they all fail.
+    pub fn connect_with_retry<A: ToUnixAddr>(addr: A, ty: SockType,
+                                             policy: &RetryPolicy)
+                                             -> Result<UnixDatagram> {
+        let addr = addr.to_unix_addr()?;
+        policy.run(|| UnixDatagram::connect_addr(&addr, ty))
+    }
+
+    /// Binds to `name` in the abstract namespace. See
+    /// `SocketAddr::from_abstract_name()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract(name: &[u8], ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(&SocketAddr::from_abstract_name(name)?, ty,
+                                false)
+    }
+
+    /// Binds to a unique name in the abstract namespace chosen by the
+    /// kernel, such as a datagram client can use as its reply address.
+    /// `local_addr()` returns the name that was assigned.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn autobind(ty: SockType) -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(&SocketAddr::autobind(), ty, false)
+    }
+
+    /// Connects to `name` in the abstract namespace.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_abstract(name: &[u8], ty: SockType)
+                            -> Result<UnixDatagram> {
+        let fd = connect(&SocketAddr::from_abstract_name(name)?,
+                         ty.as_raw())?;
+        Ok(UnixDatagram {
+            fd,
+            connected: true,
+            bound: BoundPath::default(),
+        })
+    }
+
+    /// Creates a datagram socket that is neither bound nor connected. Use
+    /// `connect_to()` to give it a peer later.
+    pub fn unbound() -> Result<UnixDatagram> {
+        Ok(UnixDatagram {
+            fd: unix_socket(libc::SOCK_DGRAM, false)?,
+            connected: false,
+            bound: BoundPath::default(),
+        })
+    }
+
+    /// Like `bind()`, but the socket is created in non-blocking mode.
+    pub fn bind_nonblocking<A: ToUnixAddr>(addr: A, ty: SockType)
+                                           -> Result<UnixDatagram> {
+        UnixDatagram::bind_with(&addr.to_unix_addr()?, ty, true)
+    }
+
+    fn bind_with(addr: &SocketAddr, ty: SockType, nonblocking: bool)
+                 -> Result<UnixDatagram> {
+        bind(addr, ty.as_raw(), nonblocking).map(|fd| {
+            UnixDatagram {
+                fd,
+                connected: false,
+                bound: BoundPath::new(addr),
+            }
+        })
+    }
+
+    /// Creates an unnamed pair of connected sockets, using `socketpair(2)`.
+    pub fn pair() -> Result<(UnixDatagram, UnixDatagram)> {
+        UnixDatagram::pair_with(false)
+    }
+
+    /// Like `pair()`, but both sockets are created in non-blocking mode.
+    pub fn pair_nonblocking() -> Result<(UnixDatagram, UnixDatagram)> {
+        UnixDatagram::pair_with(true)
+    }
+
+    fn pair_with(nonblocking: bool) -> Result<(UnixDatagram, UnixDatagram)> {
+        let (a, b) = socketpair(libc::SOCK_DGRAM, nonblocking)?;
+        Ok((UnixDatagram {
+                fd: a,
+                connected: true,
+                bound: BoundPath::default(),
+            },
+            UnixDatagram {
+                fd: b,
+                connected: true,
+                bound: BoundPath::default(),
+            }))
+    }
+
+    /// Closes the socket, reporting any error from `close(2)`. Dropping the
+    /// socket also closes it, but ignores errors.
+    pub fn close(mut self) -> Result<()> {
+        self.bound.unlink();
+        close(self.into_raw_fd())
+    }
+
+    /// Sets whether the socket file is unlinked when the socket is dropped
+    /// or closed, which is the default for a socket bound to a pathname.
+    /// The file is only removed if it is still the one the socket created.
+    /// Handles made by `try_clone()` or from raw descriptors never unlink.
+    pub fn set_cleanup(&mut self, cleanup: bool) {
+        self.bound.cleanup = cleanup;
+    }
+
+    /// Stops tracking the socket file, so it is never unlinked, and returns
+    /// its path if it was being tracked.
+    pub fn forget_path(&mut self) -> Option<PathBuf> {
+        self.bound.path.take()
+    }
+
+    /// Changes the owner and group of the socket file, leaving either
+    /// unchanged if `None`, such as for a service started as root that
+    /// serves a particular group. Fails with `ErrorKind::PermissionDenied`
+    /// if the process isn't allowed to make the change.
+    pub fn set_socket_owner(&self, uid: Option<libc::uid_t>,
+                            gid: Option<libc::gid_t>) -> Result<()> {
+        set_owner(self.as_raw_fd(), &self.bound, uid, gid)
+    }
+
+    /// Connects the socket to `addr`, so that `send()` and `recv()` can be
+    /// used. This is the method form of the `connect()` constructor.
+    pub fn connect_to<A: ToUnixAddr>(&mut self, addr: A) -> Result<()> {
+        let addr = addr.to_unix_addr()?;
+        connect_fd(self.as_raw_fd(), &addr)?;
+        self.connected = true;
+        Ok(())
+    }
+
+    /// Dissolves the association made by `connect()` or `connect_to()`, by
+    /// connecting to an `AF_UNSPEC` address. Afterwards the socket can be
+    /// used with `send_to_addr()` and `recv_from()` again.
+    pub fn disconnect(&mut self) -> Result<()> {
+        let mut addr: libc::sockaddr = unsafe { mem::zeroed() };
+        addr.sa_family = libc::AF_UNSPEC as libc::sa_family_t;
+        match unsafe {
+            libc::connect(self.as_raw_fd(), &addr,
+                          mem::size_of::<libc::sockaddr>() as libc::socklen_t)
+        } {
+            -1 => Err(last_error()),
+            _  => {
+                self.connected = false;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.connected {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "must call connect() before calling recv()"));
+        }
+
+        recv_packet(self.as_raw_fd(), buf).map(|(n, _)| n)
+    }
+
+    pub fn recvfrom(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.recv_from(buf).map(|(n, _)| n)
+    }
+
+    /// Receives a datagram, returning its length and the address of the
+    /// socket that sent it. If the datagram doesn't fit in `buf`, the rest
+    /// of it is discarded and a `MessageTruncated` error is returned.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        recv_packet(self.as_raw_fd(), buf)
+    }
+
+    /// Sends `buf` to the connected peer, returning the number of bytes
+    /// sent. For `SockType::Stream` sockets this may be less than
+    /// `buf.len()`; for packet-based sockets a message that can't be sent
+    /// whole is an error.
+    pub fn send(&mut self, buf: &[u8]) -> Result<usize> {
+        if !self.connected {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "must call connect() before calling send()"));
+        }
+
+        match write(self.as_raw_fd(), buf)? {
+            n if n != buf.len() && !self.is_stream()? => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               "couldn't send entire packet at once"))
+            }
+            n => Ok(n)
+        }
+    }
+
+    fn is_stream(&self) -> Result<bool> {
+        let ty: libc::c_int =
+            getsockopt(self.as_raw_fd(), libc::SOL_SOCKET, libc::SO_TYPE)?;
+        Ok(ty == libc::SOCK_STREAM)
+    }
+
+    pub fn sendto<A: ToUnixAddr>(&mut self, buf: &[u8], dst: A) -> Result<()> {
+        self.send_to_addr(buf, dst)
+    }
+
+    /// Sends a datagram to `dst`, which may be a pathname or abstract
+    /// address, such as one returned by `recv_from()`.
+    pub fn send_to_addr<A: ToUnixAddr>(&self, buf: &[u8], dst: A)
+                                       -> Result<()> {
+        let dst = dst.to_unix_addr()?;
+        match send_to(self.as_raw_fd(), buf, &dst, MsgFlags::empty())? {
+            n if n != buf.len() => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               "couldn't send entire packet at once"))
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Sends a single datagram gathered from `bufs` to the connected peer,
+    /// using `sendmsg(2)`.
+    pub fn send_vectored(&self, bufs: &[IoSlice]) -> Result<usize> {
+        send_vectored(self.as_raw_fd(), bufs)
+    }
+
+    /// Receives a single datagram, scattering it across `bufs`.
+    pub fn recv_vectored(&self, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        recv_vectored(self.as_raw_fd(), bufs)
+    }
+
+    /// Creates a new independently owned handle to the same socket.
+    pub fn try_clone(&self) -> Result<UnixDatagram> {
+        Ok(UnixDatagram {
+            fd: duplicate(self.as_fd())?,
+            connected: self.connected,
+            bound: self.bound.for_clone(),
+        })
+    }
+
+    /// Shuts down the read, write, or both halves of the socket. Further
+    /// calls to the affected halves will fail or see end-of-file.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        shutdown(self.as_raw_fd(), how)
+    }
+
+    /// Returns the address this socket is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.bound.local_addr(self.as_raw_fd())
+    }
+
+    /// Returns the address of the peer this socket is connected to.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        peer_addr(self.as_raw_fd())
+    }
+
+    /// Moves the socket into or out of non-blocking mode. In non-blocking
+    /// mode, operations that would block fail with `ErrorKind::WouldBlock`.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    /// Sets the timeout for receive operations. A blocked receive that
+    /// times out fails with `ErrorKind::WouldBlock`. `None` blocks forever,
+    /// and a zero duration is rejected.
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_RCVTIMEO)
+    }
+
+    /// Sets the timeout for send operations, like `set_read_timeout()`.
+    pub fn set_write_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_SNDTIMEO)
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_RCVTIMEO)
+    }
+
+    pub fn write_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_SNDTIMEO)
+    }
+
+    /// Returns and clears the pending socket error (`SO_ERROR`), if any.
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    /// Receives a message with `recvmsg(2)`, scattering the data across
+    /// `bufs` and placing any control messages in `control`. The result
+    /// gives the sender's address, the returned `MSG_*` flags, and a view
+    /// of the control messages. Descriptors received in `SCM_RIGHTS`
+    /// messages are close-on-exec.
+    ///
+    /// If `control` is too small for what was sent, the received
+    /// descriptors are closed and a `ControlTruncated` error is returned.
+    pub fn recv_msg<'a>(&self, bufs: &mut [IoSliceMut], control: &'a mut [u8],
+                        flags: MsgFlags) -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, true)
+    }
+
+    /// Like `recv_msg()`, but leaves descriptors received in `SCM_RIGHTS`
+    /// messages inheritable by child processes.
+    pub fn recv_msg_inheritable<'a>(&self, bufs: &mut [IoSliceMut],
+                                    control: &'a mut [u8], flags: MsgFlags)
+                                    -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, false)
+    }
+
+    /// Sends a message with `sendmsg(2)`, gathering the data from `bufs`.
+    /// `addr` is the destination for unconnected sockets, and `control`
+    /// holds prepared control messages.
+    pub fn send_msg(&self, bufs: &[IoSlice], addr: Option<&SocketAddr>,
+                    control: &[u8], flags: MsgFlags) -> Result<usize> {
+        msg::send_msg(self.as_raw_fd(), bufs, addr, control, flags)
+    }
+
+    /// Sends `data` to the connected peer along with duplicates of `fds`,
+    /// in an `SCM_RIGHTS` message.
+    pub fn send_fds(&self, fds: &[BorrowedFd], data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), fds, data)
+    }
+
+    /// Receives data into `buf`, appending any file descriptors passed with
+    /// it to `fds`. Returns the number of bytes received. The descriptors
+    /// are close-on-exec.
+    pub fn recv_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>)
+                    -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, true)
+    }
+
+    /// Like `recv_fds()`, but leaves the received descriptors inheritable
+    /// by child processes.
+    pub fn recv_fds_inheritable(&self, buf: &mut [u8],
+                                fds: &mut Vec<OwnedFd>) -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, false)
+    }
+
+    /// Passes `sock`, such as a `UnixListener` or `UnixStream`, to the peer
+    /// along with `data`. The peer can rebuild it with `recv_socket()`.
+    pub fn send_socket<S: AsFd>(&self, sock: &S, data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), &[sock.as_fd()], data)
+    }
+
+    /// Receives a socket sent with `send_socket()`, checking that it is of
+    /// type `S`. Returns the number of bytes of data received into `buf`.
+    pub fn recv_socket<S: FromSocketFd>(&self, buf: &mut [u8])
+                                        -> Result<(usize, S)> {
+        recv_socket(self.as_raw_fd(), buf)
+    }
+
+    /// Returns the number of bytes waiting to be received (`FIONREAD`). On
+    /// Linux this is the size of the next datagram; some other platforms
+    /// report the total across all queued datagrams.
+    pub fn bytes_available(&self) -> Result<usize> {
+        bytes_available(self.as_raw_fd())
+    }
+
+    /// Returns whether the socket is in non-blocking mode, for sockets
+    /// inherited or received from elsewhere.
+    pub fn nonblocking(&self) -> Result<bool> {
+        is_nonblocking(self.as_raw_fd())
+    }
+
+    /// Receives from the connected peer like `recv()`, passing `flags` to
+    /// `recv(2)`: for instance `MsgFlags::DONTWAIT` for a single
+    /// non-blocking receive, or `MsgFlags::PEEK`.
+    pub fn recv_with_flags(&self, buf: &mut [u8], flags: MsgFlags)
+                           -> Result<usize> {
+        recv(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Sends to the connected peer, passing `flags` to `send(2)`. Returns
+    /// the number of bytes sent.
+    pub fn send_with_flags(&self, buf: &[u8], flags: MsgFlags)
+                           -> Result<usize> {
+        send(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Like `recv_from()`, passing `flags` to `recvfrom(2)`.
+    pub fn recv_from_with_flags(&self, buf: &mut [u8], flags: MsgFlags)
+                                -> Result<(usize, SocketAddr)> {
+        recv_from(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Like `send_to_addr()`, passing `flags` to `sendto(2)`. Returns the
+    /// number of bytes sent.
+    pub fn send_to_with_flags(&self, buf: &[u8], dst: &SocketAddr,
+                              flags: MsgFlags) -> Result<usize> {
+        send_to(self.as_raw_fd(), buf, dst, flags)
+    }
+
+    /// Returns the length of the next datagram without receiving it, so a
+    /// buffer of exactly the right size can be passed to `recv_from()`.
+    /// Blocks until a datagram arrives, unless the socket is non-blocking.
+    pub fn peek_len(&self) -> Result<usize> {
+        peek_len(self.as_raw_fd())
+    }
+
+    /// Receives up to `msgs.len()` datagrams in one `recvmmsg(2)` call,
+    /// returning how many were received. Each `MsgBuffer` records its
+    /// datagram's length and sender.
+    ///
+    /// This waits for the first datagram, unless the socket is
+    /// non-blocking, then takes whatever else is already queued without
+    /// waiting further. `timeout` bounds the wait for the first datagram,
+    /// after which a `TimedOut` error is returned.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn recv_multiple(&self, msgs: &mut [MsgBuffer],
+                         timeout: Option<Duration>) -> Result<usize> {
+        msg::recv_multiple(self.as_raw_fd(), msgs, timeout)
+    }
+
+    /// Sends a batch of datagrams in one `sendmmsg(2)` call, returning how
+    /// many the kernel accepted. If that is fewer than `msgs.len()`, the
+    /// rest can be retried.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn send_multiple(&self, msgs: &[OutgoingMsg]) -> Result<usize> {
+        msg::send_multiple(self.as_raw_fd(), msgs)
+    }
+
+    /// Blocks until the socket is readable or `timeout` passes, returning
+    /// whether it became readable. A hang-up or pending error also counts as
+    /// readable, since the next receive will report it without blocking.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLIN, timeout).map(|ev| ev != 0)
+    }
+
+    /// Like `wait_readable()`, but waits for the socket to be writable.
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLOUT, timeout).map(|ev| ev != 0)
+    }
+
+    /// Like `recv_from()`, but returns early if `cancel` is cancelled from
+    /// another thread, with an `ErrorKind::Interrupted` error wrapping
+    /// `Cancelled`.
+    pub fn recv_from_cancellable(&self, buf: &mut [u8], cancel: &CancelHandle)
+                                 -> Result<(usize, SocketAddr)> {
+        cancel::retry_until_cancelled(self.as_raw_fd(), cancel, || {
+            recv_packet_with(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+        })
+    }
+
+    /// Like `recv_from()`, but fails with `ErrorKind::TimedOut` if no
+    /// datagram arrives before `deadline`. Unlike a read timeout, one
+    /// deadline can bound a whole series of calls.
+    pub fn recv_from_deadline(&self, buf: &mut [u8], deadline: Instant)
+                              -> Result<(usize, SocketAddr)> {
+        recv_until(self.as_raw_fd(), deadline, || {
+            recv_packet_with(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+        })
+    }
+
+    /// Like `recv_from_deadline()`, but returns only the datagram's length.
+    pub fn recv_deadline(&self, buf: &mut [u8], deadline: Instant)
+                         -> Result<usize> {
+        self.recv_from_deadline(buf, deadline).map(|(n, _)| n)
+    }
+}
+
+impl AsRawFd for UnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for UnixDatagram {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl FromRawFd for UnixDatagram {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixDatagram {
+        UnixDatagram::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for UnixDatagram {
+    fn into_raw_fd(mut self) -> RawFd {
+        self.bound.path = None;
+        self.fd.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for UnixDatagram {
+    fn from(fd: OwnedFd) -> UnixDatagram {
+        UnixDatagram {
+            connected: is_connected(fd.as_raw_fd()),
+            fd,
+            bound: BoundPath::default(),
+        }
+    }
+}
+
+impl From<UnixDatagram> for OwnedFd {
+    fn from(mut sock: UnixDatagram) -> OwnedFd {
+        sock.bound.path = None;
+        sock.fd
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unix Stream
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UnixStream {
+    fd: OwnedFd,
+}
+
+impl UnixStream {
+    pub fn connect<A: ToUnixAddr>(addr: A) -> Result<UnixStream> {
+        let addr = addr.to_unix_addr()?;
+        let fd = connect(&addr, libc::SOCK_STREAM)?;
+        Ok(UnixStream {
+            fd,
+        })
+    }
+
+    /// Connects to `name` in the abstract namespace. See
+    /// `SocketAddr::from_abstract_name()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_abstract(name: &[u8]) -> Result<UnixStream> {
+        let addr = SocketAddr::from_abstract_name(name)?;
+        Ok(UnixStream {
+            fd: connect(&addr, libc::SOCK_STREAM)?,
+        })
+    }
+
+    /// Connects to `addr`, failing with `ErrorKind::TimedOut` if the
+    /// connection isn't established within `timeout`.
+    pub fn connect_timeout<A: ToUnixAddr>(addr: A, timeout: Duration)
+                                          -> Result<UnixStream> {
+        let addr = addr.to_unix_addr()?;
+        let fd = connect_timeout(&addr, libc::SOCK_STREAM, timeout)?;
+        Ok(UnixStream {
+            fd,
+        })
+    }
+
+    /// Begins connecting a non-blocking socket to `addr` without waiting
+    /// for the connection to be established. Wait for the returned handle
+    /// to become writable, then call `finish_connect()` on it.
+    pub fn start_connect<A: ToUnixAddr>(addr: A)
+                                        -> Result<PendingConnect<UnixStream>> {
+        PendingConnect::start(addr, libc::SOCK_STREAM)
+    }
+
+    /// See `UnixDatagram::connect_addr()`.
+    pub fn connect_addr(addr: &SocketAddr) -> Result<UnixStream> {
+        UnixStream::connect(addr)
+    }
+
+    /// See `UnixDatagram::connect_with_retry()`.
+    pub fn connect_with_retry<A: ToUnixAddr>(addr: A, policy: &RetryPolicy)
+                                             -> Result<UnixStream> {
+        let addr = addr.to_unix_addr()?;
+        policy.run(|| UnixStream::connect_addr(&addr))
+    }
+
+    /// See `UnixDatagram::connect_uri()`.
+    pub fn connect_uri(uri: &str) -> Result<UnixStream> {
+        UnixStream::connect(SocketAddr::from_uri(uri)?)
+    }
+
+    /// See `UnixDatagram::connect_long_path()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_long_path<P: AsRef<Path>>(path: P) -> Result<UnixStream> {
+        with_long_path(path.as_ref(), UnixStream::connect_addr)
+    }
+
+    /// Creates an unnamed pair of connected sockets.
+    pub fn pair() -> Result<(UnixStream, UnixStream)> {
+        UnixStream::pair_with(false)
+    }
+
+    /// Like `pair()`, but both sockets are created in non-blocking mode.
+    pub fn pair_nonblocking() -> Result<(UnixStream, UnixStream)> {
+        UnixStream::pair_with(true)
+    }
+
+    fn pair_with(nonblocking: bool) -> Result<(UnixStream, UnixStream)> {
+        let (a, b) = socketpair(libc::SOCK_STREAM, nonblocking)?;
+        Ok((UnixStream { fd: a }, UnixStream { fd: b }))
+    }
+
+    /// Closes the socket, reporting any error from `close(2)`.
+    pub fn close(self) -> Result<()> {
+        close(self.into_raw_fd())
+    }
+
+    /// Creates a new handle to the same socket.
+    pub fn try_clone(&self) -> Result<UnixStream> {
+        Ok(UnixStream {
+            fd: duplicate(self.as_fd())?,
+        })
+    }
+
+    /// Returns the connection passed on standard input, as by inetd or
+    /// another super-server, after checking that it is a connected
+    /// `AF_UNIX` stream socket.
+    ///
+    /// The returned stream is a duplicate of descriptor 0, so standard
+    /// input and output are left open and still refer to the connection.
+    pub fn from_stdio() -> Result<UnixStream> {
+        let stdin = unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) };
+        check_socket(stdin.as_raw_fd(), libc::SOCK_STREAM, false)?;
+        if let Err(err) = peer_addr(stdin.as_raw_fd()) {
+            if err.raw_os_error() == Some(libc::ENOTCONN) {
+                return Err(Error::new(ErrorKind::NotConnected,
+                                      "socket is not connected"));
+            }
+            return Err(err);
+        }
+        Ok(UnixStream { fd: duplicate(stdin)? })
+    }
+
+    /// Shuts down the read, write, or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        shutdown(self.as_raw_fd(), how)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        local_addr(self.as_raw_fd())
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        peer_addr(self.as_raw_fd())
+    }
+
+    /// Moves the socket into or out of non-blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_RCVTIMEO)
+    }
+
+    pub fn set_write_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_SNDTIMEO)
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_RCVTIMEO)
+    }
+
+    pub fn write_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_SNDTIMEO)
+    }
+
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    /// Writes all of `buf`, retrying after short writes and interruptions.
+    /// If the socket is non-blocking, waits for it to become writable. Fails
+    /// with `ErrorKind::TimedOut` if the write timeout passes without any
+    /// progress.
+    pub fn send_all(&self, buf: &[u8]) -> Result<()> {
+        write_all(self.as_raw_fd(), buf)
+    }
+
+    /// Returns true, as `read_vectored()` is implemented with `readv(2)`
+    /// rather than reading into the first buffer only.
+    pub fn is_read_vectored(&self) -> bool {
+        true
+    }
+
+    /// Returns true, as `write_vectored()` is implemented with `sendmsg(2)`.
+    pub fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    /// Receives a message with `recvmsg(2)`. See `UnixDatagram::recv_msg()`.
+    pub fn recv_msg<'a>(&self, bufs: &mut [IoSliceMut], control: &'a mut [u8],
+                        flags: MsgFlags) -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, true)
+    }
+
+    /// See `UnixDatagram::recv_msg_inheritable()`.
+    pub fn recv_msg_inheritable<'a>(&self, bufs: &mut [IoSliceMut],
+                                    control: &'a mut [u8], flags: MsgFlags)
+                                    -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, false)
+    }
+
+    /// Sends a message with `sendmsg(2)`. See `UnixDatagram::send_msg()`.
+    pub fn send_msg(&self, bufs: &[IoSlice], addr: Option<&SocketAddr>,
+                    control: &[u8], flags: MsgFlags) -> Result<usize> {
+        msg::send_msg(self.as_raw_fd(), bufs, addr, control, flags)
+    }
+
+    /// Sends `data` along with duplicates of `fds`. See
+    /// `UnixDatagram::send_fds()`.
+    pub fn send_fds(&self, fds: &[BorrowedFd], data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), fds, data)
+    }
+
+    /// Receives data and file descriptors. See `UnixDatagram::recv_fds()`.
+    pub fn recv_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>)
+                    -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, true)
+    }
+
+    /// See `UnixDatagram::recv_fds_inheritable()`.
+    pub fn recv_fds_inheritable(&self, buf: &mut [u8],
+                                fds: &mut Vec<OwnedFd>) -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, false)
+    }
+
+    /// Passes `sock` to the peer. See `UnixDatagram::send_socket()`.
+    pub fn send_socket<S: AsFd>(&self, sock: &S, data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), &[sock.as_fd()], data)
+    }
+
+    /// Receives a socket. See `UnixDatagram::recv_socket()`.
+    pub fn recv_socket<S: FromSocketFd>(&self, buf: &mut [u8])
+                                        -> Result<(usize, S)> {
+        recv_socket(self.as_raw_fd(), buf)
+    }
+
+    /// Returns the number of bytes waiting to be read (`FIONREAD`).
+    pub fn bytes_available(&self) -> Result<usize> {
+        bytes_available(self.as_raw_fd())
+    }
+
+    /// See `UnixDatagram::nonblocking()`.
+    pub fn nonblocking(&self) -> Result<bool> {
+        is_nonblocking(self.as_raw_fd())
+    }
+
+    /// Receives data, passing `flags` to `recv(2)`. See
+    /// `UnixDatagram::recv_with_flags()`.
+    pub fn recv_with_flags(&self, buf: &mut [u8], flags: MsgFlags)
+                           -> Result<usize> {
+        recv(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Sends data, passing `flags` to `send(2)`.
+    pub fn send_with_flags(&self, buf: &[u8], flags: MsgFlags)
+                           -> Result<usize> {
+        send(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Moves up to `len` bytes from this socket to `dst`, returning how many
+    /// were moved; 0 means the peer has shut down. On Linux this uses
+    /// `splice(2)` when `dst` is a pipe, so the data never enters
+    /// userspace; otherwise it is copied through a buffer, which fails with
+    /// `InvalidInput` if `dst` is non-blocking, as a short write to it would
+    /// lose data already read from the socket.
+    pub fn splice_to<F: AsFd>(&self, dst: &F, len: usize) -> Result<usize> {
+        splice::splice_to(self.as_raw_fd(), dst.as_fd().as_raw_fd(), len)
+    }
+
+    /// Moves up to `len` bytes from `src` to this socket, returning how many
+    /// were moved; 0 means `src` is at end of file. As for `splice_to()`,
+    /// `src` needs to be a pipe to avoid a copy.
+    pub fn splice_from<F: AsFd>(&self, src: &F, len: usize)
+                                -> Result<usize> {
+        splice::splice_from(self.as_raw_fd(), src.as_fd().as_raw_fd(), len)
+    }
+
+    /// Sends `len` bytes of `file`, starting at `offset`, returning the
+    /// number sent; this is less than `len` if the file ends first, or if a
+    /// non-blocking socket fills up. The file's position isn't changed. On
+    /// Linux this uses `sendfile(2)`; elsewhere the file is read into a
+    /// buffer.
+    pub fn send_file(&self, file: &File, offset: u64, len: u64)
+                     -> Result<u64> {
+        splice::send_file(self.as_raw_fd(), file.as_raw_fd(), offset, len)
+    }
+
+    /// See `UnixDatagram::wait_readable()`.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLIN, timeout).map(|ev| ev != 0)
+    }
+
+    /// See `UnixDatagram::wait_writable()`.
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLOUT, timeout).map(|ev| ev != 0)
+    }
+
+    /// Reads like `read()`, but returns early if `cancel` is cancelled. See
+    /// `UnixDatagram::recv_from_cancellable()`.
+    pub fn read_cancellable(&self, buf: &mut [u8], cancel: &CancelHandle)
+                            -> Result<usize> {
+        cancel::retry_until_cancelled(self.as_raw_fd(), cancel, || {
+            recv(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+        })
+    }
+
+    /// Reads like `read()`, but fails with `ErrorKind::TimedOut` if no data
+    /// arrives before `deadline`. See `UnixDatagram::recv_from_deadline()`.
+    pub fn recv_deadline(&self, buf: &mut [u8], deadline: Instant)
+                         -> Result<usize> {
+        recv_until(self.as_raw_fd(), deadline, || {
+            recv(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+        })
+    }
+}
+
+impl AsRawFd for UnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for UnixStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl FromRawFd for UnixStream {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixStream {
+        UnixStream::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for UnixStream {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for UnixStream {
+    fn from(fd: OwnedFd) -> UnixStream {
+        UnixStream {
+            fd,
+        }
+    }
+}
+
+impl From<UnixStream> for OwnedFd {
+    fn from(sock: UnixStream) -> OwnedFd {
+        sock.fd
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read(self.as_raw_fd(), buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        read_vectored(self.as_raw_fd(), bufs)
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        (&*self).write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl Write for &UnixStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write(self.as_raw_fd(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        send_vectored(self.as_raw_fd(), bufs)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.send_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unix Seqpacket
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UnixSeqpacket {
+    fd: OwnedFd,
+}
+
+impl UnixSeqpacket {
+    pub fn connect<A: ToUnixAddr>(addr: A) -> Result<UnixSeqpacket> {
+        let addr = addr.to_unix_addr()?;
+        let fd = connect(&addr, libc::SOCK_SEQPACKET)?;
+        Ok(UnixSeqpacket {
+            fd,
+        })
+    }
+
+    /// See `UnixStream::connect_abstract()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_abstract(name: &[u8]) -> Result<UnixSeqpacket> {
+        let addr = SocketAddr::from_abstract_name(name)?;
+        Ok(UnixSeqpacket {
+            fd: connect(&addr, libc::SOCK_SEQPACKET)?,
+        })
+    }
+
+    /// Like `connect()`, but fails with `ErrorKind::TimedOut` if the
+    /// connection isn't established within `timeout`.
+    pub fn connect_timeout<A: ToUnixAddr>(addr: A, timeout: Duration)
+                                          -> Result<UnixSeqpacket> {
+        let addr = addr.to_unix_addr()?;
+        let fd = connect_timeout(&addr, libc::SOCK_SEQPACKET, timeout)?;
+        Ok(UnixSeqpacket {
+            fd,
+        })
+    }
+
+    /// See `UnixStream::start_connect()`.
+    pub fn start_connect<A: ToUnixAddr>(addr: A)
+        -> Result<PendingConnect<UnixSeqpacket>> {
+        PendingConnect::start(addr, libc::SOCK_SEQPACKET)
+    }
+
+    /// See `UnixDatagram::connect_addr()`.
+    pub fn connect_addr(addr: &SocketAddr) -> Result<UnixSeqpacket> {
+        UnixSeqpacket::connect(addr)
+    }
+
+    /// See `UnixDatagram::connect_with_retry()`.
+    pub fn connect_with_retry<A: ToUnixAddr>(addr: A, policy: &RetryPolicy)
+                                             -> Result<UnixSeqpacket> {
+        let addr = addr.to_unix_addr()?;
+        policy.run(|| UnixSeqpacket::connect_addr(&addr))
+    }
+
+    /// See `UnixDatagram::connect_uri()`.
+    pub fn connect_uri(uri: &str) -> Result<UnixSeqpacket> {
+        UnixSeqpacket::connect(SocketAddr::from_uri(uri)?)
+    }
+
+    /// See `UnixDatagram::connect_long_path()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_long_path<P: AsRef<Path>>(path: P) -> Result<UnixSeqpacket> {
+        with_long_path(path.as_ref(), UnixSeqpacket::connect_addr)
+    }
+
+    /// Creates an unnamed pair of connected sockets.
+    pub fn pair() -> Result<(UnixSeqpacket, UnixSeqpacket)> {
+        UnixSeqpacket::pair_with(false)
+    }
+
+    /// Like `pair()`, but both sockets are created in non-blocking mode.
+    pub fn pair_nonblocking() -> Result<(UnixSeqpacket, UnixSeqpacket)> {
+        UnixSeqpacket::pair_with(true)
+    }
+
+    fn pair_with(nonblocking: bool) -> Result<(UnixSeqpacket, UnixSeqpacket)> {
+        let (a, b) = socketpair(libc::SOCK_SEQPACKET, nonblocking)?;
+        Ok((UnixSeqpacket { fd: a }, UnixSeqpacket { fd: b }))
+    }
+
+    /// Closes the socket, reporting any error from `close(2)`.
+    pub fn close(self) -> Result<()> {
+        close(self.into_raw_fd())
+    }
+
+    /// Sends `buf` as a single packet. The packet is never split; if it
+    /// can't be sent whole, an error is returned.
+    pub fn send(&self, buf: &[u8]) -> Result<()> {
+        send_packet(self.as_raw_fd(), buf)
+    }
+
+    /// Receives a single packet. If the packet doesn't fit in `buf`, the
+    /// rest of it is discarded and a `MessageTruncated` error is returned.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        recv_packet(self.as_raw_fd(), buf).map(|(n, _)| n)
+    }
+
+    /// Creates a new handle to the same socket.
+    pub fn try_clone(&self) -> Result<UnixSeqpacket> {
+        Ok(UnixSeqpacket {
+            fd: duplicate(self.as_fd())?,
+        })
+    }
+
+    /// Shuts down the read, write, or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        shutdown(self.as_raw_fd(), how)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        local_addr(self.as_raw_fd())
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        peer_addr(self.as_raw_fd())
+    }
+
+    /// Moves the socket into or out of non-blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_RCVTIMEO)
+    }
+
+    pub fn set_write_timeout(&self, dur: Option<Duration>) -> Result<()> {
+        set_timeout(self.as_raw_fd(), dur, libc::SO_SNDTIMEO)
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_RCVTIMEO)
+    }
+
+    pub fn write_timeout(&self) -> Result<Option<Duration>> {
+        timeout(self.as_raw_fd(), libc::SO_SNDTIMEO)
+    }
+
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    /// Receives a message with `recvmsg(2)`. See `UnixDatagram::recv_msg()`.
+    pub fn recv_msg<'a>(&self, bufs: &mut [IoSliceMut], control: &'a mut [u8],
+                        flags: MsgFlags) -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, true)
+    }
+
+    /// See `UnixDatagram::recv_msg_inheritable()`.
+    pub fn recv_msg_inheritable<'a>(&self, bufs: &mut [IoSliceMut],
+                                    control: &'a mut [u8], flags: MsgFlags)
+                                    -> Result<RecvMsg<'a>> {
+        msg::recv_msg(self.as_raw_fd(), bufs, control, flags, false)
+    }
+
+    /// Sends a message with `sendmsg(2)`. See `UnixDatagram::send_msg()`.
+    pub fn send_msg(&self, bufs: &[IoSlice], addr: Option<&SocketAddr>,
+                    control: &[u8], flags: MsgFlags) -> Result<usize> {
+        msg::send_msg(self.as_raw_fd(), bufs, addr, control, flags)
+    }
+
+    /// Sends `data` along with duplicates of `fds`. See
+    /// `UnixDatagram::send_fds()`.
+    pub fn send_fds(&self, fds: &[BorrowedFd], data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), fds, data)
+    }
+
+    /// Receives data and file descriptors. See `UnixDatagram::recv_fds()`.
+    pub fn recv_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>)
+                    -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, true)
+    }
+
+    /// See `UnixDatagram::recv_fds_inheritable()`.
+    pub fn recv_fds_inheritable(&self, buf: &mut [u8],
+                                fds: &mut Vec<OwnedFd>) -> Result<usize> {
+        msg::recv_fds(self.as_raw_fd(), buf, fds, false)
+    }
+
+    /// Passes `sock` to the peer. See `UnixDatagram::send_socket()`.
+    pub fn send_socket<S: AsFd>(&self, sock: &S, data: &[u8]) -> Result<usize> {
+        msg::send_fds(self.as_raw_fd(), &[sock.as_fd()], data)
+    }
+
+    /// Receives a socket. See `UnixDatagram::recv_socket()`.
+    pub fn recv_socket<S: FromSocketFd>(&self, buf: &mut [u8])
+                                        -> Result<(usize, S)> {
+        recv_socket(self.as_raw_fd(), buf)
+    }
+
+    /// Returns the number of bytes waiting to be received (`FIONREAD`),
+    /// across all queued packets.
+    pub fn bytes_available(&self) -> Result<usize> {
+        bytes_available(self.as_raw_fd())
+    }
+
+    /// See `UnixDatagram::nonblocking()`.
+    pub fn nonblocking(&self) -> Result<bool> {
+        is_nonblocking(self.as_raw_fd())
+    }
+
+    /// Receives data, passing `flags` to `recv(2)`. See
+    /// `UnixDatagram::recv_with_flags()`.
+    pub fn recv_with_flags(&self, buf: &mut [u8], flags: MsgFlags)
+                           -> Result<usize> {
+        recv(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Sends data, passing `flags` to `send(2)`.
+    pub fn send_with_flags(&self, buf: &[u8], flags: MsgFlags)
+                           -> Result<usize> {
+        send(self.as_raw_fd(), buf, flags)
+    }
+
+    /// Returns the length of the next packet without receiving it. See
+    /// `UnixDatagram::peek_len()`.
+    pub fn peek_len(&self) -> Result<usize> {
+        peek_len(self.as_raw_fd())
+    }
+
+    /// Receives a batch of packets. See `UnixDatagram::recv_multiple()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn recv_multiple(&self, msgs: &mut [MsgBuffer],
+                         timeout: Option<Duration>) -> Result<usize> {
+        msg::recv_multiple(self.as_raw_fd(), msgs, timeout)
+    }
+
+    /// Sends a batch of packets. See `UnixDatagram::send_multiple()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn send_multiple(&self, msgs: &[OutgoingMsg]) -> Result<usize> {
+        msg::send_multiple(self.as_raw_fd(), msgs)
+    }
+
+    /// See `UnixDatagram::wait_readable()`.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLIN, timeout).map(|ev| ev != 0)
+    }
+
+    /// See `UnixDatagram::wait_writable()`.
+    pub fn wait_writable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLOUT, timeout).map(|ev| ev != 0)
+    }
+
+    /// Like `recv()`, but returns early if `cancel` is cancelled. See
+    /// `UnixDatagram::recv_from_cancellable()`.
+    pub fn recv_cancellable(&self, buf: &mut [u8], cancel: &CancelHandle)
+                            -> Result<usize> {
+        cancel::retry_until_cancelled(self.as_raw_fd(), cancel, || {
+            recv_packet_with(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+                .map(|(n, _)| n)
+        })
+    }
+
+    /// See `UnixDatagram::recv_deadline()`.
+    pub fn recv_deadline(&self, buf: &mut [u8], deadline: Instant)
+                         -> Result<usize> {
+        recv_until(self.as_raw_fd(), deadline, || {
+            recv_packet_with(self.as_raw_fd(), buf, MsgFlags::DONTWAIT)
+                .map(|(n, _)| n)
+        })
+    }
+}
+
+impl AsRawFd for UnixSeqpacket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for UnixSeqpacket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl FromRawFd for UnixSeqpacket {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixSeqpacket {
+        UnixSeqpacket::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for UnixSeqpacket {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for UnixSeqpacket {
+    fn from(fd: OwnedFd) -> UnixSeqpacket {
+        UnixSeqpacket {
+            fd,
+        }
+    }
+}
+
+impl From<UnixSeqpacket> for OwnedFd {
+    fn from(sock: UnixSeqpacket) -> OwnedFd {
+        sock.fd
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Seqpacket Listener
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct SeqpacketListener {
+    fd: OwnedFd,
+    bound: BoundPath,
+    shutdown: OnceLock<ListenerShutdown>,
+    mode: ListenerMode,
+}
+
+impl SeqpacketListener {
+    pub fn bind<A: ToUnixAddr>(addr: A) -> Result<SeqpacketListener> {
+        SeqpacketListener::bind_with(&addr.to_unix_addr()?, false)
+    }
+
+    /// See `UnixListener::bind_atomic()`.
+    pub fn bind_atomic<P, F>(path: P, configure: F)
+                             -> Result<SeqpacketListener>
+        where P: AsRef<Path>,
+              F: FnOnce(&SeqpacketListener) -> Result<()>
+    {
+        let path = path.as_ref();
+        let mut listener = publish(path, SeqpacketListener::bind_addr,
+                                   configure)?;
+        listener.bound.publish(path)?;
+        Ok(listener)
+    }
+
+    /// See `UnixListener::bind_with_permissions()`.
+    pub fn bind_with_permissions<P: AsRef<Path>>(path: P, mode: u32)
+                                                 -> Result<SeqpacketListener> {
+        let path = path.as_ref();
+        let mut listener = publish(path, |addr| {
+            let listener = SeqpacketListener {
+                fd: bind(addr, libc::SOCK_SEQPACKET, false)?,
+                bound: BoundPath::new(addr),
+                shutdown: OnceLock::new(),
+                mode: ListenerMode::default(),
+            };
+            set_mode(addr, mode)?;
+            listen(listener.as_raw_fd(), 128)?;
+            Ok(listener)
+        }, |_| Ok(()))?;
+        listener.bound.publish(path)?;
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_at()`.
+    #[cfg(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd"))]
+    pub fn bind_at<D: AsFd, N: AsRef<Path>>(dir: &D, name: N)
+                                            -> Result<SeqpacketListener> {
+        let listener = SeqpacketListener {
+            fd: bind_at(dir.as_fd().as_raw_fd(), name.as_ref(),
+                        libc::SOCK_SEQPACKET)?,
+            bound: BoundPath::default(),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        };
+        listen(listener.as_raw_fd(), 128)?;
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_in_tempdir()`.
+    pub fn bind_in_tempdir() -> Result<SeqpacketListener> {
+        let (mut listener, dir) =
+            bind_in_tempdir(SeqpacketListener::bind_addr)?;
+        listener.bound.dir = Some(dir);
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_reusing()`.
+    pub fn bind_reusing<A: ToUnixAddr>(addr: A) -> Result<SeqpacketListener> {
+        let addr = addr.to_unix_addr()?;
+        match SeqpacketListener::bind_with(&addr, false) {
+            Err(ref err) if err.kind() == ErrorKind::AddrInUse => {
+                reclaim_stale(&addr, libc::SOCK_SEQPACKET)?;
+                SeqpacketListener::bind_with(&addr, false)
+            }
+            result => result,
+        }
+    }
+
+    /// Binds to `name` in the abstract namespace. See
+    /// `SocketAddr::from_abstract_name()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract(name: &[u8]) -> Result<SeqpacketListener> {
+        let addr = SocketAddr::from_abstract_name(name)?;
+        SeqpacketListener::bind_with(&addr, false)
+    }
+
+    /// Binds to a unique abstract name chosen by the kernel. See
+    /// `UnixDatagram::autobind()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn autobind() -> Result<SeqpacketListener> {
+        SeqpacketListener::bind_with(&SocketAddr::autobind(), false)
+    }
+
+    /// Like `bind()`, but the socket is created in non-blocking mode.
+    pub fn bind_nonblocking<A: ToUnixAddr>(addr: A)
+                                           -> Result<SeqpacketListener> {
+        SeqpacketListener::bind_with(&addr.to_unix_addr()?, true)
+    }
+
+    /// See `UnixDatagram::bind_addr()`.
+    pub fn bind_addr(addr: &SocketAddr) -> Result<SeqpacketListener> {
+        SeqpacketListener::bind_with(addr, false)
+    }
+
+    /// See `UnixDatagram::bind_uri()`.
+    pub fn bind_uri(uri: &str) -> Result<SeqpacketListener> {
+        SeqpacketListener::bind_with(&SocketAddr::from_uri(uri)?, false)
+    }
+
+    /// See `UnixDatagram::bind_long_path()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_long_path<P: AsRef<Path>>(path: P)
+                                          -> Result<SeqpacketListener> {
+        let path = path.as_ref();
+        let mut listener = with_long_path(path, SeqpacketListener::bind_addr)?;
+        listener.bound.retarget(path);
+        Ok(listener)
+    }
+
+    fn bind_with(addr: &SocketAddr, nonblocking: bool)
+                 -> Result<SeqpacketListener> {
+        let listener = SeqpacketListener {
+            fd: bind(addr, libc::SOCK_SEQPACKET, nonblocking)?,
+            bound: BoundPath::new(addr),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        };
+        listen(listener.as_raw_fd(), 128)?;
+        Ok(listener)
+    }
+
+    /// Closes the socket, reporting any error from `close(2)`.
+    pub fn close(mut self) -> Result<()> {
+        self.bound.unlink();
+        close(self.into_raw_fd())
+    }
+
+    /// See `UnixDatagram::set_cleanup()`.
+    pub fn set_cleanup(&mut self, cleanup: bool) {
+        self.bound.cleanup = cleanup;
+    }
+
+    /// See `UnixDatagram::forget_path()`.
+    pub fn forget_path(&mut self) -> Option<PathBuf> {
+        self.bound.path.take()
+    }
+
+    /// See `UnixDatagram::set_socket_owner()`.
+    pub fn set_socket_owner(&self, uid: Option<libc::uid_t>,
+                            gid: Option<libc::gid_t>) -> Result<()> {
+        set_owner(self.as_raw_fd(), &self.bound, uid, gid)
+    }
+
+    /// Changes the length of the queue of pending connections. `bind()`
+    /// starts listening with a backlog of 128.
+    pub fn listen(&self, backlog: i32) -> Result<()> {
+        listen(self.as_raw_fd(), backlog as libc::c_int)
+    }
+
+    /// Accepts a new connection, returning it along with the peer's
+    /// address. See `UnixListener::accept()` for sharing a listener
+    /// between workers.
+    pub fn accept(&self) -> Result<(UnixSeqpacket, SocketAddr)> {
+        let fd = self.as_raw_fd();
+        let (fd, addr) = match self.shutdown.get() {
+            Some(shutdown) => {
+                let nonblocking = self.mode.poll_mode(fd)?;
+                shutdown.accept(fd, nonblocking, || accept_polled(fd))?
+            }
+            None => self.mode.accept(fd)?,
+        };
+        Ok((UnixSeqpacket { fd }, addr))
+    }
+
+    /// Creates a new handle to the same socket.
+    pub fn try_clone(&self) -> Result<SeqpacketListener> {
+        Ok(SeqpacketListener {
+            fd: duplicate(self.as_fd())?,
+            bound: self.bound.for_clone(),
+            shutdown: self.shutdown.clone(),
+            mode: self.mode.clone(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.bound.local_addr(self.as_raw_fd())
+    }
+
+    /// Moves the socket into or out of non-blocking mode. In non-blocking
+    /// mode, `accept()` fails with `ErrorKind::WouldBlock` when there are no
+    /// pending connections.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.mode.set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    /// See `UnixDatagram::nonblocking()`.
+    pub fn nonblocking(&self) -> Result<bool> {
+        self.mode.nonblocking(self.as_raw_fd())
+    }
+
+    /// Blocks until a connection is ready to accept or `timeout` passes,
+    /// returning whether one is. See `UnixDatagram::wait_readable()`.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLIN, timeout).map(|ev| ev != 0)
+    }
+
+    /// Like `accept()`, but returns early if `cancel` is cancelled. See
+    /// `UnixDatagram::recv_from_cancellable()`. Other threads and processes
+    /// may accept from the same listener: one that takes the connection
+    /// this call was woken for leaves it waiting for the next. As with
+    /// `UnixListener::shutdown_handle()`, this leaves the socket
+    /// non-blocking.
+    pub fn accept_cancellable(&self, cancel: &CancelHandle)
+                              -> Result<(UnixSeqpacket, SocketAddr)> {
+        let fd = self.as_raw_fd();
+        self.mode.poll_mode(fd)?;
+        let (fd, addr) = cancel::retry_until_cancelled(fd, cancel, || {
+            accept_polled(fd)
+        })?;
+        Ok((UnixSeqpacket { fd }, addr))
+    }
+
+    /// See `UnixListener::shutdown_handle()`.
+    pub fn shutdown_handle(&self) -> Result<ListenerShutdown> {
+        if let Some(shutdown) = self.shutdown.get() {
+            return Ok(shutdown.clone());
+        }
+        let shutdown = ListenerShutdown::new()?;
+        Ok(self.shutdown.get_or_init(|| shutdown).clone())
+    }
+}
+
+impl AsRawFd for SeqpacketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for SeqpacketListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl FromRawFd for SeqpacketListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> SeqpacketListener {
+        SeqpacketListener::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for SeqpacketListener {
+    fn into_raw_fd(mut self) -> RawFd {
+        self.bound.path = None;
+        self.fd.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for SeqpacketListener {
+    fn from(fd: OwnedFd) -> SeqpacketListener {
+        SeqpacketListener {
+            fd,
+            bound: BoundPath::default(),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        }
+    }
+}
+
+impl From<SeqpacketListener> for OwnedFd {
+    fn from(mut sock: SeqpacketListener) -> OwnedFd {
+        sock.bound.path = None;
+        sock.fd
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unix Listener
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UnixListener {
+    fd: OwnedFd,
+    bound: BoundPath,
+    shutdown: OnceLock<ListenerShutdown>,
+    mode: ListenerMode,
+}
+
+impl UnixListener {
+    pub fn bind<A: ToUnixAddr>(addr: A) -> Result<UnixListener> {
+        UnixListener::bind_with(&addr.to_unix_addr()?, false)
+    }
+
+    /// Binds and listens at `path` without clients ever seeing a socket
+    /// that isn't ready. The listener is bound at a unique temporary name
+    /// beside `path`, `configure` is called to finish setting it up, such
+    /// as by changing the file's permissions, and the file is then moved to
+    /// `path`. As with `bind()`, this fails with `ErrorKind::AddrInUse` if
+    /// something is already at `path`, which is never replaced.
+    ///
+    /// `local_addr()` reports `path`, but the kernel keeps the name the
+    /// socket was bound with, so clients see the temporary name in
+    /// `peer_addr()`.
+    pub fn bind_atomic<P, F>(path: P, configure: F) -> Result<UnixListener>
+        where P: AsRef<Path>,
+              F: FnOnce(&UnixListener) -> Result<()>
+    {
+        let path = path.as_ref();
+        let mut listener = publish(path, UnixListener::bind_addr, configure)?;
+        listener.bound.publish(path)?;
+        Ok(listener)
+    }
+
+    /// Binds and listens at `path`, with the socket file's permissions set
+    /// to `mode`, such as `0o660`, rather than by the umask. The mode is
+    /// applied before the socket is listening and before it is moved into
+    /// place as by `bind_atomic()`, so no client can connect while it has
+    /// other permissions. As with `bind()`, this fails with
+    /// `ErrorKind::AddrInUse` if something is already at `path`, which is
+    /// never replaced. `local_addr()` reports `path`; see `bind_atomic()`
+    /// for what clients see.
+    pub fn bind_with_permissions<P: AsRef<Path>>(path: P, mode: u32)
+                                                 -> Result<UnixListener> {
+        let path = path.as_ref();
+        let mut listener = publish(path, |addr| {
+            let listener = UnixListener {
+                fd: bind(addr, libc::SOCK_STREAM, false)?,
+                bound: BoundPath::new(addr),
+                shutdown: OnceLock::new(),
+                mode: ListenerMode::default(),
+            };
+            set_mode(addr, mode)?;
+            listen(listener.as_raw_fd(), 128)?;
+            Ok(listener)
+        }, |_| Ok(()))?;
+        listener.bound.publish(path)?;
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_at()`.
+    #[cfg(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd"))]
+    pub fn bind_at<D: AsFd, N: AsRef<Path>>(dir: &D, name: N)
+                                            -> Result<UnixListener> {
+        let listener = UnixListener {
+            fd: bind_at(dir.as_fd().as_raw_fd(), name.as_ref(),
+                        libc::SOCK_STREAM)?,
+            bound: BoundPath::default(),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        };
+        listen(listener.as_raw_fd(), 128)?;
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_in_tempdir()`.
+    pub fn bind_in_tempdir() -> Result<UnixListener> {
+        let (mut listener, dir) = bind_in_tempdir(UnixListener::bind_addr)?;
+        listener.bound.dir = Some(dir);
+        Ok(listener)
+    }
+
+    /// See `UnixDatagram::bind_reusing()`.
+    pub fn bind_reusing<A: ToUnixAddr>(addr: A) -> Result<UnixListener> {
+        let addr = addr.to_unix_addr()?;
+        match UnixListener::bind_with(&addr, false) {
+            Err(ref err) if err.kind() == ErrorKind::AddrInUse => {
+                reclaim_stale(&addr, libc::SOCK_STREAM)?;
+                UnixListener::bind_with(&addr, false)
+            }
+            result => result,
+        }
+    }
+
+    /// Binds to `name` in the abstract namespace. See
+    /// `SocketAddr::from_abstract_name()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract(name: &[u8]) -> Result<UnixListener> {
+        UnixListener::bind_with(&SocketAddr::from_abstract_name(name)?, false)
+    }
+
+    /// Binds to a unique abstract name chosen by the kernel. See
+    /// `UnixDatagram::autobind()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn autobind() -> Result<UnixListener> {
+        UnixListener::bind_with(&SocketAddr::autobind(), false)
+    }
+
+    /// Like `bind()`, but the socket is created in non-blocking mode.
+    pub fn bind_nonblocking<A: ToUnixAddr>(addr: A)
+                                           -> Result<UnixListener> {
+        UnixListener::bind_with(&addr.to_unix_addr()?, true)
+    }
+
+    /// See `UnixDatagram::bind_addr()`.
+    pub fn bind_addr(addr: &SocketAddr) -> Result<UnixListener> {
+        UnixListener::bind_with(addr, false)
+    }
+
+    /// See `UnixDatagram::bind_uri()`.
+    pub fn bind_uri(uri: &str) -> Result<UnixListener> {
+        UnixListener::bind_with(&SocketAddr::from_uri(uri)?, false)
+    }
+
+    /// See `UnixDatagram::bind_long_path()`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_long_path<P: AsRef<Path>>(path: P)
+                                          -> Result<UnixListener> {
+        let path = path.as_ref();
+        let mut listener = with_long_path(path, UnixListener::bind_addr)?;
+        listener.bound.retarget(path);
+        Ok(listener)
+    }
+
+    fn bind_with(addr: &SocketAddr, nonblocking: bool) -> Result<UnixListener> {
+        let listener = UnixListener {
+            fd: bind(addr, libc::SOCK_STREAM, nonblocking)?,
+            bound: BoundPath::new(addr),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        };
+        listen(listener.as_raw_fd(), 128)?;
+        Ok(listener)
+    }
+
+    /// Closes the socket, reporting any error from `close(2)`.
+    pub fn close(mut self) -> Result<()> {
+        self.bound.unlink();
+        close(self.into_raw_fd())
+    }
+
+    /// See `UnixDatagram::set_cleanup()`.
+    pub fn set_cleanup(&mut self, cleanup: bool) {
+        self.bound.cleanup = cleanup;
+    }
+
+    /// See `UnixDatagram::forget_path()`.
+    pub fn forget_path(&mut self) -> Option<PathBuf> {
+        self.bound.path.take()
+    }
+
+    /// See `UnixDatagram::set_socket_owner()`.
+    pub fn set_socket_owner(&self, uid: Option<libc::uid_t>,
+                            gid: Option<libc::gid_t>) -> Result<()> {
+        set_owner(self.as_raw_fd(), &self.bound, uid, gid)
+    }
+
+    /// Accepts a new connection, returning it along with the peer's
+    /// address.
+    ///
+    /// Several threads or processes may accept from the same listener, as
+    /// with workers forked after binding it, or given it with
+    /// `handoff::share_listener()`. Each connection goes to only one of
+    /// them. Blocked in `accept()`, they take turns and only one is woken
+    /// for each connection. Workers that instead wait for the listener to
+    /// become readable, with `wait_readable()` or a poller, may all be
+    /// woken by one connection, so they should put the listener in
+    /// non-blocking mode and go back to waiting on
+    /// `ErrorKind::WouldBlock`.
+    pub fn accept(&self) -> Result<(UnixStream, SocketAddr)> {
+        let fd = self.as_raw_fd();
+        let (fd, addr) = match self.shutdown.get() {
+            Some(shutdown) => {
+                let nonblocking = self.mode.poll_mode(fd)?;
+                shutdown.accept(fd, nonblocking, || accept_polled(fd))?
+            }
+            None => self.mode.accept(fd)?,
+        };
+        Ok((UnixStream { fd }, addr))
+    }
+
+    /// Returns an iterator over incoming connections. The iterator only
+    /// returns `None` once the listener has been shut down.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+        }
+    }
+
+    /// Creates a new handle to the same socket.
+    pub fn try_clone(&self) -> Result<UnixListener> {
+        Ok(UnixListener {
+            fd: duplicate(self.as_fd())?,
+            bound: self.bound.for_clone(),
+            shutdown: self.shutdown.clone(),
+            mode: self.mode.clone(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.bound.local_addr(self.as_raw_fd())
+    }
+
+    /// Moves the socket into or out of non-blocking mode. In non-blocking
+    /// mode, `accept()` fails with `ErrorKind::WouldBlock` when there are no
+    /// pending connections.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.mode.set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        take_error(self.as_raw_fd())
+    }
+
+    /// See `UnixDatagram::nonblocking()`.
+    pub fn nonblocking(&self) -> Result<bool> {
+        self.mode.nonblocking(self.as_raw_fd())
+    }
+
+    /// Blocks until a connection is ready to accept or `timeout` passes,
+    /// returning whether one is. See `UnixDatagram::wait_readable()`.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool> {
+        poll(self.as_raw_fd(), libc::POLLIN, timeout).map(|ev| ev != 0)
+    }
+
+    /// Like `accept()`, but returns early if `cancel` is cancelled. See
+    /// `UnixDatagram::recv_from_cancellable()`. Other threads and processes
+    /// may accept from the same listener: one that takes the connection
+    /// this call was woken for leaves it waiting for the next. As with
+    /// `shutdown_handle()`, this leaves the socket non-blocking.
+    pub fn accept_cancellable(&self, cancel: &CancelHandle)
+                              -> Result<(UnixStream, SocketAddr)> {
+        let fd = self.as_raw_fd();
+        self.mode.poll_mode(fd)?;
+        let (fd, addr) = cancel::retry_until_cancelled(fd, cancel, || {
+            accept_polled(fd)
+        })?;
+        Ok((UnixStream { fd }, addr))
+    }
+
+    /// Returns a handle for shutting the listener down from another
+    /// thread, which wakes `accept()` calls blocked on it. Handles are
+    /// shared with clones made by `try_clone()`. Take the handle before
+    /// starting to accept: calls already blocked when the first handle is
+    /// created aren't woken.
+    ///
+    /// Accepts on a listener with a handle wait for connections in
+    /// `poll(2)`, and the first one leaves the socket itself non-blocking
+    /// for good, so that a connection another worker takes first can't
+    /// leave it blocked. This listener and its clones still behave as
+    /// `set_nonblocking()` last asked, but other processes sharing the
+    /// socket, such as forked workers, see it as non-blocking. Listeners
+    /// passed on with the `handoff` functions keep their mode.
+    pub fn shutdown_handle(&self) -> Result<ListenerShutdown> {
+        if let Some(shutdown) = self.shutdown.get() {
+            return Ok(shutdown.clone());
+        }
+        let shutdown = ListenerShutdown::new()?;
+        Ok(self.shutdown.get_or_init(|| shutdown).clone())
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown.get().is_some_and(ListenerShutdown::is_shutdown)
+    }
+}
+
+impl AsRawFd for UnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for UnixListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl FromRawFd for UnixListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> UnixListener {
+        UnixListener::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl IntoRawFd for UnixListener {
+    fn into_raw_fd(mut self) -> RawFd {
+        self.bound.path = None;
+        self.fd.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for UnixListener {
+    fn from(fd: OwnedFd) -> UnixListener {
+        UnixListener {
+            fd,
+            bound: BoundPath::default(),
+            shutdown: OnceLock::new(),
+            mode: ListenerMode::default(),
+        }
+    }
+}
+
+impl From<UnixListener> for OwnedFd {
+    fn from(mut sock: UnixListener) -> OwnedFd {
+        sock.bound.path = None;
+        sock.fd
+    }
+}
+
+impl<'a> IntoIterator for &'a UnixListener {
+    type Item = Result<UnixStream>;
+    type IntoIter = Incoming<'a>;
+
+    fn into_iter(self) -> Incoming<'a> {
+        self.incoming()
+    }
+}
+
+/// An iterator over the connections accepted by a `UnixListener`.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a UnixListener,
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = Result<UnixStream>;
+
+    fn next(&mut self) -> Option<Result<UnixStream>> {
+        match self.listener.accept() {
+            Err(_) if self.listener.is_shut_down() => None,
+            res => Some(res.map(|(stream, _)| stream)),
+        }
+    }
+}
+
+/// A connection started by `start_connect()` that may not be established
+/// yet. Register it with a reactor for writability, then call
+/// `finish_connect()`.
+#[derive(Debug)]
+pub struct PendingConnect<S> {
+    sock: S,
+    addr: SocketAddr,
+    state: ConnectState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectState {
+    Connected,
+    // connect(2) returned EINPROGRESS; SO_ERROR gives the outcome
+    InProgress,
+    // The listener's backlog was full, so connect(2) must be retried
+    Retry,
+}
+
+// Attempts a connection on the non-blocking `fd`.
+fn try_connect(fd: RawFd, addr: &SocketAddr) -> Result<ConnectState> {
+    match connect_fd(fd, addr) {
+        Ok(()) => Ok(ConnectState::Connected),
+        Err(err) => match err.raw_os_error() {
+            Some(libc::EISCONN) => Ok(ConnectState::Connected),
+            Some(libc::EINPROGRESS) | Some(libc::EALREADY) => {
+                Ok(ConnectState::InProgress)
+            }
+            Some(libc::EAGAIN) => Ok(ConnectState::Retry),
+            _ => Err(err),
+        },
+    }
+}
+
+impl<S: From<OwnedFd> + AsRawFd> PendingConnect<S> {
+    fn start<A: ToUnixAddr>(addr: A, ty: libc::c_int)
+                            -> Result<PendingConnect<S>> {
+        let addr = addr.to_unix_addr()?;
+        let fd = unix_socket(ty, true)?;
+        let state = try_connect(fd.as_raw_fd(), &addr)?;
+        Ok(PendingConnect {
+            sock: S::from(fd),
+            addr,
+            state,
+        })
+    }
+
+    /// Completes the connection, returning the connected socket, which is
+    /// left in non-blocking mode. While the connection is still in
+    /// progress this fails with `ErrorKind::WouldBlock` and gives the
+    /// handle back, so it can be waited on again.
+    ///
+    /// If the listener's backlog was full, the socket may be reported
+    /// writable before there is room; each call then retries the
+    /// connection.
+    pub fn finish_connect(mut self) -> std::result::Result<S, (Self, Error)> {
+        match self.poll_connect() {
+            Ok(ConnectState::Connected) => Ok(self.sock),
+            Ok(state) => {
+                self.state = state;
+                let err = Error::new(ErrorKind::WouldBlock,
+                                     "connection in progress");
+                Err((self, err))
+            }
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    fn poll_connect(&self) -> Result<ConnectState> {
+        let fd = self.sock.as_raw_fd();
+        match self.state {
+            ConnectState::Connected => Ok(ConnectState::Connected),
+            ConnectState::InProgress => {
+                if poll(fd, libc::POLLOUT, Some(Duration::from_secs(0)))? == 0 {
+                    return Ok(ConnectState::InProgress);
+                }
+                match take_error(fd)? {
+                    Some(err) => Err(err),
+                    None => Ok(ConnectState::Connected),
+                }
+            }
+            ConnectState::Retry => try_connect(fd, &self.addr),
+        }
+    }
+
+    /// The socket being connected.
+    pub fn get_ref(&self) -> &S {
+        &self.sock
+    }
+}
+
+impl<S: AsRawFd> AsRawFd for PendingConnect<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl<S: AsFd> AsFd for PendingConnect<S> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.sock.as_fd()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Socket Passing
+////////////////////////////////////////////////////////////////////////////////
+
+/// Socket types that can be rebuilt from a descriptor of unknown origin,
+/// such as one received with `recv_socket()`.
+pub trait FromSocketFd: Sized {
+    /// Wraps `fd`, after checking that it is an `AF_UNIX` socket of the
+    /// right type for `Self`.
+    fn from_socket_fd(fd: OwnedFd) -> Result<Self>;
+}
+
+fn recv_socket<S: FromSocketFd>(fd: RawFd, buf: &mut [u8])
+                                -> Result<(usize, S)> {
+    let mut fds = Vec::new();
+    let n = msg::recv_fds(fd, buf, &mut fds, true)?;
+    // any extra descriptors are closed as `fds` is dropped
+    if fds.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              "no socket was received"));
+    }
+    Ok((n, S::from_socket_fd(fds.swap_remove(0))?))
+}
+
+fn check_socket(fd: RawFd, ty: libc::c_int, listening: bool) -> Result<()> {
+    if local_addr(fd).is_err() {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "descriptor is not an AF_UNIX socket"));
+    }
+
+    let actual: libc::c_int = getsockopt(fd, libc::SOL_SOCKET, libc::SO_TYPE)?;
+    if actual != ty {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "socket has the wrong type"));
+    }
+
+    let accepting: libc::c_int =
+        getsockopt(fd, libc::SOL_SOCKET, libc::SO_ACCEPTCONN)?;
+    if (accepting != 0) != listening {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              if listening {
+                                  "socket is not listening"
+                              } else {
+                                  "socket is a listener"
+                              }));
+    }
+
+    Ok(())
+}
+
+impl FromSocketFd for UnixDatagram {
+    fn from_socket_fd(fd: OwnedFd) -> Result<UnixDatagram> {
+        check_socket(fd.as_raw_fd(), libc::SOCK_DGRAM, false)?;
+        Ok(UnixDatagram::from(fd))
+    }
+}
+
+impl FromSocketFd for UnixStream {
+    fn from_socket_fd(fd: OwnedFd) -> Result<UnixStream> {
+        check_socket(fd.as_raw_fd(), libc::SOCK_STREAM, false)?;
+        Ok(UnixStream::from(fd))
+    }
+}
+
+impl FromSocketFd for UnixSeqpacket {
+    fn from_socket_fd(fd: OwnedFd) -> Result<UnixSeqpacket> {
+        check_socket(fd.as_raw_fd(), libc::SOCK_SEQPACKET, false)?;
+        Ok(UnixSeqpacket::from(fd))
+    }
+}
+
+impl FromSocketFd for SeqpacketListener {
+    fn from_socket_fd(fd: OwnedFd) -> Result<SeqpacketListener> {
+        check_socket(fd.as_raw_fd(), libc::SOCK_SEQPACKET, true)?;
+        Ok(SeqpacketListener::from(fd))
+    }
+}
+
+impl FromSocketFd for UnixListener {
+    fn from_socket_fd(fd: OwnedFd) -> Result<UnixListener> {
+        check_socket(fd.as_raw_fd(), libc::SOCK_STREAM, true)?;
+        Ok(UnixListener::from(fd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::path::Path;
+
+    use super::{percent_decode, SocketAddr};
+
+    #[test]
+    fn percent_decode_valid() {
+        assert_eq!(percent_decode("").unwrap(), b"");
+        assert_eq!(percent_decode("plain").unwrap(), b"plain");
+        assert_eq!(percent_decode("a%20b").unwrap(), b"a b");
+        assert_eq!(percent_decode("%2f%2F").unwrap(), b"//");
+        assert_eq!(percent_decode("%ff%00").unwrap(), b"\xff\x00");
+        assert_eq!(percent_decode("%2520").unwrap(), b"%20");
+    }
+
+    #[test]
+    fn percent_decode_invalid() {
+        // truncated, non-hex, signed and multibyte escapes
+        for s in &["%", "%2", "a%", "%zz", "%2g", "%+1", "%-1", "%\u{e9}",
+                   "%2\u{e9}"] {
+            let err = percent_decode(s).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn from_uri_path() {
+        let addr = SocketAddr::from_uri("unix:///run/app.sock").unwrap();
+        assert_eq!(addr.as_pathname(), Some(Path::new("/run/app.sock")));
+
+        let addr = SocketAddr::from_uri("UNIX:///run/my%20app.sock").unwrap();
+        assert_eq!(addr.as_pathname(), Some(Path::new("/run/my app.sock")));
+
+        let addr = SocketAddr::from_uri("unix://relative.sock").unwrap();
+        assert_eq!(addr.as_pathname(), Some(Path::new("relative.sock")));
+    }
+
+    #[test]
+    fn from_uri_invalid() {
+        for uri in &["", "unix:", "unix:/run/app.sock", "unix://",
+                     "http:///run/app.sock", "unix:///run/%zz",
+                     "unix:///run/app%2", "unix:///run/a%00b"] {
+            let err = SocketAddr::from_uri(uri).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{:?}", uri);
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn from_uri_abstract() {
+        let addr = SocketAddr::from_uri("unix://@app").unwrap();
+        assert_eq!(addr.as_abstract_name(), Some(&b"app"[..]));
+
+        // an abstract name may hold any byte, null included
+        let addr = SocketAddr::from_uri("unix://@a%00b%40").unwrap();
+        assert_eq!(addr.as_abstract_name(), Some(&b"a\0b@"[..]));
+
+        let err = SocketAddr::from_uri("unix://@%g0").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn from_uri_round_trip() {
+        let path = Path::new("/tmp/a b%c\u{e9}.sock");
+        let mut uri = String::from("unix://");
+        for &b in path.to_str().unwrap().as_bytes() {
+            if b.is_ascii_alphanumeric() || b"/._".contains(&b) {
+                uri.push(b as char);
+            } else {
+                uri.push_str(&format!("%{:02X}", b));
+            }
         }
+        let addr = SocketAddr::from_uri(&uri).unwrap();
+        assert_eq!(addr.as_pathname(), Some(path));
+        assert_eq!(SocketAddr::from_pathname(path).unwrap().to_string(),
+                   addr.to_string());
     }
 }
@@ -0,0 +1,62 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! `mio::event::Source` for the socket types, enabled with the `mio`
+//! feature, so they can be registered with a `mio::Poll` directly.
+
+use std::io::Result;
+use std::os::unix::io::AsRawFd;
+
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+
+use super::{SeqpacketListener, UnixDatagram, UnixListener, UnixSeqpacket,
+            UnixStream};
+
+macro_rules! impl_source {
+    ($ty:ty) => {
+        impl Source for $ty {
+            fn register(&mut self, registry: &Registry, token: Token,
+                        interests: Interest) -> Result<()> {
+                SourceFd(&self.as_raw_fd()).register(registry, token,
+                                                     interests)
+            }
+
+            fn reregister(&mut self, registry: &Registry, token: Token,
+                          interests: Interest) -> Result<()> {
+                SourceFd(&self.as_raw_fd()).reregister(registry, token,
+                                                       interests)
+            }
+
+            fn deregister(&mut self, registry: &Registry) -> Result<()> {
+                SourceFd(&self.as_raw_fd()).deregister(registry)
+            }
+        }
+    }
+}
+
+impl_source!(UnixDatagram);
+impl_source!(UnixStream);
+impl_source!(UnixSeqpacket);
+impl_source!(SeqpacketListener);
+impl_source!(UnixListener);
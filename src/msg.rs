@@ -0,0 +1,535 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Message-level sends and receives, using `sendmsg(2)` and `recvmsg(2)`.
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ptr;
+use std::os::unix::io::{BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::time::SystemTime;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::time::Duration;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Result};
+
+use libc;
+
+use super::{last_error, retry, SocketAddr, MAX_IOV, MSG_NOSIGNAL};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::poll;
+use ancillary::{ControlMessages, SocketAncillary};
+
+// The most descriptors Linux will pass in one message (SCM_MAX_FD)
+const MAX_FDS: usize = 253;
+
+/// Flags passed to message-level sends and receives, or returned by them.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MsgFlags(libc::c_int);
+
+impl MsgFlags {
+    /// Return data without removing it from the receive queue.
+    pub const PEEK: MsgFlags = MsgFlags(libc::MSG_PEEK);
+    /// Don't block, even if the socket is in blocking mode.
+    pub const DONTWAIT: MsgFlags = MsgFlags(libc::MSG_DONTWAIT);
+    /// Block until the full request is satisfied (stream sockets).
+    pub const WAITALL: MsgFlags = MsgFlags(libc::MSG_WAITALL);
+    /// As an input flag, return the real length of the message even if it
+    /// was truncated. As an output flag, the message was truncated.
+    pub const TRUNC: MsgFlags = MsgFlags(libc::MSG_TRUNC);
+    /// Control data was discarded for lack of space in the control buffer.
+    pub const CTRUNC: MsgFlags = MsgFlags(libc::MSG_CTRUNC);
+    /// End of record, for sockets that support records.
+    pub const EOR: MsgFlags = MsgFlags(libc::MSG_EOR);
+    /// Set close-on-exec on descriptors received in `SCM_RIGHTS` messages.
+    /// The receive functions add this themselves; their `_inheritable()`
+    /// variants leave it out.
+    #[cfg(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd"))]
+    pub const CMSG_CLOEXEC: MsgFlags = MsgFlags(libc::MSG_CMSG_CLOEXEC);
+
+    pub fn empty() -> MsgFlags {
+        MsgFlags(0)
+    }
+
+    /// Creates flags from raw `MSG_*` bits, keeping any bits this type
+    /// doesn't name.
+    pub fn from_bits(bits: libc::c_int) -> MsgFlags {
+        MsgFlags(bits)
+    }
+
+    pub fn bits(&self) -> libc::c_int {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, other: MsgFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MsgFlags {
+    type Output = MsgFlags;
+
+    fn bitor(self, other: MsgFlags) -> MsgFlags {
+        MsgFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for MsgFlags {
+    fn bitor_assign(&mut self, other: MsgFlags) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for MsgFlags {
+    type Output = MsgFlags;
+
+    fn bitand(self, other: MsgFlags) -> MsgFlags {
+        MsgFlags(self.0 & other.0)
+    }
+}
+
+impl fmt::Debug for MsgFlags {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (MsgFlags::PEEK, "PEEK"),
+            (MsgFlags::DONTWAIT, "DONTWAIT"),
+            (MsgFlags::WAITALL, "WAITALL"),
+            (MsgFlags::TRUNC, "TRUNC"),
+            (MsgFlags::CTRUNC, "CTRUNC"),
+            (MsgFlags::EOR, "EOR"),
+            #[cfg(any(target_os = "linux", target_os = "android",
+                      target_os = "freebsd", target_os = "dragonfly",
+                      target_os = "netbsd", target_os = "openbsd"))]
+            (MsgFlags::CMSG_CLOEXEC, "CMSG_CLOEXEC"),
+        ];
+
+        let mut rest = self.0;
+        let mut first = true;
+        write!(fmt, "MsgFlags(")?;
+        for &(flag, name) in names.iter() {
+            if rest & flag.0 == flag.0 {
+                write!(fmt, "{}{}", if first { "" } else { " | " }, name)?;
+                rest &= !flag.0;
+                first = false;
+            }
+        }
+        if rest != 0 {
+            write!(fmt, "{}{:#x}", if first { "" } else { " | " }, rest)?;
+        }
+        write!(fmt, ")")
+    }
+}
+
+/// The error returned when a receive's control buffer was too small and the
+/// kernel discarded control messages (`MSG_CTRUNC`). Any descriptors that
+/// did arrive have been closed; the data itself was still consumed.
+///
+/// It is wrapped in an `io::Error` of kind `InvalidData`, and can be
+/// recovered with `get_ref()` and `downcast_ref()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlTruncated {
+    bytes: usize,
+}
+
+impl ControlTruncated {
+    /// The number of bytes of data received with the truncated control
+    /// messages.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl fmt::Display for ControlTruncated {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "control messages were truncated")
+    }
+}
+
+impl error::Error for ControlTruncated {}
+
+/// The error returned when a datagram or packet was larger than the receive
+/// buffer. The buffer holds the start of the message, and the rest is lost.
+///
+/// It is wrapped in an `io::Error` of kind `InvalidData`, and can be
+/// recovered with `get_ref()` and `downcast_ref()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageTruncated {
+    received: usize,
+    len: Option<usize>,
+}
+
+impl MessageTruncated {
+    pub(crate) fn new(received: usize, len: Option<usize>) -> MessageTruncated {
+        MessageTruncated { received, len }
+    }
+
+    /// The number of bytes placed in the buffer.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// The full length of the message. Only Linux and Android report this.
+    pub fn message_len(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+impl fmt::Display for MessageTruncated {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.len {
+            Some(len) => write!(fmt, "message truncated to {} of {} bytes",
+                                self.received, len),
+            None => write!(fmt, "message truncated to {} bytes",
+                           self.received),
+        }
+    }
+}
+
+impl error::Error for MessageTruncated {}
+
+/// A message received by `recv_msg()`.
+#[derive(Debug)]
+pub struct RecvMsg<'a> {
+    bytes: usize,
+    addr: SocketAddr,
+    flags: MsgFlags,
+    control: ControlMessages<'a>,
+}
+
+impl<'a> RecvMsg<'a> {
+    /// The number of bytes of data received.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The address of the sender. This is unnamed for connected sockets.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// The flags the kernel returned, such as `MsgFlags::TRUNC` and
+    /// `MsgFlags::EOR`.
+    pub fn flags(&self) -> MsgFlags {
+        self.flags
+    }
+
+    /// The control messages received with the data.
+    pub fn control(&self) -> ControlMessages<'a> {
+        self.control.clone()
+    }
+
+    /// The time the kernel received the data, if the socket has
+    /// `set_timestamping()` or `set_timestamping_ns()` enabled.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.control().filter_map(|cmsg| cmsg.as_timestamp()).next()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn cloexec_flag(flags: MsgFlags, cloexec: bool) -> MsgFlags {
+    if cloexec { flags | MsgFlags::CMSG_CLOEXEC } else { flags }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn cloexec_flag(flags: MsgFlags, _cloexec: bool) -> MsgFlags {
+    flags
+}
+
+// Without MSG_CMSG_CLOEXEC there is a window between recvmsg() and here
+// where a concurrent fork can inherit the descriptors; this is the best
+// these platforms allow.
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "netbsd", target_os = "openbsd")))]
+fn set_cloexec_fallback(control: &ControlMessages) {
+    for rights in control.clone().filter_map(|cmsg| cmsg.as_rights()) {
+        for fd in rights {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "netbsd", target_os = "openbsd"))]
+fn set_cloexec_fallback(_control: &ControlMessages) {}
+
+// Closes pidfds in messages the caller won't see, so they don't leak.
+#[cfg(target_os = "linux")]
+fn close_pidfds(control: &ControlMessages) {
+    for pidfd in control.clone().filter_map(|cmsg| cmsg.as_pidfd()) {
+        drop(unsafe { OwnedFd::from_raw_fd(pidfd) });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn close_pidfds(_control: &ControlMessages) {}
+
+pub fn recv_msg<'a>(fd: RawFd, bufs: &mut [IoSliceMut], control: &'a mut [u8],
+                    flags: MsgFlags, cloexec: bool) -> Result<RecvMsg<'a>> {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+    msg.msg_iov = bufs.as_mut_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = cmp::min(bufs.len(), MAX_IOV) as _;
+    if !control.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+    }
+
+    let flags = cloexec_flag(flags, cloexec);
+    let ret = retry(|| unsafe { libc::recvmsg(fd, &mut msg, flags.bits()) });
+
+    if ret < 0 { return Err(last_error()) }
+
+    let controllen = cmp::min(msg.msg_controllen as usize, control.len());
+    let control = ControlMessages::new(&control[..controllen]);
+    if cloexec {
+        set_cloexec_fallback(&control);
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        close_pidfds(&control);
+        for rights in control.filter_map(|cmsg| cmsg.as_rights()) {
+            for fd in rights {
+                drop(unsafe { OwnedFd::from_raw_fd(fd) });
+            }
+        }
+        return Err(Error::new(ErrorKind::InvalidData,
+                              ControlTruncated { bytes: ret as usize }));
+    }
+    Ok(RecvMsg {
+        bytes: ret as usize,
+        addr: SocketAddr::from_parts(addr, msg.msg_namelen)?,
+        flags: MsgFlags(msg.msg_flags),
+        control,
+    })
+}
+
+pub fn send_msg(fd: RawFd, bufs: &[IoSlice], addr: Option<&SocketAddr>,
+                control: &[u8], flags: MsgFlags) -> Result<usize> {
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    if let Some(addr) = addr {
+        msg.msg_name = addr.as_ptr() as *mut libc::c_void;
+        msg.msg_namelen = addr.len;
+    }
+    msg.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = cmp::min(bufs.len(), MAX_IOV) as _;
+    if !control.is_empty() {
+        msg.msg_control = control.as_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+    }
+
+    let flags = flags.bits() | MSG_NOSIGNAL;
+    let ret = retry(|| unsafe { libc::sendmsg(fd, &msg, flags) });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+pub fn send_fds(fd: RawFd, fds: &[BorrowedFd], data: &[u8]) -> Result<usize> {
+    let mut buf = vec![0; SocketAncillary::space_for_fds(fds.len())];
+    let mut ancillary = SocketAncillary::new(&mut buf);
+    ancillary.add_fds(fds)?;
+    send_msg(fd, &[IoSlice::new(data)], None, ancillary.as_bytes(),
+             MsgFlags::empty())
+}
+
+pub fn recv_fds(fd: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>,
+                cloexec: bool) -> Result<usize> {
+    let mut control = vec![0; SocketAncillary::space_for_fds(MAX_FDS)];
+    let msg = recv_msg(fd, &mut [IoSliceMut::new(buf)], &mut control,
+                       MsgFlags::empty(), cloexec)?;
+    for rights in msg.control().filter_map(|cmsg| cmsg.as_rights()) {
+        fds.extend(rights.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+    }
+    close_pidfds(&msg.control());
+    Ok(msg.bytes())
+}
+
+/// A buffer for one message in a batch receive with `recv_multiple()`.
+/// After the receive, it holds the message's length, sender and flags.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct MsgBuffer<'a> {
+    buf: &'a mut [u8],
+    bytes: usize,
+    addr: Option<SocketAddr>,
+    flags: MsgFlags,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl<'a> MsgBuffer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> MsgBuffer<'a> {
+        MsgBuffer {
+            buf,
+            bytes: 0,
+            addr: None,
+            flags: MsgFlags::empty(),
+        }
+    }
+
+    /// The number of bytes received into the buffer.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The data received.
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.bytes]
+    }
+
+    /// The address of the sender, once a message has been received.
+    pub fn addr(&self) -> Option<&SocketAddr> {
+        self.addr.as_ref()
+    }
+
+    /// The flags the kernel returned. `MsgFlags::TRUNC` means the message
+    /// didn't fit in the buffer and the rest of it was discarded.
+    pub fn flags(&self) -> MsgFlags {
+        self.flags
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl<'a> fmt::Debug for MsgBuffer<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MsgBuffer")
+            .field("capacity", &self.buf.len())
+            .field("bytes", &self.bytes)
+            .field("addr", &self.addr)
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+// UIO_MAXIOV, the most messages Linux handles in one call
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAX_MMSG: usize = 1024;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn recv_multiple(fd: RawFd, msgs: &mut [MsgBuffer],
+                     timeout: Option<Duration>) -> Result<usize> {
+    let count = cmp::min(msgs.len(), MAX_MMSG);
+    let mut addrs: Vec<libc::sockaddr_un> = vec![unsafe { mem::zeroed() };
+                                                 count];
+    let mut iovs: Vec<libc::iovec> = msgs[..count].iter_mut().map(|msg| {
+        libc::iovec {
+            iov_base: msg.buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: msg.buf.len() as libc::size_t,
+        }
+    }).collect();
+    let mut hdrs: Vec<libc::mmsghdr> = (0..count).map(|i| {
+        let mut hdr: libc::mmsghdr = unsafe { mem::zeroed() };
+        hdr.msg_hdr.msg_name = &mut addrs[i] as *mut _ as *mut libc::c_void;
+        hdr.msg_hdr.msg_namelen =
+            mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+        hdr.msg_hdr.msg_iov = &mut iovs[i];
+        hdr.msg_hdr.msg_iovlen = 1;
+        hdr
+    }).collect();
+
+    if let Some(timeout) = timeout {
+        if poll(fd, libc::POLLIN, Some(timeout))? == 0 {
+            return Err(Error::new(ErrorKind::TimedOut,
+                                  "timed out waiting for a message"));
+        }
+    }
+
+    let ret = retry(|| unsafe {
+        libc::recvmmsg(fd, hdrs.as_mut_ptr(), count as libc::c_uint,
+                       libc::MSG_WAITFORONE as _, ptr::null_mut())
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    for (i, msg) in msgs[..ret as usize].iter_mut().enumerate() {
+        let hdr = &hdrs[i];
+        msg.bytes = cmp::min(hdr.msg_len as usize, msg.buf.len());
+        msg.addr = Some(SocketAddr::from_parts(addrs[i],
+                                               hdr.msg_hdr.msg_namelen)?);
+        msg.flags = MsgFlags(hdr.msg_hdr.msg_flags);
+    }
+    Ok(ret as usize)
+}
+
+/// One message in a batch send with `send_multiple()`: the data gathered
+/// from `bufs`, and the destination for unconnected sockets.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Copy, Debug)]
+pub struct OutgoingMsg<'a> {
+    bufs: &'a [IoSlice<'a>],
+    addr: Option<&'a SocketAddr>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl<'a> OutgoingMsg<'a> {
+    pub fn new(bufs: &'a [IoSlice<'a>], addr: Option<&'a SocketAddr>)
+               -> OutgoingMsg<'a> {
+        OutgoingMsg {
+            bufs,
+            addr,
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn send_multiple(fd: RawFd, msgs: &[OutgoingMsg]) -> Result<usize> {
+    let count = cmp::min(msgs.len(), MAX_MMSG);
+    let mut hdrs = Vec::with_capacity(count);
+    for msg in &msgs[..count] {
+        let mut hdr: libc::mmsghdr = unsafe { mem::zeroed() };
+        if let Some(addr) = msg.addr {
+            if addr.is_unnamed() {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      "can't send to an unnamed address"));
+            }
+            hdr.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_namelen = addr.len;
+        }
+        hdr.msg_hdr.msg_iov = msg.bufs.as_ptr() as *mut libc::iovec;
+        hdr.msg_hdr.msg_iovlen = cmp::min(msg.bufs.len(), MAX_IOV) as _;
+        hdrs.push(hdr);
+    }
+
+    let ret = retry(|| unsafe {
+        libc::sendmmsg(fd, hdrs.as_mut_ptr(), count as libc::c_uint,
+                       MSG_NOSIGNAL as _)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
@@ -0,0 +1,124 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Registration with a `polling::Poller`, enabled with the `polling`
+//! feature.
+//!
+//! The socket types implement `AsFd`, so they can already be passed to
+//! `Poller::modify()` and `Poller::delete()`, but `Poller::add()` is unsafe
+//! because the poller must not outlive the registration of a descriptor
+//! that has been closed. `Registration` owns the socket and deletes it from
+//! the poller when dropped, which makes adding it safe. It can't borrow the
+//! socket instead: a registration can be leaked with `mem::forget()`, and
+//! only if it owns the socket does that leak the descriptor too, rather
+//! than leaving it registered after it is closed.
+
+use std::fmt;
+use std::io::Result;
+use std::os::unix::io::AsFd;
+
+use polling::{Event, PollMode, Poller};
+
+use super::{SeqpacketListener, UnixDatagram, UnixListener, UnixSeqpacket};
+use super::UnixStream;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The crate's socket types, which keep the same descriptor for as long as
+/// they live.
+pub trait Source: AsFd + private::Sealed {}
+
+macro_rules! impl_source {
+    ($($ty:ty),*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl Source for $ty {}
+        )*
+    }
+}
+
+impl_source!(UnixDatagram, UnixStream, UnixSeqpacket, SeqpacketListener,
+             UnixListener);
+
+/// A socket registered with a `Poller`, removed from it again on drop.
+pub struct Registration<'p, S: Source> {
+    poller: &'p Poller,
+    sock: S,
+}
+
+impl<'p, S: Source> Registration<'p, S> {
+    /// Adds `sock` to `poller` in oneshot mode: after each event the
+    /// interest must be re-armed with `modify()`.
+    pub fn new(poller: &'p Poller, sock: S, interest: Event)
+               -> Result<Registration<'p, S>> {
+        Registration::with_mode(poller, sock, interest, PollMode::Oneshot)
+    }
+
+    /// Adds `sock` to `poller` with the given mode.
+    pub fn with_mode(poller: &'p Poller, sock: S, interest: Event,
+                     mode: PollMode) -> Result<Registration<'p, S>> {
+        // The registration is deleted before `sock` is dropped.
+        unsafe { poller.add_with_mode(&sock.as_fd(), interest, mode)? };
+        Ok(Registration {
+            poller,
+            sock,
+        })
+    }
+
+    /// Changes the interest, re-arming it in oneshot mode.
+    pub fn modify(&self, interest: Event) -> Result<()> {
+        self.poller.modify(&self.sock, interest)
+    }
+
+    pub fn modify_with_mode(&self, interest: Event, mode: PollMode)
+                            -> Result<()> {
+        self.poller.modify_with_mode(&self.sock, interest, mode)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sock
+    }
+
+    /// Removes the socket from the poller and returns it.
+    pub fn into_inner(self) -> Result<S> {
+        self.poller.delete(&self.sock)?;
+        let sock = unsafe { std::ptr::read(&self.sock) };
+        std::mem::forget(self);
+        Ok(sock)
+    }
+}
+
+impl<'p, S: Source> Drop for Registration<'p, S> {
+    fn drop(&mut self) {
+        let _ = self.poller.delete(&self.sock);
+    }
+}
+
+impl<'p, S: Source + fmt::Debug> fmt::Debug for Registration<'p, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("sock", &self.sock)
+            .finish()
+    }
+}
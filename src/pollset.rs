@@ -0,0 +1,210 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::BitOr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::slice;
+use std::time::Duration;
+
+use libc;
+
+use super::poll_fds;
+
+/// The readiness a `PollSet` waits for on a socket.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interest(libc::c_short);
+
+impl Interest {
+    /// Data, a connection, or end-of-file is ready to be received.
+    pub const READABLE: Interest = Interest(libc::POLLIN);
+    /// There is room to send.
+    pub const WRITABLE: Interest = Interest(libc::POLLOUT);
+
+    pub fn is_readable(&self) -> bool {
+        self.0 & libc::POLLIN != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.0 & libc::POLLOUT != 0
+    }
+}
+
+impl BitOr for Interest {
+    type Output = Interest;
+
+    fn bitor(self, other: Interest) -> Interest {
+        Interest(self.0 | other.0)
+    }
+}
+
+impl fmt::Debug for Interest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_readable(), self.is_writable()) {
+            (true, true) => write!(f, "READABLE | WRITABLE"),
+            (true, false) => write!(f, "READABLE"),
+            (false, true) => write!(f, "WRITABLE"),
+            (false, false) => write!(f, "(empty)"),
+        }
+    }
+}
+
+/// A set of sockets to wait on together with `poll(2)`, each identified by
+/// a caller-chosen token.
+///
+/// The set holds only the sockets' descriptors, so a socket must be removed
+/// before it is closed; otherwise the set may end up watching whatever
+/// descriptor reuses its number.
+#[derive(Debug, Default)]
+pub struct PollSet {
+    fds: Vec<libc::pollfd>,
+    tokens: Vec<usize>,
+}
+
+impl PollSet {
+    pub fn new() -> PollSet {
+        PollSet::default()
+    }
+
+    /// Adds `sock` to the set, reporting its readiness under `token`, which
+    /// must not already be in use.
+    pub fn add<S: AsRawFd>(&mut self, sock: &S, token: usize,
+                           interest: Interest) -> Result<()> {
+        if self.tokens.contains(&token) {
+            return Err(Error::new(ErrorKind::AlreadyExists,
+                                  "token is already registered"));
+        }
+
+        self.fds.push(libc::pollfd {
+            fd: sock.as_raw_fd(),
+            events: interest.0,
+            revents: 0,
+        });
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    /// Changes the readiness waited for on the socket registered as
+    /// `token`.
+    pub fn modify(&mut self, token: usize, interest: Interest) -> Result<()> {
+        let i = self.index(token)?;
+        self.fds[i].events = interest.0;
+        Ok(())
+    }
+
+    /// Removes the socket registered as `token`, returning its descriptor.
+    pub fn remove(&mut self, token: usize) -> Result<RawFd> {
+        let i = self.index(token)?;
+        self.tokens.swap_remove(i);
+        Ok(self.fds.swap_remove(i).fd)
+    }
+
+    fn index(&self, token: usize) -> Result<usize> {
+        self.tokens.iter().position(|&t| t == token).ok_or_else(|| {
+            Error::new(ErrorKind::NotFound, "token is not registered")
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Blocks until at least one socket is ready or `timeout` passes, then
+    /// returns the ready sockets' events. The events are empty if the
+    /// timeout passed first.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Events<'_>> {
+        for pfd in &mut self.fds {
+            pfd.revents = 0;
+        }
+        poll_fds(&mut self.fds, timeout)?;
+        Ok(Events {
+            fds: self.fds.iter(),
+            tokens: self.tokens.iter(),
+        })
+    }
+}
+
+/// An iterator over the sockets `PollSet::wait()` found ready.
+#[derive(Debug)]
+pub struct Events<'a> {
+    fds: slice::Iter<'a, libc::pollfd>,
+    tokens: slice::Iter<'a, usize>,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = PollEvent;
+
+    fn next(&mut self) -> Option<PollEvent> {
+        loop {
+            let pfd = self.fds.next()?;
+            let token = *self.tokens.next()?;
+            if pfd.revents != 0 {
+                return Some(PollEvent::new(token, pfd.revents));
+            }
+        }
+    }
+}
+
+/// The readiness of one socket in a `PollSet`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollEvent {
+    token: usize,
+    revents: libc::c_short,
+}
+
+impl PollEvent {
+    pub(crate) fn new(token: usize, revents: libc::c_short) -> PollEvent {
+        PollEvent {
+            token,
+            revents,
+        }
+    }
+
+    /// The token the socket was registered with.
+    pub fn token(&self) -> usize {
+        self.token
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.revents & libc::POLLIN != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.revents & libc::POLLOUT != 0
+    }
+
+    /// The peer hung up. Any data it sent first can still be received.
+    pub fn is_hup(&self) -> bool {
+        self.revents & libc::POLLHUP != 0
+    }
+
+    /// The socket has a pending error, which `take_error()` will return, or
+    /// its descriptor is no longer open.
+    pub fn is_error(&self) -> bool {
+        self.revents & (libc::POLLERR | libc::POLLNVAL) != 0
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! A client-side pool of connections, so that a client making many short
+//! requests to a server can reuse connections rather than opening one for
+//! each.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Result, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use super::{poll, UnixStream};
+
+struct Idle {
+    stream: UnixStream,
+    since: Instant,
+}
+
+/// Keeps idle connections to each socket path for reuse.
+///
+/// `get()` hands out an idle connection if there is one that is still
+/// usable, and otherwise connects. The connection goes back to the pool
+/// when it is dropped. At most `max_idle()` connections are kept per path,
+/// each for at most `max_idle_time()`.
+///
+/// Only return connections that are between requests: a connection the
+/// server has unread data on, or that is partway through a response,
+/// should be dropped with `discard()` instead. One that had a read or
+/// write error through the pooled handle is discarded automatically;
+/// `WouldBlock`, `Interrupted` and `TimedOut` don't count, as they leave the
+/// connection usable.
+pub struct Pool {
+    idle: Mutex<HashMap<PathBuf, Vec<Idle>>>,
+    max_idle: usize,
+    max_idle_time: Duration,
+}
+
+impl Pool {
+    /// Creates a pool keeping up to 4 idle connections per path, for up to
+    /// a minute each.
+    pub fn new() -> Pool {
+        Pool {
+            idle: Mutex::new(HashMap::new()),
+            max_idle: 4,
+            max_idle_time: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the number of idle connections kept per path. With 0
+    /// connections are never reused.
+    pub fn max_idle(mut self, max: usize) -> Pool {
+        self.max_idle = max;
+        self
+    }
+
+    /// Sets how long a connection may sit idle before it is closed rather
+    /// than reused. A server is likely to close idle connections itself
+    /// after a while, so this should be less than the server's timeout.
+    pub fn max_idle_time(mut self, max: Duration) -> Pool {
+        self.max_idle_time = max;
+        self
+    }
+
+    /// Returns a connection to the socket at `path`, reusing an idle one
+    /// if one is still usable. Idle connections that the server has closed,
+    /// or that have unexpected data to read, are closed.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Result<Pooled<'_>> {
+        let path = path.as_ref();
+        let reused = {
+            let mut idle = self.lock();
+            let now = Instant::now();
+            let mut reused = None;
+            if let Some(conns) = idle.get_mut(path) {
+                while let Some(conn) = conns.pop() {
+                    if now.duration_since(conn.since) <= self.max_idle_time
+                        && usable(&conn.stream) {
+                        reused = Some(conn.stream);
+                        break;
+                    }
+                }
+            }
+            reused
+        };
+
+        let stream = match reused {
+            Some(stream) => stream,
+            None => UnixStream::connect(path)?,
+        };
+        Ok(Pooled {
+            pool: self,
+            path: path.to_path_buf(),
+            stream: Some(stream),
+            broken: false,
+        })
+    }
+
+    /// Returns the number of idle connections, across all paths.
+    pub fn idle_count(&self) -> usize {
+        self.lock().values().map(Vec::len).sum()
+    }
+
+    /// Closes all idle connections.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, Vec<Idle>>> {
+        self.idle.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn put(&self, path: PathBuf, stream: UnixStream) {
+        if self.max_idle == 0 {
+            return;
+        }
+        let mut idle = self.lock();
+        let now = Instant::now();
+        let max_idle_time = self.max_idle_time;
+        let conns = idle.entry(path).or_default();
+        conns.retain(|conn| now.duration_since(conn.since) <= max_idle_time);
+        if conns.len() >= self.max_idle {
+            conns.remove(0);
+        }
+        conns.push(Idle {
+            stream,
+            since: now,
+        });
+    }
+}
+
+impl Default for Pool {
+    fn default() -> Pool {
+        Pool::new()
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("idle", &self.idle_count())
+            .field("max_idle", &self.max_idle)
+            .field("max_idle_time", &self.max_idle_time)
+            .finish()
+    }
+}
+
+// An idle connection should have nothing to read: readiness means the
+// server hung up, reported an error, or sent something unasked for.
+fn usable(stream: &UnixStream) -> bool {
+    match poll(stream.as_raw_fd(), libc::POLLIN, Some(Duration::ZERO)) {
+        Ok(revents) => revents == 0,
+        Err(_) => false,
+    }
+}
+
+/// A connection from a `Pool`, which goes back to the pool when dropped.
+/// It dereferences to the connection.
+#[derive(Debug)]
+pub struct Pooled<'a> {
+    pool: &'a Pool,
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    broken: bool,
+}
+
+impl<'a> Pooled<'a> {
+    /// Closes the connection rather than returning it to the pool.
+    pub fn discard(mut self) {
+        self.stream = None;
+    }
+
+    /// Takes the connection out of the pool's care.
+    pub fn into_inner(mut self) -> UnixStream {
+        self.stream.take().unwrap()
+    }
+
+    fn stream(&self) -> &UnixStream {
+        self.stream.as_ref().unwrap()
+    }
+
+    fn check<T>(&mut self, res: Result<T>) -> Result<T> {
+        if let Err(ref err) = res {
+            match err.kind() {
+                ErrorKind::WouldBlock | ErrorKind::Interrupted
+                    | ErrorKind::TimedOut => {}
+                _ => self.broken = true,
+            }
+        }
+        res
+    }
+}
+
+impl<'a> Deref for Pooled<'a> {
+    type Target = UnixStream;
+
+    fn deref(&self) -> &UnixStream {
+        self.stream()
+    }
+}
+
+impl<'a> DerefMut for Pooled<'a> {
+    fn deref_mut(&mut self) -> &mut UnixStream {
+        self.stream.as_mut().unwrap()
+    }
+}
+
+impl<'a> Read for Pooled<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let res = self.stream().read(buf);
+        self.check(res)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        let res = self.stream().read_vectored(bufs);
+        self.check(res)
+    }
+}
+
+impl<'a> Write for Pooled<'a> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let res = self.stream().write(buf);
+        self.check(res)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize> {
+        let res = self.stream().write_vectored(bufs);
+        self.check(res)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> Drop for Pooled<'a> {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            if !self.broken {
+                self.pool.put(::std::mem::take(&mut self.path), stream);
+            }
+        }
+    }
+}
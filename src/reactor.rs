@@ -0,0 +1,324 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! A minimal event loop over epoll(7), or kqueue(2) on macOS and the BSDs,
+//! enabled with the `reactor` feature. Unlike `PollSet`, the cost of a wait
+//! depends on the number of ready sockets rather than registered ones.
+
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use super::{last_error, Interest, PollEvent};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::timeout_ms;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use super::set_cloexec;
+
+// How many events one `run_once()` call can dispatch
+const MAX_EVENTS: usize = 1024;
+
+// The types of `kevent`'s `filter` and `flags` fields
+#[cfg(target_os = "netbsd")]
+type Filter = u32;
+#[cfg(target_os = "netbsd")]
+type Flags = u32;
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "netbsd")))]
+type Filter = libc::c_short;
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "netbsd")))]
+type Flags = libc::c_ushort;
+
+/// When a registered socket is reported ready.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// On every wait, for as long as the socket stays ready.
+    Level,
+    /// Once each time the socket becomes ready. The socket must then be
+    /// drained until it returns `WouldBlock` before it is reported again,
+    /// so it should be non-blocking.
+    Edge,
+}
+
+/// An epoll or kqueue instance that sockets are registered with under
+/// caller-chosen tokens.
+///
+/// As with `PollSet`, a socket should be deregistered before it is closed.
+/// On kqueue platforms a socket that is both readable and writable is
+/// reported as two events with the same token.
+pub struct Reactor {
+    poller: OwnedFd,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    events: Vec<libc::epoll_event>,
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    events: Vec<libc::kevent>,
+}
+
+impl fmt::Debug for Reactor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reactor")
+            .field("poller", &self.poller)
+            .finish()
+    }
+}
+
+impl Reactor {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new() -> Result<Reactor> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 { return Err(last_error()) }
+
+        Ok(Reactor {
+            poller: unsafe { OwnedFd::from_raw_fd(fd) },
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn new() -> Result<Reactor> {
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 { return Err(last_error()) }
+
+        let poller = unsafe { OwnedFd::from_raw_fd(fd) };
+        set_cloexec(poller.as_raw_fd(), true)?;
+        Ok(Reactor {
+            poller,
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    /// Starts watching `sock` for `interest`, reporting its events under
+    /// `token`.
+    pub fn register<S: AsRawFd>(&self, sock: &S, token: usize,
+                                interest: Interest, trigger: Trigger)
+                                -> Result<()> {
+        self.update(sock.as_raw_fd(), token, interest, trigger, false)
+    }
+
+    /// Changes the token, interest or trigger of a registered socket.
+    pub fn reregister<S: AsRawFd>(&self, sock: &S, token: usize,
+                                  interest: Interest, trigger: Trigger)
+                                  -> Result<()> {
+        self.update(sock.as_raw_fd(), token, interest, trigger, true)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn update(&self, fd: RawFd, token: usize, interest: Interest,
+              trigger: Trigger, modify: bool) -> Result<()> {
+        let mut events = 0;
+        if interest.is_readable() {
+            events |= libc::EPOLLIN | libc::EPOLLRDHUP;
+        }
+        if interest.is_writable() {
+            events |= libc::EPOLLOUT;
+        }
+        if trigger == Trigger::Edge {
+            events |= libc::EPOLLET;
+        }
+
+        let mut event = libc::epoll_event {
+            events: events as u32,
+            u64: token as u64,
+        };
+        let op = if modify { libc::EPOLL_CTL_MOD } else { libc::EPOLL_CTL_ADD };
+        match unsafe {
+            libc::epoll_ctl(self.poller.as_raw_fd(), op, fd, &mut event)
+        } {
+            -1 => Err(last_error()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn update(&self, fd: RawFd, token: usize, interest: Interest,
+              trigger: Trigger, _modify: bool) -> Result<()> {
+        let mut flags = libc::EV_ADD | libc::EV_ENABLE;
+        if trigger == Trigger::Edge {
+            flags |= libc::EV_CLEAR;
+        }
+
+        for &(filter, wanted) in &[(libc::EVFILT_READ, interest.is_readable()),
+                                   (libc::EVFILT_WRITE,
+                                    interest.is_writable())] {
+            if wanted {
+                self.change(fd, filter, flags, token)?;
+            } else {
+                self.delete(fd, filter)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops watching `sock`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn deregister<S: AsRawFd>(&self, sock: &S) -> Result<()> {
+        match unsafe {
+            libc::epoll_ctl(self.poller.as_raw_fd(), libc::EPOLL_CTL_DEL,
+                            sock.as_raw_fd(), std::ptr::null_mut())
+        } {
+            -1 => Err(last_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stops watching `sock`.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn deregister<S: AsRawFd>(&self, sock: &S) -> Result<()> {
+        self.delete(sock.as_raw_fd(), libc::EVFILT_READ)?;
+        self.delete(sock.as_raw_fd(), libc::EVFILT_WRITE)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn change(&self, fd: RawFd, filter: Filter, flags: Flags, token: usize)
+              -> Result<()> {
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        change.ident = fd as libc::uintptr_t;
+        change.filter = filter;
+        change.flags = flags;
+        change.udata = token as _;
+        match unsafe {
+            libc::kevent(self.poller.as_raw_fd(), &change, 1,
+                         std::ptr::null_mut(), 0, std::ptr::null())
+        } {
+            -1 => Err(last_error()),
+            _ => Ok(()),
+        }
+    }
+
+    // Deletes a kqueue filter, ignoring one that wasn't registered.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn delete(&self, fd: RawFd, filter: Filter) -> Result<()> {
+        match self.change(fd, filter, libc::EV_DELETE, 0) {
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Waits until at least one registered socket is ready or `timeout`
+    /// passes, then calls `dispatch` with each ready socket's event.
+    /// Returns the number of events dispatched, which is 0 if the timeout
+    /// passed first.
+    pub fn run_once<F>(&mut self, timeout: Option<Duration>, mut dispatch: F)
+                       -> Result<usize>
+        where F: FnMut(PollEvent)
+    {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let n = loop {
+            match self.wait(deadline) {
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                res => break res?,
+            }
+        };
+
+        for i in 0..n {
+            dispatch(self.event(i));
+        }
+        Ok(n)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn wait(&mut self, deadline: Option<Instant>) -> Result<usize> {
+        let ret = unsafe {
+            libc::epoll_wait(self.poller.as_raw_fd(),
+                             self.events.as_mut_ptr(),
+                             MAX_EVENTS as libc::c_int,
+                             timeout_ms(deadline))
+        };
+        if ret < 0 { return Err(last_error()) }
+
+        unsafe { self.events.set_len(ret as usize) };
+        Ok(ret as usize)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn wait(&mut self, deadline: Option<Instant>) -> Result<usize> {
+        let ts = deadline.map(|deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            libc::timespec {
+                tv_sec: remaining.as_secs() as libc::time_t,
+                tv_nsec: remaining.subsec_nanos() as _,
+            }
+        });
+        let ts_ptr = match ts {
+            Some(ref ts) => ts as *const libc::timespec,
+            None => std::ptr::null(),
+        };
+
+        let ret = unsafe {
+            libc::kevent(self.poller.as_raw_fd(), std::ptr::null(), 0,
+                         self.events.as_mut_ptr(), MAX_EVENTS as _, ts_ptr)
+        };
+        if ret < 0 { return Err(last_error()) }
+
+        unsafe { self.events.set_len(ret as usize) };
+        Ok(ret as usize)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn event(&self, i: usize) -> PollEvent {
+        let event = self.events[i];
+        let events = event.events as libc::c_int;
+        let mut revents = 0;
+        if events & libc::EPOLLIN != 0 {
+            revents |= libc::POLLIN;
+        }
+        if events & libc::EPOLLOUT != 0 {
+            revents |= libc::POLLOUT;
+        }
+        if events & (libc::EPOLLHUP | libc::EPOLLRDHUP) != 0 {
+            revents |= libc::POLLHUP;
+        }
+        if events & libc::EPOLLERR != 0 {
+            revents |= libc::POLLERR;
+        }
+        PollEvent::new(event.u64 as usize, revents)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn event(&self, i: usize) -> PollEvent {
+        let event = &self.events[i];
+        let mut revents = 0;
+        if event.filter == libc::EVFILT_READ {
+            revents |= libc::POLLIN;
+        }
+        if event.filter == libc::EVFILT_WRITE {
+            revents |= libc::POLLOUT;
+        }
+        if event.flags & libc::EV_EOF != 0 {
+            revents |= libc::POLLHUP;
+        }
+        if event.flags & libc::EV_ERROR != 0 {
+            revents |= libc::POLLERR;
+        }
+        PollEvent::new(event.udata as usize, revents)
+    }
+}
+
+impl AsRawFd for Reactor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.poller.as_raw_fd()
+    }
+}
@@ -0,0 +1,598 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Helpers for writing servers on top of the listener types.
+//!
+//! `LimitedListener` caps how many of the connections it accepts may be
+//! open at once, to protect a server against running out of descriptors.
+//! `Server` runs an accept loop that hands each connection to a handler on
+//! its own thread, or on one of a pool of worker threads.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut, Read, Result, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use libc;
+
+use super::{ListenerShutdown, SeqpacketListener, SocketAddr, ToUnixAddr};
+use super::{UnixListener, UnixSeqpacket, UnixStream};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The listener types the server helpers can accept connections from.
+pub trait Listener: private::Sealed {
+    /// The type of the accepted connections.
+    type Conn;
+
+    #[doc(hidden)]
+    fn accept_conn(&self) -> Result<(Self::Conn, SocketAddr)>;
+
+    #[doc(hidden)]
+    fn listener_shutdown(&self) -> Result<ListenerShutdown>;
+}
+
+impl private::Sealed for UnixListener {}
+
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn accept_conn(&self) -> Result<(UnixStream, SocketAddr)> {
+        self.accept()
+    }
+
+    fn listener_shutdown(&self) -> Result<ListenerShutdown> {
+        self.shutdown_handle()
+    }
+}
+
+impl private::Sealed for SeqpacketListener {}
+
+impl Listener for SeqpacketListener {
+    type Conn = UnixSeqpacket;
+
+    fn accept_conn(&self) -> Result<(UnixSeqpacket, SocketAddr)> {
+        self.accept()
+    }
+
+    fn listener_shutdown(&self) -> Result<ListenerShutdown> {
+        self.shutdown_handle()
+    }
+}
+
+/// What a `LimitedListener` does once its limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitMode {
+    /// Stop accepting until a connection is dropped. New connections wait
+    /// in the listen backlog meanwhile.
+    Wait,
+    /// Keep accepting, but close new connections at once, so that clients
+    /// find out without waiting.
+    Reject,
+}
+
+#[derive(Debug)]
+struct Limit {
+    max: usize,
+    active: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Limit {
+    fn active(&self) -> MutexGuard<'_, usize> {
+        // the count is always consistent, even if a holder panicked
+        self.active.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut active = self.active();
+        if *active < self.max {
+            *active += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Waits for a free place, unless `shutdown` is shut down first.
+    fn acquire(&self, shutdown: &ListenerShutdown) -> Result<()> {
+        let mut active = self.active();
+        while *active >= self.max {
+            if shutdown.is_shutdown() {
+                return Err(Error::new(ErrorKind::NotConnected,
+                                      "listener closed"));
+            }
+            active = self.freed.wait(active)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        *active += 1;
+        Ok(())
+    }
+
+    fn release(&self) {
+        *self.active() -= 1;
+        self.freed.notify_one();
+    }
+}
+
+// One connection's place in the count, given back when dropped.
+#[derive(Debug)]
+struct Permit {
+    limit: Arc<Limit>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limit.release();
+    }
+}
+
+/// A listener that limits how many of the connections accepted through it
+/// may be open at once. A connection counts against the limit until the
+/// `Limited` wrapping it is dropped.
+#[derive(Debug)]
+pub struct LimitedListener<L> {
+    listener: L,
+    limit: Arc<Limit>,
+    mode: LimitMode,
+    shutdown: ListenerShutdown,
+}
+
+impl<L: Listener> LimitedListener<L> {
+    /// Wraps `listener`, allowing `max` connections at once. `max` must be
+    /// at least 1, or this fails with `ErrorKind::InvalidInput`.
+    pub fn new(listener: L, max: usize, mode: LimitMode)
+               -> Result<LimitedListener<L>> {
+        if max == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "connection limit must be at least 1"));
+        }
+
+        let limit = Arc::new(Limit {
+            max,
+            active: Mutex::new(0),
+            freed: Condvar::new(),
+        });
+        let shutdown = listener.listener_shutdown()?;
+        let weak = Arc::downgrade(&limit);
+        shutdown.on_shutdown(move || {
+            if let Some(limit) = weak.upgrade() {
+                // taking the lock means a waiter has either seen the
+                // shutdown or is waiting, and so is woken
+                let _active = limit.active();
+                limit.freed.notify_all();
+            }
+        });
+        Ok(LimitedListener {
+            listener,
+            limit,
+            mode,
+            shutdown,
+        })
+    }
+
+    /// Accepts a new connection, first waiting for one to be dropped if the
+    /// limit has been reached in `LimitMode::Wait`. In `LimitMode::Reject`,
+    /// connections over the limit are accepted and closed, and this keeps
+    /// waiting for one that fits. Shutting the listener down ends the
+    /// wait in either mode, with `ErrorKind::NotConnected`.
+    pub fn accept(&self) -> Result<(Limited<L::Conn>, SocketAddr)> {
+        loop {
+            if self.mode == LimitMode::Wait {
+                self.limit.acquire(&self.shutdown)?;
+                let permit = self.permit();
+                let (conn, addr) = self.listener.accept_conn()?;
+                return Ok((Limited { conn, _permit: permit }, addr));
+            }
+
+            let (conn, addr) = self.listener.accept_conn()?;
+            if self.limit.try_acquire() {
+                let permit = self.permit();
+                return Ok((Limited { conn, _permit: permit }, addr));
+            }
+            drop(conn);
+        }
+    }
+
+    fn permit(&self) -> Permit {
+        Permit {
+            limit: self.limit.clone(),
+        }
+    }
+
+    /// Returns the number of accepted connections that are still open.
+    pub fn active(&self) -> usize {
+        *self.limit.active()
+    }
+
+    pub fn max(&self) -> usize {
+        self.limit.max
+    }
+
+    pub fn get_ref(&self) -> &L {
+        &self.listener
+    }
+
+    /// Returns the listener. Connections already accepted still return
+    /// their places when dropped, but nothing is counting them any more.
+    pub fn into_inner(self) -> L {
+        self.listener
+    }
+}
+
+/// A connection accepted by a `LimitedListener`, which counts against its
+/// limit until dropped. It dereferences to the connection.
+#[derive(Debug)]
+pub struct Limited<S> {
+    conn: S,
+    _permit: Permit,
+}
+
+impl<S> Deref for Limited<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.conn
+    }
+}
+
+impl<S> DerefMut for Limited<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        &mut self.conn
+    }
+}
+
+impl<S: Read> Read for Limited<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.conn.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> Result<usize> {
+        self.conn.read_vectored(bufs)
+    }
+}
+
+impl<S: Write> Write for Limited<S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.conn.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize> {
+        self.conn.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.conn.flush()
+    }
+}
+
+/// Where an error passed to a `Server`'s error hook came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorSource {
+    /// Accepting a connection, or starting a thread for it.
+    Accept,
+    /// A connection's handler.
+    Handler,
+}
+
+/// What a `Server` does after an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    Continue,
+    /// Stop accepting. `serve()` returns an accept error that stopped it;
+    /// a handler error shuts the server down, and `serve()` returns
+    /// `Ok(())`.
+    Stop,
+}
+
+type ErrorHook = dyn Fn(&Error, ErrorSource) -> ErrorAction + Send + Sync;
+
+/// The default error policy: handler errors only end their own
+/// connection, and accept errors that affect a single connection, or that
+/// may pass once descriptors or memory are freed, are retried, the latter
+/// after a pause. Any other accept error stops the server.
+pub fn default_error_policy(err: &Error, source: ErrorSource) -> ErrorAction {
+    if source == ErrorSource::Handler {
+        return ErrorAction::Continue;
+    }
+
+    match err.kind() {
+        ErrorKind::ConnectionAborted | ErrorKind::Interrupted => {
+            return ErrorAction::Continue;
+        }
+        _ => {}
+    }
+    match err.raw_os_error() {
+        Some(libc::EMFILE) | Some(libc::ENFILE) | Some(libc::ENOBUFS)
+            | Some(libc::ENOMEM) => {
+            thread::sleep(Duration::from_millis(100));
+            ErrorAction::Continue
+        }
+        _ => ErrorAction::Stop,
+    }
+}
+
+/// A server that accepts connections in a loop and calls a handler with
+/// each one, and its peer's address. By default each connection gets a
+/// thread of its own; `worker_pool()` hands them to a fixed set of threads
+/// instead.
+pub struct Server<L: Listener> {
+    listener: L,
+    shutdown: ListenerShutdown,
+    max: usize,
+    mode: LimitMode,
+    pool: Option<(usize, usize)>,
+    on_error: Arc<ErrorHook>,
+}
+
+impl Server<UnixListener> {
+    pub fn bind<A: ToUnixAddr>(addr: A) -> Result<Server<UnixListener>> {
+        Server::new(UnixListener::bind(addr)?)
+    }
+}
+
+impl<L: Listener> Server<L> {
+    pub fn new(listener: L) -> Result<Server<L>> {
+        Ok(Server {
+            shutdown: listener.listener_shutdown()?,
+            listener,
+            max: usize::MAX,
+            mode: LimitMode::Wait,
+            pool: None,
+            on_error: Arc::new(default_error_policy),
+        })
+    }
+
+    /// Limits the number of connections being handled at once. See
+    /// `LimitedListener`. `serve()` fails with `ErrorKind::InvalidInput` if
+    /// `max` is 0.
+    pub fn max_connections(mut self, max: usize, mode: LimitMode)
+                           -> Server<L> {
+        self.max = max;
+        self.mode = mode;
+        self
+    }
+
+    /// Handles connections on `size` worker threads, started by `serve()`,
+    /// rather than a thread each. Up to `queue_depth` accepted connections
+    /// wait for an idle worker; once the queue is full the server stops
+    /// accepting until a worker takes one, or the server is shut down.
+    /// `serve()` fails with `ErrorKind::InvalidInput` if `size` is 0.
+    pub fn worker_pool(mut self, size: usize, queue_depth: usize)
+                       -> Server<L> {
+        self.pool = Some((size, queue_depth));
+        self
+    }
+
+    /// Replaces `default_error_policy()` with `hook`, which is called with
+    /// each accept and handler error and decides whether to go on.
+    pub fn on_error<F>(mut self, hook: F) -> Server<L>
+        where F: Fn(&Error, ErrorSource) -> ErrorAction + Send + Sync + 'static
+    {
+        self.on_error = Arc::new(hook);
+        self
+    }
+
+    /// Returns a handle whose `shutdown()` makes `serve()` return.
+    pub fn shutdown_handle(&self) -> ListenerShutdown {
+        self.shutdown.clone()
+    }
+
+    pub fn get_ref(&self) -> &L {
+        &self.listener
+    }
+
+    /// Accepts connections until the server is shut down or the error
+    /// hook stops it, calling `handler` for each. Handlers still running
+    /// when this returns are left to finish, as are pool workers, which
+    /// exit once the connections queued for them have been handled.
+    pub fn serve<H>(self, handler: H) -> Result<()>
+        where H: Fn(L::Conn, SocketAddr) -> Result<()> + Send + Sync + 'static,
+              L::Conn: Send + 'static
+    {
+        let Server { listener, shutdown, max, mode, pool, on_error } = self;
+        if pool.is_some_and(|(size, _)| size == 0) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "worker pool must have at least 1 thread"));
+        }
+        let listener = LimitedListener::new(listener, max, mode)?;
+        let run = {
+            let (on_error, shutdown) = (on_error.clone(), shutdown.clone());
+            Arc::new(move |conn: Limited<L::Conn>, addr| {
+                let Limited { conn, _permit } = conn;
+                if let Err(err) = handler(conn, addr) {
+                    if on_error(&err, ErrorSource::Handler)
+                        == ErrorAction::Stop {
+                        let _ = shutdown.shutdown();
+                    }
+                }
+            })
+        };
+
+        let queue = match pool {
+            Some((size, queue_depth)) => {
+                // closes the queue if a spawn fails, so the workers
+                // already started don't wait on it forever
+                let closer = QueueCloser(Arc::new(JobQueue::new(queue_depth)));
+                for _ in 0..size {
+                    let (run, queue) = (run.clone(), closer.0.clone());
+                    thread::Builder::new().spawn(move || {
+                        while let Some((conn, addr)) = queue.pop() {
+                            run(conn, addr);
+                        }
+                    })?;
+                }
+                let weak = Arc::downgrade(&closer.0);
+                shutdown.on_shutdown(move || {
+                    if let Some(queue) = weak.upgrade() {
+                        queue.wake();
+                    }
+                });
+                Some(closer)
+            }
+            None => None,
+        };
+
+        loop {
+            let (conn, addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(_) if shutdown.is_shutdown() => return Ok(()),
+                Err(err) => match on_error(&err, ErrorSource::Accept) {
+                    ErrorAction::Continue => continue,
+                    ErrorAction::Stop => return Err(err),
+                },
+            };
+
+            if let Some(QueueCloser(ref queue)) = queue {
+                if !queue.push((conn, addr), &shutdown) {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let run = run.clone();
+            let spawned = thread::Builder::new().spawn(move || {
+                run(conn, addr)
+            });
+            if let Err(err) = spawned {
+                if on_error(&err, ErrorSource::Accept) == ErrorAction::Stop {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+// The connections a server has accepted for its pool workers. There is
+// room for another while fewer than `depth` would be left waiting once each
+// idle worker has taken one.
+struct JobQueue<T> {
+    state: Mutex<Jobs<T>>,
+    // signalled when a job is queued, or the queue is closed
+    queued: Condvar,
+    // signalled when a worker goes idle, or the server is shut down
+    room: Condvar,
+    depth: usize,
+}
+
+struct Jobs<T> {
+    jobs: VecDeque<T>,
+    idle: usize,
+    closed: bool,
+}
+
+impl<T> JobQueue<T> {
+    fn new(depth: usize) -> JobQueue<T> {
+        JobQueue {
+            state: Mutex::new(Jobs {
+                jobs: VecDeque::new(),
+                idle: 0,
+                closed: false,
+            }),
+            queued: Condvar::new(),
+            room: Condvar::new(),
+            depth,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Jobs<T>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Queues `job` once there is room, or returns false if `shutdown` is
+    // shut down first.
+    fn push(&self, job: T, shutdown: &ListenerShutdown) -> bool {
+        let mut state = self.lock();
+        while state.jobs.len() >= self.depth + state.idle {
+            if shutdown.is_shutdown() {
+                return false;
+            }
+            state = self.room.wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        state.jobs.push_back(job);
+        self.queued.notify_one();
+        true
+    }
+
+    // Takes the next job for a worker, or `None` once the queue is closed
+    // and empty.
+    fn pop(&self) -> Option<T> {
+        let mut state = self.lock();
+        state.idle += 1;
+        self.room.notify_one();
+        while state.jobs.is_empty() && !state.closed {
+            state = self.queued.wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        state.idle -= 1;
+        state.jobs.pop_front()
+    }
+
+    // Wakes `push()` to notice a shutdown. The lock is taken so that the
+    // wakeup can't fall between its check and its wait.
+    fn wake(&self) {
+        let _state = self.lock();
+        self.room.notify_all();
+    }
+}
+
+// Closes the queue when `serve()` returns, so the workers exit once they
+// have handled what is left in it.
+struct QueueCloser<T>(Arc<JobQueue<T>>);
+
+impl<T> Drop for QueueCloser<T> {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.queued.notify_all();
+    }
+}
+
+impl<L: Listener + fmt::Debug> fmt::Debug for Server<L> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Server")
+            .field("listener", &self.listener)
+            .field("max", &self.max)
+            .field("mode", &self.mode)
+            .field("pool", &self.pool)
+            .finish()
+    }
+}
+
+/// Binds a `UnixListener` to `addr` and serves it with `handler`, on a
+/// thread per connection, with `default_error_policy()`.
+///
+/// Nothing can shut this down: it returns only if binding fails or an
+/// accept error stops it, so it suits processes that exit to stop serving.
+/// Use `Server` and its `shutdown_handle()` to stop serving from within the
+/// process, or for connection limits and other error handling.
+pub fn serve<A, H>(addr: A, handler: H) -> Result<()>
+    where A: ToUnixAddr,
+          H: Fn(UnixStream, SocketAddr) -> Result<()> + Send + Sync + 'static
+{
+    Server::bind(addr)?.serve(handler)
+}
@@ -0,0 +1,192 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Moving data between a socket and another descriptor without copying it
+//! through userspace, where the platform allows.
+
+use std::cmp;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::io::RawFd;
+
+use libc;
+
+use super::{is_nonblocking, last_error, read, retry, write_all};
+
+// The most a fallback copy moves per call.
+const COPY_LEN: usize = 64 * 1024;
+
+fn read_fd(fd: RawFd, buf: &mut [u8]) -> Result<usize> {
+    let ret = retry(|| unsafe {
+        libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void,
+                   buf.len() as libc::size_t)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+fn write_fd_all(fd: RawFd, mut buf: &[u8]) -> Result<()> {
+    while !buf.is_empty() {
+        let ret = retry(|| unsafe {
+            libc::write(fd, buf.as_ptr() as *const libc::c_void,
+                        buf.len() as libc::size_t)
+        });
+        match ret {
+            -1 => return Err(last_error()),
+            0 => {
+                return Err(Error::new(ErrorKind::WriteZero,
+                                      "failed to write whole buffer"));
+            }
+            n => buf = &buf[n as usize..],
+        }
+    }
+    Ok(())
+}
+
+// Both copies read a chunk and write all of it, so nothing read is dropped
+// unless the write fails. write_all() waits out a full non-blocking socket,
+// but there is no such wait for `dst`: a short write there would lose the
+// rest of the chunk, which has already been taken off the socket, so a
+// non-blocking `dst` is refused before anything is read.
+fn copy_to(sock: RawFd, dst: RawFd, len: usize) -> Result<usize> {
+    if is_nonblocking(dst)? {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "can't copy to a non-blocking descriptor"));
+    }
+    let mut buf = vec![0; cmp::min(len, COPY_LEN)];
+    let n = read(sock, &mut buf)?;
+    write_fd_all(dst, &buf[..n])?;
+    Ok(n)
+}
+
+fn copy_from(sock: RawFd, src: RawFd, len: usize) -> Result<usize> {
+    let mut buf = vec![0; cmp::min(len, COPY_LEN)];
+    let n = read_fd(src, &mut buf)?;
+    write_all(sock, &buf[..n])?;
+    Ok(n)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn splice(from: RawFd, to: RawFd, len: usize) -> Result<Option<usize>> {
+    let ret = retry(|| unsafe {
+        libc::splice(from, std::ptr::null_mut(), to, std::ptr::null_mut(),
+                     len, libc::SPLICE_F_MOVE)
+    });
+
+    if ret >= 0 { return Ok(Some(ret as usize)) }
+
+    // splice() needs a pipe at one end; anything else is copied instead
+    let err = last_error();
+    match err.raw_os_error() {
+        Some(libc::EINVAL) => Ok(None),
+        _ => Err(err),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn splice_to(sock: RawFd, dst: RawFd, len: usize) -> Result<usize> {
+    match splice(sock, dst, len)? {
+        Some(n) => Ok(n),
+        None => copy_to(sock, dst, len),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn splice_from(sock: RawFd, src: RawFd, len: usize) -> Result<usize> {
+    match splice(src, sock, len)? {
+        Some(n) => Ok(n),
+        None => copy_from(sock, src, len),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn splice_to(sock: RawFd, dst: RawFd, len: usize) -> Result<usize> {
+    copy_to(sock, dst, len)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn splice_from(sock: RawFd, src: RawFd, len: usize) -> Result<usize> {
+    copy_from(sock, src, len)
+}
+
+fn pread(fd: RawFd, buf: &mut [u8], offset: u64) -> Result<usize> {
+    let ret = retry(|| unsafe {
+        libc::pread(fd, buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len() as libc::size_t, offset as libc::off_t)
+    });
+
+    if ret < 0 { return Err(last_error()) }
+
+    Ok(ret as usize)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn send_file(sock: RawFd, file: RawFd, offset: u64, len: u64)
+                 -> Result<u64> {
+    let mut off = offset as libc::off_t;
+    let mut sent = 0;
+    while sent < len {
+        let count = cmp::min(len - sent, COPY_LEN as u64 * 16) as usize;
+        let ret = retry(|| unsafe {
+            libc::sendfile(sock, file, &mut off, count)
+        });
+        match ret {
+            -1 => {
+                let err = last_error();
+                // Files sendfile() can't read from are read by hand
+                if sent == 0 && err.raw_os_error() == Some(libc::EINVAL) {
+                    return copy_file(sock, file, offset, len);
+                }
+                if sent > 0 && err.kind() == ErrorKind::WouldBlock {
+                    break;
+                }
+                return Err(err);
+            }
+            0 => break,
+            n => sent += n as u64,
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn send_file(sock: RawFd, file: RawFd, offset: u64, len: u64)
+                 -> Result<u64> {
+    copy_file(sock, file, offset, len)
+}
+
+fn copy_file(sock: RawFd, file: RawFd, offset: u64, len: u64)
+             -> Result<u64> {
+    let mut buf = vec![0; cmp::min(len, COPY_LEN as u64) as usize];
+    let mut sent = 0;
+    while sent < len {
+        let count = cmp::min(len - sent, buf.len() as u64) as usize;
+        let n = pread(file, &mut buf[..count], offset + sent)?;
+        if n == 0 {
+            break;
+        }
+        write_all(sock, &buf[..n])?;
+        sent += n as u64;
+    }
+    Ok(sent)
+}
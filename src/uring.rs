@@ -0,0 +1,302 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! A completion-based interface for high-throughput servers, using
+//! `io_uring(7)`. Enabled with the `io-uring` feature, on Linux only.
+//!
+//! Operations are queued on a `Ring` with a caller-chosen token and take
+//! ownership of their buffers, which come back with the operation's
+//! `Completion`. Nothing reaches the kernel until `submit()` or
+//! `submit_and_wait()` is called.
+//!
+//! Each operation also holds its own descriptor for the socket until it
+//! completes, so a socket may be dropped while operations on it are queued
+//! or in flight; they go on until they finish, or the socket is shut down.
+
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+use io_uring::{opcode, squeue, types, IoUring};
+use libc;
+
+use super::{duplicate, SocketAddr, UnixListener, UnixStream, MSG_NOSIGNAL};
+
+/// The result of a finished operation, carrying back its buffers.
+#[derive(Debug)]
+pub enum Completion {
+    Accept {
+        token: u64,
+        result: Result<(UnixStream, SocketAddr)>,
+    },
+    Recv {
+        token: u64,
+        result: Result<usize>,
+        buf: Vec<u8>,
+    },
+    Send {
+        token: u64,
+        result: Result<usize>,
+        buf: Vec<u8>,
+    },
+    SendMsg {
+        token: u64,
+        result: Result<usize>,
+        buf: Vec<u8>,
+        control: Vec<u8>,
+    },
+}
+
+impl Completion {
+    /// The token the operation was queued with.
+    pub fn token(&self) -> u64 {
+        match *self {
+            Completion::Accept { token, .. }
+            | Completion::Recv { token, .. }
+            | Completion::Send { token, .. }
+            | Completion::SendMsg { token, .. } => token,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OpKind {
+    Accept,
+    Recv,
+    Send,
+    SendMsg,
+}
+
+// The kernel holds pointers into this until the operation completes, so it
+// is boxed to keep it in place.
+struct OpState {
+    addr: libc::sockaddr_un,
+    addrlen: libc::socklen_t,
+    iov: libc::iovec,
+    msg: libc::msghdr,
+}
+
+struct Op {
+    token: u64,
+    kind: OpKind,
+    // keeps the descriptor number the entry refers to open until it
+    // completes
+    _fd: OwnedFd,
+    buf: Vec<u8>,
+    control: Vec<u8>,
+    state: Box<OpState>,
+}
+
+/// An `io_uring` instance for socket operations.
+pub struct Ring {
+    ring: IoUring,
+    ops: Vec<Option<Op>>,
+    free: Vec<usize>,
+}
+
+impl Ring {
+    /// Creates a ring with room for `entries` queued operations.
+    pub fn new(entries: u32) -> Result<Ring> {
+        Ok(Ring {
+            ring: IoUring::new(entries)?,
+            ops: Vec::new(),
+            free: Vec::new(),
+        })
+    }
+
+    /// Queues an accept on `listener`. The connection is close-on-exec.
+    pub fn accept(&mut self, listener: &UnixListener, token: u64)
+                  -> Result<()> {
+        self.push(listener.as_fd(), token, OpKind::Accept, Vec::new(),
+                  Vec::new(), |fd, state| {
+            opcode::Accept::new(fd,
+                                &mut state.addr as *mut _ as *mut _,
+                                &mut state.addrlen)
+                .flags(libc::SOCK_CLOEXEC)
+                .build()
+        })
+    }
+
+    /// Queues a receive into `buf`, which is filled from the start.
+    pub fn recv<S: AsFd>(&mut self, sock: &S, mut buf: Vec<u8>, token: u64)
+                         -> Result<()> {
+        let ptr = buf.as_mut_ptr();
+        let len = buf.len() as u32;
+        self.push(sock.as_fd(), token, OpKind::Recv, buf, Vec::new(),
+                  |fd, _| {
+            opcode::Recv::new(fd, ptr, len).build()
+        })
+    }
+
+    /// Queues a send of `buf` to the connected peer.
+    pub fn send<S: AsFd>(&mut self, sock: &S, buf: Vec<u8>, token: u64)
+                         -> Result<()> {
+        let ptr = buf.as_ptr();
+        let len = buf.len() as u32;
+        self.push(sock.as_fd(), token, OpKind::Send, buf, Vec::new(),
+                  |fd, _| {
+            opcode::Send::new(fd, ptr, len).flags(MSG_NOSIGNAL).build()
+        })
+    }
+
+    /// Queues a `sendmsg(2)` of `buf`, to `addr` for unconnected sockets,
+    /// with the control messages in `control`.
+    pub fn send_msg<S: AsFd>(&mut self, sock: &S, mut buf: Vec<u8>,
+                             addr: Option<&SocketAddr>, control: Vec<u8>,
+                             token: u64) -> Result<()> {
+        let ptr = buf.as_mut_ptr();
+        let len = buf.len();
+        let addr = addr.map(|addr| (addr.addr, addr.len));
+        let mut control = control;
+        let control_ptr = control.as_mut_ptr();
+        let control_len = control.len();
+        self.push(sock.as_fd(), token, OpKind::SendMsg, buf, control,
+                  |fd, state| {
+            state.iov.iov_base = ptr as *mut libc::c_void;
+            state.iov.iov_len = len;
+            state.msg.msg_iov = &mut state.iov;
+            state.msg.msg_iovlen = 1;
+            if let Some((addr, addrlen)) = addr {
+                state.addr = addr;
+                state.msg.msg_name =
+                    &mut state.addr as *mut _ as *mut libc::c_void;
+                state.msg.msg_namelen = addrlen;
+            }
+            if control_len > 0 {
+                state.msg.msg_control = control_ptr as *mut libc::c_void;
+                state.msg.msg_controllen = control_len as _;
+            }
+            opcode::SendMsg::new(fd, &state.msg).flags(MSG_NOSIGNAL as u32)
+                .build()
+        })
+    }
+
+    /// Submits queued operations to the kernel, returning how many were
+    /// submitted.
+    pub fn submit(&mut self) -> Result<usize> {
+        self.ring.submit()
+    }
+
+    /// Submits queued operations, then waits until at least `want`
+    /// operations have completed.
+    pub fn submit_and_wait(&mut self, want: usize) -> Result<usize> {
+        self.ring.submit_and_wait(want)
+    }
+
+    /// Returns the next finished operation, if any.
+    pub fn completion(&mut self) -> Option<Completion> {
+        let entry = self.ring.completion().next()?;
+        let index = entry.user_data() as usize;
+        let op = self.ops[index].take()?;
+        self.free.push(index);
+
+        let ret = entry.result();
+        let result = if ret < 0 {
+            Err(Error::from_raw_os_error(-ret))
+        } else {
+            Ok(ret as usize)
+        };
+
+        let token = op.token;
+        Some(match op.kind {
+            OpKind::Accept => Completion::Accept {
+                token,
+                result: result.and_then(|fd| {
+                    let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+                    let addr = SocketAddr::from_parts(op.state.addr,
+                                                      op.state.addrlen)?;
+                    Ok((UnixStream::from(fd), addr))
+                }),
+            },
+            OpKind::Recv => Completion::Recv { token, result, buf: op.buf },
+            OpKind::Send => Completion::Send { token, result, buf: op.buf },
+            OpKind::SendMsg => Completion::SendMsg {
+                token,
+                result,
+                buf: op.buf,
+                control: op.control,
+            },
+        })
+    }
+
+    /// The number of operations queued or in flight.
+    pub fn pending(&self) -> usize {
+        self.ops.len() - self.free.len()
+    }
+
+    fn push<F>(&mut self, sock: BorrowedFd, token: u64, kind: OpKind,
+               buf: Vec<u8>, control: Vec<u8>, build: F) -> Result<()>
+        where F: FnOnce(types::Fd, &mut OpState) -> squeue::Entry
+    {
+        let fd = duplicate(sock)?;
+        let mut state = Box::new(OpState {
+            addr: unsafe { mem::zeroed() },
+            addrlen: mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+            iov: unsafe { mem::zeroed() },
+            msg: unsafe { mem::zeroed() },
+        });
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.ops.push(None);
+                self.ops.len() - 1
+            }
+        };
+        let entry = build(types::Fd(fd.as_raw_fd()), &mut state)
+            .user_data(index as u64);
+
+        // When the submission queue is full, hand it to the kernel to make
+        // room
+        if self.ring.submission().is_full() {
+            if let Err(err) = self.ring.submit() {
+                self.free.push(index);
+                return Err(err);
+            }
+        }
+        if unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.free.push(index);
+            return Err(Error::new(ErrorKind::WouldBlock,
+                                  "io_uring submission queue is full"));
+        }
+
+        self.ops[index] = Some(Op {
+            token,
+            kind,
+            _fd: fd,
+            buf,
+            control,
+            state,
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // The kernel may still write to the buffers of operations that
+        // haven't completed, so they are leaked rather than freed, along
+        // with their descriptors.
+        for op in self.ops.drain(..).flatten() {
+            mem::forget(op);
+        }
+    }
+}
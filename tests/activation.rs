@@ -0,0 +1,135 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{self, Command};
+
+use af_unix::{activation, SockType, UnixDatagram, UnixListener};
+
+fn activate(fds: &str) {
+    env::set_var("LISTEN_PID", process::id().to_string());
+    env::set_var("LISTEN_FDS", fds);
+}
+
+// The environment is shared by every test in the process, so the cases
+// all run in one test.
+#[test]
+fn from_systemd() {
+    env::set_var("LISTEN_PID", "1");
+    env::set_var("LISTEN_FDS", "1");
+    assert!(activation::from_systemd().unwrap().is_empty());
+    assert!(env::var_os("LISTEN_FDS").is_none());
+
+    activate("0");
+    assert!(activation::from_systemd().unwrap().is_empty());
+
+    for fds in &["2147483647", "-1", "many"] {
+        activate(fds);
+        let err = activation::from_systemd().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(env::var_os("LISTEN_PID").is_none());
+    }
+
+    // some descriptor in this range isn't open
+    activate("100000");
+    let err = activation::from_systemd().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+// Set in the child process that `adopt_sockets` runs the adoption in,
+// to the path the listener is bound to.
+const CHILD_VAR: &str = "AF_UNIX_TEST_ACTIVATED";
+
+// The sockets have to be at descriptors 3 and 4, which in this process
+// may already be in use, so the test runs the adoption in a child that
+// is given them there, the way systemd passes them.
+#[test]
+fn adopt_sockets() {
+    if let Some(path) = env::var_os(CHILD_VAR) {
+        return adopted(path.as_ref());
+    }
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let datagram = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let path = addr.as_pathname().unwrap();
+    let fds = [listener.as_raw_fd(), datagram.as_raw_fd()];
+    let mut child = Command::new(env::current_exe().unwrap());
+    child.args(["--exact", "adopt_sockets", "--test-threads=1", "-q"])
+        .env(CHILD_VAR, path)
+        .env("LISTEN_FDS", "2")
+        .env("LISTEN_FDNAMES", "web:events");
+    unsafe {
+        child.pre_exec(move || {
+            // move them out of the way first, as either may be 3 or 4
+            let high = [libc::fcntl(fds[0], libc::F_DUPFD, 100),
+                        libc::fcntl(fds[1], libc::F_DUPFD, 100)];
+            for (i, &fd) in high.iter().enumerate() {
+                if fd == -1 || libc::dup2(fd, 3 + i as i32) == -1 {
+                    return Err(Error::last_os_error());
+                }
+                libc::close(fd);
+            }
+            Ok(())
+        });
+    }
+    let status = child.status().unwrap();
+    assert!(status.success(), "child process failed: {}", status);
+}
+
+fn adopted(path: &Path) {
+    // the child can only know its own pid
+    env::set_var("LISTEN_PID", process::id().to_string());
+    let mut sockets = activation::from_systemd().unwrap();
+    assert_eq!(sockets.len(), 2);
+    assert!(env::var_os("LISTEN_FDNAMES").is_none());
+    assert!(activation::from_systemd().unwrap().is_empty());
+
+    for sock in &sockets {
+        let flags = unsafe { libc::fcntl(sock.as_raw_fd(), libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+    }
+
+    let events = sockets.pop().unwrap();
+    let web = sockets.pop().unwrap();
+    assert_eq!(web.as_raw_fd(), 3);
+    assert_eq!(web.name(), Some("web"));
+    assert_eq!(events.name(), Some("events"));
+
+    web.expect_path(path).unwrap();
+    let err = web.expect_path("/elsewhere").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = events.expect_path(path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let err = events.into_socket::<UnixListener>().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let listener: UnixListener = web.into_socket().unwrap();
+    assert_eq!(listener.local_addr().unwrap().as_pathname(), Some(path));
+}
@@ -0,0 +1,105 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::ffi::{OsStr, OsString};
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use af_unix::{SocketAddr, ToUnixAddr, UnixStream};
+
+#[test]
+fn pathname() {
+    let addr = SocketAddr::from_pathname("/run/app.sock").unwrap();
+    assert_eq!(addr.as_pathname(), Some(Path::new("/run/app.sock")));
+    assert!(addr.as_abstract_name().is_none());
+    assert!(!addr.is_unnamed());
+
+    // paths needn't be UTF-8
+    let path = Path::new(OsStr::from_bytes(b"/tmp/\xff.sock"));
+    let addr = SocketAddr::from_pathname(path).unwrap();
+    assert_eq!(addr.as_pathname(), Some(path));
+}
+
+#[test]
+fn pathname_invalid() {
+    let err = SocketAddr::from_pathname("/tmp/a\0b").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let long = format!("/{}", "x".repeat(200));
+    let err = SocketAddr::from_pathname(&long).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+fn path_of<A: ToUnixAddr>(addr: A) -> PathBuf {
+    addr.to_unix_addr().unwrap().as_pathname().unwrap().to_path_buf()
+}
+
+#[test]
+fn to_unix_addr() {
+    let expected = Path::new("/run/app.sock");
+    assert_eq!(path_of("/run/app.sock"), expected);
+    assert_eq!(path_of(String::from("/run/app.sock")), expected);
+    assert_eq!(path_of(expected), expected);
+    assert_eq!(path_of(expected.to_path_buf()), expected);
+    assert_eq!(path_of(OsStr::new("/run/app.sock")), expected);
+    assert_eq!(path_of(OsString::from("/run/app.sock")), expected);
+    let addr = SocketAddr::from_pathname(expected).unwrap();
+    assert_eq!(path_of(addr), expected);
+
+    let err = "/tmp/a\0b".to_unix_addr().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn abstract_name_to_unix_addr() {
+    use af_unix::AbstractName;
+
+    let addr = AbstractName(b"bus").to_unix_addr().unwrap();
+    assert_eq!(addr.as_abstract_name(), Some(&b"bus"[..]));
+    assert!(addr.as_pathname().is_none());
+}
+
+#[test]
+fn display_and_debug() {
+    let addr = SocketAddr::from_pathname("/run/app.sock").unwrap();
+    assert_eq!(addr.to_string(), "/run/app.sock");
+    assert_eq!(format!("{:?}", addr), "\"/run/app.sock\" (pathname)");
+
+    let (a, _b) = UnixStream::pair().unwrap();
+    let unnamed = a.local_addr().unwrap();
+    assert!(unnamed.is_unnamed());
+    assert_eq!(unnamed.to_string(), "(unnamed)");
+    assert_eq!(format!("{:?}", unnamed), "(unnamed)");
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn display_abstract() {
+    // non-printable bytes are escaped, so distinct names never look alike
+    let addr = SocketAddr::from_abstract_name(b"bus\0\n\xff\"").unwrap();
+    assert_eq!(addr.to_string(), r#"@bus\x00\n\xff\""#);
+    assert_eq!(format!("{:?}", addr), r#""bus\x00\n\xff\"" (abstract)"#);
+}
@@ -0,0 +1,104 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "async-io")]
+
+extern crate af_unix;
+extern crate async_io;
+
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use async_io::{block_on, Async};
+
+use af_unix::async_io_ext::{AsyncDatagramExt, AsyncListenerExt,
+                            AsyncSeqpacketExt};
+use af_unix::{SeqpacketListener, SockType, UnixDatagram, UnixListener,
+              UnixSeqpacket, UnixStream};
+
+#[test]
+fn datagram() {
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    let b = Async::new(b).unwrap();
+    let sender = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        a.send(b"late").unwrap();
+        a
+    });
+
+    let mut buf = [0; 8];
+    assert_eq!(block_on(b.recv(&mut buf)).unwrap(), 4);
+    assert_eq!(&buf[..4], b"late");
+    let mut a = sender.join().unwrap();
+    assert_eq!(block_on(b.send(b"back")).unwrap(), 4);
+    assert_eq!(a.recv(&mut buf).unwrap(), 4);
+
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let rx_addr = rx.local_addr().unwrap();
+    let rx = Async::new(rx).unwrap();
+    let tx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let tx_addr = tx.local_addr().unwrap();
+    let tx = Async::new(tx).unwrap();
+    assert_eq!(block_on(tx.send_to(b"named", &rx_addr)).unwrap(), 5);
+    let (n, from) = block_on(rx.recv_from(&mut buf)).unwrap();
+    assert_eq!(&buf[..n], b"named");
+    assert_eq!(from.as_pathname(), tx_addr.as_pathname());
+}
+
+#[test]
+fn seqpacket_accept() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = Async::new(listener).unwrap();
+    let client = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        let conn = UnixSeqpacket::connect(addr).unwrap();
+        conn.send(b"one").unwrap();
+        conn.send(b"two").unwrap();
+        conn
+    });
+
+    let (conn, _) = block_on(listener.accept()).unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(block_on(conn.recv(&mut buf)).unwrap(), 3);
+    assert_eq!(&buf[..3], b"one");
+    assert_eq!(block_on(conn.recv(&mut buf)).unwrap(), 3);
+    assert_eq!(&buf[..3], b"two");
+    block_on(conn.send(b"ack")).unwrap();
+    let client = client.join().unwrap();
+    assert_eq!(client.recv(&mut buf).unwrap(), 3);
+}
+
+#[test]
+fn stream_accept() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = Async::new(listener).unwrap();
+    let mut client = UnixStream::connect(addr).unwrap();
+
+    let (conn, _) = block_on(listener.accept()).unwrap();
+    client.write_all(b"hi").unwrap();
+    let mut buf = [0; 2];
+    block_on(conn.read_with(|mut conn| conn.read(&mut buf))).unwrap();
+    assert_eq!(&buf, b"hi");
+}
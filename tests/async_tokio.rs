@@ -0,0 +1,229 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "tokio")]
+
+extern crate af_unix;
+extern crate bytes;
+extern crate futures_core;
+extern crate futures_sink;
+extern crate tokio;
+
+use std::future;
+use std::io::{ErrorKind, Read, Write};
+use std::pin::Pin;
+use std::thread;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime::{Builder, Runtime};
+
+use af_unix::async_tokio::{AsyncUnixDatagram, AsyncUnixListener,
+                           AsyncUnixStream};
+use af_unix::{MessageTruncated, SockType, UnixDatagram, UnixListener,
+              UnixStream};
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().enable_io().build().unwrap()
+}
+
+#[test]
+fn stream_read_write() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let (a, mut b) = UnixStream::pair().unwrap();
+    let mut a = AsyncUnixStream::new(a).unwrap();
+    // more than the socket buffer holds, so each side has to wait for the
+    // other
+    let data = vec![7; 1 << 20];
+
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).unwrap();
+        b.write_all(&received).unwrap();
+        received
+    });
+    rt.block_on(a.write_all(&data)).unwrap();
+    rt.block_on(a.shutdown()).unwrap();
+    let mut echoed = Vec::new();
+    rt.block_on(a.read_to_end(&mut echoed)).unwrap();
+    assert!(reader.join().unwrap() == data);
+    assert!(echoed == data);
+}
+
+#[test]
+fn stream_readiness() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let (a, b) = AsyncUnixStream::pair().unwrap();
+    let mut buf = [0; 4];
+    assert_eq!(b.try_read(&mut buf).unwrap_err().kind(),
+               ErrorKind::WouldBlock);
+    rt.block_on(a.writable()).unwrap();
+    assert_eq!(a.try_write(b"ping").unwrap(), 4);
+    rt.block_on(b.readable()).unwrap();
+    assert_eq!(b.try_read(&mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"ping");
+
+    // the inner stream is handed back non-blocking
+    let mut b = b.into_inner();
+    assert_eq!(b.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn datagram_waits_for_peer() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    let b = AsyncUnixDatagram::new(b).unwrap();
+    let sender = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        a.send(b"late").unwrap();
+        a
+    });
+
+    let mut buf = [0; 8];
+    assert_eq!(rt.block_on(b.recv(&mut buf)).unwrap(), 4);
+    assert_eq!(&buf[..4], b"late");
+    let mut a = sender.join().unwrap();
+    assert_eq!(rt.block_on(b.send(b"reply")).unwrap(), 5);
+    assert_eq!(a.recv(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"reply");
+}
+
+#[test]
+fn datagram_reply_to_sender() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let server = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let server = AsyncUnixDatagram::new(server).unwrap();
+    let client = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let client = AsyncUnixDatagram::new(client).unwrap();
+    let server_addr = server.local_addr().unwrap();
+
+    rt.block_on(client.send_to(b"ping", &server_addr)).unwrap();
+    let mut buf = [0; 8];
+    let (n, from) = rt.block_on(server.recv_from(&mut buf)).unwrap();
+    assert_eq!(&buf[..n], b"ping");
+    assert_eq!(from.as_pathname(),
+               client.local_addr().unwrap().as_pathname());
+    assert_eq!(server.try_send_to(b"pong", &from).unwrap(), 4);
+    assert_eq!(rt.block_on(client.recv(&mut buf)).unwrap(), 4);
+    assert_eq!(&buf[..4], b"pong");
+    assert_eq!(client.try_recv(&mut buf).unwrap_err().kind(),
+               ErrorKind::WouldBlock);
+}
+
+#[test]
+fn datagram_truncated() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let (a, b) = AsyncUnixDatagram::pair().unwrap();
+    rt.block_on(a.send(b"too long")).unwrap();
+    let mut buf = [0; 3];
+    let err = rt.block_on(b.recv(&mut buf)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.get_ref().unwrap().downcast_ref::<MessageTruncated>()
+                   .unwrap().received(), 3);
+    assert_eq!(&buf, b"too");
+}
+
+#[test]
+fn listener_accept() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = AsyncUnixListener::new(listener).unwrap();
+    let client = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        let mut stream = UnixStream::connect(addr).unwrap();
+        stream.write_all(b"hi").unwrap();
+        stream
+    });
+
+    let (mut conn, _) = rt.block_on(listener.accept()).unwrap();
+    let mut buf = [0; 2];
+    rt.block_on(conn.read_exact(&mut buf)).unwrap();
+    assert_eq!(&buf, b"hi");
+    client.join().unwrap();
+}
+
+#[test]
+fn listener_incoming() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = AsyncUnixListener::new(listener).unwrap();
+    let _clients = (0..3u8).map(|i| {
+        let mut stream = UnixStream::connect(addr).unwrap();
+        stream.write_all(&[i]).unwrap();
+        stream
+    }).collect::<Vec<_>>();
+
+    // connections come out in the order they were made
+    let mut incoming = listener.incoming();
+    for i in 0..3 {
+        let mut conn = rt.block_on(future::poll_fn(|cx| {
+            Pin::new(&mut incoming).poll_next(cx)
+        })).unwrap().unwrap();
+        let mut buf = [0; 1];
+        rt.block_on(conn.read_exact(&mut buf)).unwrap();
+        assert_eq!(buf[0], i);
+    }
+    assert_eq!(incoming.size_hint(), (usize::MAX, None));
+}
+
+#[test]
+fn datagram_stream_and_sink() {
+    let rt = runtime();
+    let _guard = rt.enter();
+    let mut rx = AsyncUnixDatagram::new(
+        UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap()).unwrap();
+    let rx_addr = rx.local_addr().unwrap();
+    let mut tx = AsyncUnixDatagram::new(
+        UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap()).unwrap();
+    let tx_addr = tx.local_addr().unwrap();
+
+    for item in &[&b"first"[..], &[0; 3000][..]] {
+        let mut sink = Pin::new(&mut tx);
+        rt.block_on(future::poll_fn(|cx| sink.as_mut().poll_ready(cx)))
+            .unwrap();
+        sink.as_mut().start_send((Bytes::from(item.to_vec()), rx_addr))
+            .unwrap();
+    }
+    rt.block_on(future::poll_fn(|cx| Pin::new(&mut tx).poll_flush(cx)))
+        .unwrap();
+
+    // each item is a whole datagram, however long, with its sender
+    for expected in &[&b"first"[..], &[0; 3000][..]] {
+        let (buf, from) = rt.block_on(future::poll_fn(|cx| {
+            Pin::new(&mut rx).poll_next(cx)
+        })).unwrap().unwrap();
+        assert_eq!(&buf[..], *expected);
+        assert_eq!(from.as_pathname(), tx_addr.as_pathname());
+    }
+}
@@ -0,0 +1,427 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+mod common;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::thread;
+
+use af_unix::{SeqpacketListener, SockType, SocketAddr, UnixDatagram,
+              UnixListener, UnixSeqpacket, UnixStream};
+
+use common::test_dir;
+
+#[test]
+fn bind_atomic_keeps_live_socket() {
+    let dir = test_dir("atomic-live");
+    let path = dir.join("sock");
+    let live = UnixListener::bind(&path).unwrap();
+
+    let err = UnixListener::bind_atomic(&path, |_| Ok(())).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    UnixStream::connect(&path).unwrap();
+    live.accept().unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    drop(live);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn bind_atomic_local_addr() {
+    let dir = test_dir("atomic-addr");
+    let path = dir.join("sock");
+    let listener = UnixListener::bind_atomic(&path, |_| Ok(())).unwrap();
+    assert_eq!(listener.local_addr().unwrap().as_pathname(),
+               Some(path.as_path()));
+    assert_eq!(listener.try_clone().unwrap().local_addr().unwrap()
+                   .as_pathname(),
+               Some(path.as_path()));
+
+    drop(listener);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn bind_atomic_concurrent() {
+    let dir = test_dir("atomic-concurrent");
+    let path = dir.join("sock");
+    let threads: Vec<_> = (0..8).map(|_| {
+        let path = path.clone();
+        thread::spawn(move || UnixListener::bind_atomic(&path, |_| Ok(())))
+    }).collect();
+    let results: Vec<_> = threads.into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect();
+
+    let bound = results.iter().filter(|res| res.is_ok()).count();
+    assert_eq!(bound, 1);
+    for res in &results {
+        if let Err(ref err) = *res {
+            assert_eq!(err.kind(), ErrorKind::AddrInUse);
+        }
+    }
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    drop(results);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn bind_with_permissions_keeps_live_socket() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("permissions-live");
+    let path = dir.join("sock");
+    let live = UnixListener::bind(&path).unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+
+    let err = UnixListener::bind_with_permissions(&path, 0o600)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    let err = SeqpacketListener::bind_with_permissions(&path, 0o600)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    let err = UnixDatagram::bind_with_permissions(&path, 0o600,
+                                                  SockType::Dgram)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode(), mode);
+    UnixStream::connect(&path).unwrap();
+    live.accept().unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    drop(live);
+
+    let listener = UnixListener::bind_with_permissions(&path, 0o600)
+        .unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+               0o600);
+    assert_eq!(listener.local_addr().unwrap().as_pathname(),
+               Some(path.as_path()));
+
+    drop(listener);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn bind_reusing_stale_socket() {
+    let dir = test_dir("reusing");
+    let path = dir.join("sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let listener = UnixListener::bind_reusing(&path).unwrap();
+    UnixStream::connect(&path).unwrap();
+    listener.accept().unwrap();
+
+    let err = UnixListener::bind_reusing(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    UnixStream::connect(&path).unwrap();
+    listener.accept().unwrap();
+
+    drop(listener);
+    let _ = fs::remove_file(&path);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn non_utf8_path() {
+    let dir = test_dir("non-utf8");
+    let path = dir.join(OsStr::from_bytes(b"\xff\xfe.sock"));
+    let listener = UnixListener::bind(path.as_os_str()).unwrap();
+    assert_eq!(listener.local_addr().unwrap().as_pathname(),
+               Some(path.as_path()));
+    UnixStream::connect(&path).unwrap();
+    let (_, _) = listener.accept().unwrap();
+
+    let rx = UnixDatagram::bind(path.with_extension("dgram"),
+                                SockType::Dgram).unwrap();
+    let tx = UnixDatagram::unbound().unwrap();
+    tx.send_to_addr(b"x", path.with_extension("dgram").into_os_string())
+        .unwrap();
+    let mut buf = [0; 1];
+    rx.recv_from(&mut buf).unwrap();
+
+    // an interior null can't be passed to the kernel
+    let err = UnixListener::bind(dir.join("a\0b")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(!dir.join("a").exists());
+    drop(listener);
+    drop(rx);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bind_and_connect_addr() {
+    let dir = test_dir("addr");
+    let addr = SocketAddr::from_pathname(dir.join("stream")).unwrap();
+    let listener = UnixListener::bind_addr(&addr).unwrap();
+    UnixStream::connect_addr(&listener.local_addr().unwrap()).unwrap();
+    listener.accept().unwrap();
+
+    let addr = SocketAddr::from_pathname(dir.join("seqpacket")).unwrap();
+    let listener = SeqpacketListener::bind_addr(&addr).unwrap();
+    UnixSeqpacket::connect_addr(&addr).unwrap();
+    listener.accept().unwrap();
+
+    let addr = SocketAddr::from_pathname(dir.join("dgram")).unwrap();
+    let rx = UnixDatagram::bind_addr(&addr, SockType::Dgram).unwrap();
+    let mut tx = UnixDatagram::connect_addr(&addr, SockType::Dgram).unwrap();
+    tx.send(b"x").unwrap();
+    let mut buf = [0; 1];
+    rx.recv_from(&mut buf).unwrap();
+
+    // an unnamed address can't be connected to
+    let unnamed = UnixStream::pair().unwrap().0.local_addr().unwrap();
+    for err in &[UnixStream::connect_addr(&unnamed).unwrap_err(),
+                 UnixSeqpacket::connect_addr(&unnamed).unwrap_err(),
+                 UnixDatagram::connect_addr(&unnamed, SockType::Dgram)
+                     .unwrap_err()] {
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[test]
+fn bind_unnamed_addr() {
+    let unnamed = UnixStream::pair().unwrap().0.local_addr().unwrap();
+    let err = UnixListener::bind_addr(&unnamed).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn long_path() {
+    let dir = test_dir("long-path");
+    let deep = dir.join("d".repeat(60)).join("e".repeat(60));
+    fs::create_dir_all(&deep).unwrap();
+    let path = deep.join("stream.sock");
+    assert!(path.as_os_str().len() > 108);
+    assert_eq!(UnixListener::bind(&path).unwrap_err().kind(),
+               ErrorKind::InvalidInput);
+
+    let listener = UnixListener::bind_long_path(&path).unwrap();
+    assert!(path.exists());
+    assert_eq!(UnixStream::connect(&path).unwrap_err().kind(),
+               ErrorKind::InvalidInput);
+    UnixStream::connect_long_path(&path).unwrap();
+    listener.accept().unwrap();
+    // the file is still removed by its real path
+    drop(listener);
+    assert!(!path.exists());
+
+    let path = deep.join("seqpacket.sock");
+    let listener = SeqpacketListener::bind_long_path(&path).unwrap();
+    UnixSeqpacket::connect_long_path(&path).unwrap();
+    listener.accept().unwrap();
+
+    let path = deep.join("dgram.sock");
+    let rx = UnixDatagram::bind_long_path(&path, SockType::Dgram).unwrap();
+    let mut tx = UnixDatagram::connect_long_path(&path, SockType::Dgram)
+        .unwrap();
+    tx.send(b"x").unwrap();
+    let mut buf = [0; 1];
+    rx.recv_from(&mut buf).unwrap();
+    drop(rx);
+    assert!(!path.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unlink_on_drop() {
+    let dir = test_dir("unlink");
+    let path = dir.join("sock");
+
+    let listener = UnixListener::bind(&path).unwrap();
+    // other handles to the socket leave the file to the original
+    drop(listener.try_clone().unwrap());
+    assert!(path.exists());
+    drop(listener);
+    assert!(!path.exists());
+
+    let listener = SeqpacketListener::bind(&path).unwrap();
+    listener.close().unwrap();
+    assert!(!path.exists());
+
+    let mut sock = UnixDatagram::bind(&path, SockType::Dgram).unwrap();
+    sock.set_cleanup(false);
+    drop(sock);
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+
+    let mut listener = UnixListener::bind(&path).unwrap();
+    assert_eq!(listener.forget_path(), Some(path.clone()));
+    assert_eq!(listener.forget_path(), None);
+    drop(listener);
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+
+    // a file that has replaced the socket is left alone
+    let listener = UnixListener::bind(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::write(&path, b"not a socket").unwrap();
+    drop(listener);
+    assert_eq!(fs::read(&path).unwrap(), b"not a socket");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bind_reusing_other_types() {
+    let dir = test_dir("reusing-types");
+    let path = dir.join("sock");
+    drop(std::os::unix::net::UnixDatagram::bind(&path).unwrap());
+    let sock = UnixDatagram::bind_reusing(&path, SockType::Dgram).unwrap();
+    let err = UnixDatagram::bind_reusing(&path, SockType::Dgram)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    drop(sock);
+
+    let mut sock = UnixDatagram::bind(&path, SockType::Dgram).unwrap();
+    sock.set_cleanup(false);
+    drop(sock);
+    let listener = SeqpacketListener::bind_reusing(&path).unwrap();
+    UnixSeqpacket::connect(&path).unwrap();
+    listener.accept().unwrap();
+    drop(listener);
+
+    // anything but a socket is never removed
+    fs::write(&path, b"data").unwrap();
+    let err = UnixListener::bind_reusing(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    assert_eq!(fs::read(&path).unwrap(), b"data");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bind_with_permissions_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("permissions-mode");
+    let mode = |name| {
+        fs::metadata(dir.join(name)).unwrap().permissions().mode() & 0o777
+    };
+
+    // the mode is applied as given, whatever the umask
+    let _listener = SeqpacketListener::bind_with_permissions(
+        dir.join("seqpacket"), 0o660).unwrap();
+    assert_eq!(mode("seqpacket"), 0o660);
+    let _sock = UnixDatagram::bind_with_permissions(dir.join("dgram"), 0o666,
+                                                    SockType::Dgram)
+        .unwrap();
+    assert_eq!(mode("dgram"), 0o666);
+
+    // nothing is left behind at the temporary names they were bound at
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn set_socket_owner() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = test_dir("owner");
+    let path = dir.join("sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    listener.set_socket_owner(Some(uid), Some(gid)).unwrap();
+    listener.set_socket_owner(None, None).unwrap();
+
+    let meta = fs::metadata(&path).unwrap();
+    assert_eq!((meta.uid(), meta.gid()), (uid, gid));
+    if uid == 0 {
+        listener.set_socket_owner(Some(1), None).unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        assert_eq!((meta.uid(), meta.gid()), (1, gid));
+    } else {
+        let err = listener.set_socket_owner(Some(0), None).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+
+    // only a socket with a file has an owner to change
+    let unbound = UnixDatagram::unbound().unwrap();
+    let err = unbound.set_socket_owner(Some(uid), None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(listener);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[test]
+fn bind_at() {
+    let dir = test_dir("bind-at");
+    let handle = fs::File::open(&dir).unwrap();
+    // the directory is reached through the descriptor, even once renamed
+    let moved = dir.with_extension("moved");
+    fs::rename(&dir, &moved).unwrap();
+
+    let listener = UnixListener::bind_at(&handle, "stream").unwrap();
+    UnixStream::connect(moved.join("stream")).unwrap();
+    listener.accept().unwrap();
+    SeqpacketListener::bind_at(&handle, "seqpacket").unwrap();
+    UnixDatagram::bind_at(&handle, "dgram", SockType::Dgram).unwrap();
+    assert_eq!(UnixListener::bind_at(&handle, "stream").unwrap_err().kind(),
+               ErrorKind::AddrInUse);
+
+    for name in &["a/b", "..", "/abs", ""] {
+        let err = UnixListener::bind_at(&handle, name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+    // the files aren't unlinked on drop
+    drop(listener);
+    assert_eq!(fs::read_dir(&moved).unwrap().count(), 3);
+    fs::remove_dir_all(&moved).unwrap();
+}
+
+#[test]
+fn bind_in_tempdir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let a = UnixListener::bind_in_tempdir().unwrap();
+    let b = UnixListener::bind_in_tempdir().unwrap();
+    let path = a.local_addr().unwrap().as_pathname().unwrap().to_path_buf();
+    let dir = path.parent().unwrap().to_path_buf();
+    assert_ne!(Some(path.as_path()), b.local_addr().unwrap().as_pathname());
+    assert!(dir.starts_with(std::env::temp_dir()));
+    assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777,
+               0o700);
+
+    // the socket and its directory go with the socket
+    drop(a);
+    assert!(!dir.exists());
+    let sock = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let dir = sock.local_addr().unwrap().as_pathname().unwrap().parent()
+        .unwrap().to_path_buf();
+    drop(sock);
+    assert!(!dir.exists());
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let dir = listener.local_addr().unwrap().as_pathname().unwrap().parent()
+        .unwrap().to_path_buf();
+    listener.close().unwrap();
+    assert!(!dir.exists());
+}
@@ -0,0 +1,82 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "calloop")]
+
+extern crate af_unix;
+extern crate calloop;
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use calloop::{EventLoop, PostAction};
+
+use af_unix::calloop_source::{ListenerSource, SocketSource};
+use af_unix::{UnixListener, UnixStream};
+
+#[test]
+fn listener_source() {
+    let mut event_loop = EventLoop::<Vec<UnixStream>>::try_new().unwrap();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+    event_loop.handle().insert_source(ListenerSource::new(listener),
+                                      |(conn, _), _, conns| {
+        conns.push(conn);
+    }).unwrap();
+
+    // a non-blocking listener takes every pending connection per wakeup
+    let _clients = (0..3).map(|_| UnixStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+    let mut conns = Vec::new();
+    event_loop.dispatch(Some(Duration::from_secs(5)), &mut conns).unwrap();
+    assert_eq!(conns.len(), 3);
+}
+
+#[test]
+fn socket_source() {
+    // the bytes received and the number of callbacks
+    let mut event_loop = EventLoop::<(Vec<u8>, usize)>::try_new().unwrap();
+    let (mut a, b) = UnixStream::pair().unwrap();
+    event_loop.handle().insert_source(SocketSource::new(b),
+                                      |readiness, sock, state| {
+        assert!(readiness.readable);
+        let mut buf = [0; 16];
+        let n = (&**sock).read(&mut buf)?;
+        state.0.extend_from_slice(&buf[..n]);
+        state.1 += 1;
+        Ok(if n == 0 { PostAction::Remove } else { PostAction::Continue })
+    }).unwrap();
+
+    let mut state = (Vec::new(), 0);
+    a.write_all(b"hello").unwrap();
+    event_loop.dispatch(Some(Duration::from_secs(5)), &mut state).unwrap();
+    assert_eq!(state, (b"hello".to_vec(), 1));
+
+    // on end of file the callback removes the source, so it isn't called
+    // again even though the socket stays readable
+    drop(a);
+    event_loop.dispatch(Some(Duration::from_secs(5)), &mut state).unwrap();
+    event_loop.dispatch(Some(Duration::from_millis(10)), &mut state)
+        .unwrap();
+    assert_eq!(state, (b"hello".to_vec(), 2));
+}
@@ -0,0 +1,172 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use af_unix::{CancelHandle, SeqpacketListener, UnixListener, UnixSeqpacket,
+              UnixStream};
+
+#[test]
+fn accept_cancellable_shared_listener() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cancel = CancelHandle::new().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let threads: Vec<_> = (0..2).map(|_| {
+        let listener = listener.try_clone().unwrap();
+        let cancel = cancel.clone();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            loop {
+                match listener.accept_cancellable(&cancel) {
+                    Ok(_) => tx.send(true).unwrap(),
+                    Err(err) => {
+                        assert_eq!(err.kind(), ErrorKind::Interrupted);
+                        return;
+                    }
+                }
+            }
+        })
+    }).collect();
+
+    // both threads may be woken for one connection; the one that loses
+    // the race must go back to waiting rather than blocking in accept(2),
+    // where cancelling couldn't reach it
+    let mut conns = Vec::new();
+    for _ in 0..200 {
+        thread::sleep(Duration::from_millis(1));
+        conns.push(UnixStream::connect(addr).unwrap());
+        assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+
+    cancel.cancel().unwrap();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn shutdown_wakes_shared_listener() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = listener.shutdown_handle().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let threads: Vec<_> = (0..2).map(|_| {
+        let listener = listener.try_clone().unwrap();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                conn.unwrap();
+                tx.send(()).unwrap();
+            }
+            let err = listener.accept().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotConnected);
+        })
+    }).collect();
+
+    let mut conns = Vec::new();
+    for _ in 0..200 {
+        thread::sleep(Duration::from_millis(1));
+        conns.push(UnixStream::connect(addr).unwrap());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    shutdown.shutdown().unwrap();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn shutdown_keeps_nonblocking_mode() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let shutdown = listener.shutdown_handle().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    shutdown.shutdown().unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+fn connect_later(listener: &UnixListener) -> thread::JoinHandle<UnixStream> {
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        UnixStream::connect(addr).unwrap()
+    })
+}
+
+#[test]
+fn polled_listener_keeps_blocking_mode() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let cancel = CancelHandle::new().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let _conn = UnixStream::connect(addr).unwrap();
+    listener.accept_cancellable(&cancel).unwrap();
+
+    // the socket is non-blocking now, but accept() still waits
+    assert!(!listener.nonblocking().unwrap());
+    let client = connect_later(&listener);
+    listener.accept().unwrap();
+    client.join().unwrap();
+
+    let clone = listener.try_clone().unwrap();
+    assert!(!clone.nonblocking().unwrap());
+    let client = connect_later(&clone);
+    clone.accept().unwrap();
+    client.join().unwrap();
+
+    listener.set_nonblocking(true).unwrap();
+    assert!(clone.nonblocking().unwrap());
+    let err = clone.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn shutdown_wakes_seqpacket_listener() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = listener.shutdown_handle().unwrap();
+    assert!(!shutdown.is_shutdown());
+    let acceptor = thread::spawn(move || {
+        let (conn, _) = listener.accept().unwrap();
+        // blocks until the shutdown
+        let err = listener.accept().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+        conn
+    });
+
+    let client = UnixSeqpacket::connect(addr).unwrap();
+    client.send(b"x").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    shutdown.shutdown().unwrap();
+    assert!(shutdown.is_shutdown());
+    let mut buf = [0; 1];
+    assert_eq!(acceptor.join().unwrap().recv(&mut buf).unwrap(), 1);
+}
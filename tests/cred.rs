@@ -0,0 +1,192 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Both ends of each socket are in this process, so the credentials reported
+// for the peer are always our own.
+
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+extern crate af_unix;
+extern crate libc;
+
+use std::io::{IoSlice, IoSliceMut};
+
+use af_unix::{MsgFlags, RecvMsg, SocketAncillary, UCred, UnixDatagram,
+              UnixListener, UnixSeqpacket, UnixStream};
+
+fn own_creds() -> UCred {
+    unsafe {
+        UCred {
+            pid: Some(libc::getpid()),
+            uid: libc::getuid(),
+            gid: libc::getgid(),
+        }
+    }
+}
+
+fn creds(msg: &RecvMsg) -> Vec<UCred> {
+    msg.control().filter_map(|cmsg| cmsg.as_credentials())
+        .flatten().collect()
+}
+
+#[test]
+fn send_creds() {
+    let (tx, rx) = UnixDatagram::pair().unwrap();
+    rx.set_passcred(true).unwrap();
+
+    let mut space = vec![0; SocketAncillary::space_for_creds(1)];
+    let mut ancillary = SocketAncillary::new(&mut space);
+    let mut cred = own_creds();
+    cred.pid = None;
+    ancillary.add_creds(&[cred]).unwrap();
+    tx.send_msg(&[IoSlice::new(b"creds")], None, ancillary.as_bytes(),
+                MsgFlags::empty()).unwrap();
+
+    let mut buf = [0; 8];
+    let mut control = [0; 64];
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    assert_eq!(creds(&msg), [own_creds()]);
+}
+
+#[test]
+fn passcred() {
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    assert!(!rx.passcred().unwrap());
+    let mut buf = [0; 8];
+    let mut control = [0; 64];
+
+    tx.send(b"plain").unwrap();
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    assert!(creds(&msg).is_empty());
+
+    // with SO_PASSCRED the kernel attaches them unasked
+    rx.set_passcred(true).unwrap();
+    assert!(rx.passcred().unwrap());
+    tx.send(b"plain").unwrap();
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    assert_eq!(creds(&msg), [own_creds()]);
+}
+
+#[test]
+fn peer_cred() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    assert_eq!(a.peer_cred().unwrap(), own_creds());
+    let (a, _b) = UnixSeqpacket::pair().unwrap();
+    assert_eq!(a.peer_cred().unwrap(), own_creds());
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (conn, _) = listener.accept().unwrap();
+    assert_eq!(conn.peer_cred().unwrap(), own_creds());
+    assert_eq!(client.peer_cred().unwrap(), own_creds());
+}
+
+// Without a security module there is no context to report.
+#[test]
+fn peer_sec_context() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    match a.peer_sec_context() {
+        Ok(context) => assert!(!context.is_empty()),
+        Err(err) => assert_eq!(err.raw_os_error(), Some(libc::ENOPROTOOPT)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn peer_groups() {
+    let mut groups = vec![0; 1024];
+    let n = unsafe {
+        libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr())
+    };
+    assert!(n >= 0);
+    groups.truncate(n as usize);
+    groups.sort();
+
+    let (a, _b) = UnixStream::pair().unwrap();
+    let mut peer = a.peer_groups().unwrap();
+    peer.sort();
+    assert_eq!(peer, groups);
+}
+
+// The pid a pidfd refers to, as /proc reports it.
+#[cfg(target_os = "linux")]
+fn pidfd_pid(pidfd: libc::c_int) -> libc::pid_t {
+    let info = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", pidfd))
+        .unwrap();
+    info.lines().find_map(|line| line.strip_prefix("Pid:"))
+        .unwrap().trim().parse().unwrap()
+}
+
+// Kernels before 6.5 lack pidfd support, which is reported as
+// ENOPROTOOPT.
+#[cfg(target_os = "linux")]
+#[test]
+fn peer_pidfd() {
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    let pidfd = match rx.peer_pidfd() {
+        Ok(pidfd) => pidfd,
+        Err(err) => {
+            assert_eq!(err.raw_os_error(), Some(libc::ENOPROTOOPT));
+            return;
+        }
+    };
+    let pid = unsafe { libc::getpid() };
+    assert_eq!(pidfd_pid(pidfd.as_raw_fd()), pid);
+
+    rx.set_passpidfd(true).unwrap();
+    assert!(rx.passpidfd().unwrap());
+    tx.send(b"pidfd").unwrap();
+    let mut buf = [0; 8];
+    let mut control = [0; 64];
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    let pidfd = msg.control().find_map(|cmsg| cmsg.as_pidfd()).unwrap();
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd) };
+    assert_eq!(pidfd_pid(pidfd.as_raw_fd()), pid);
+}
+
+// Without a security module the kernel has no label to attach.
+#[test]
+fn passsec() {
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    assert!(!rx.passsec().unwrap());
+    rx.set_passsec(true).unwrap();
+    assert!(rx.passsec().unwrap());
+
+    tx.send(b"label").unwrap();
+    let mut buf = [0; 8];
+    let mut control = [0; 256];
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    assert_eq!(msg.bytes(), 5);
+    for label in msg.control().filter_map(|cmsg| cmsg.as_security_label()) {
+        assert!(!label.is_empty());
+    }
+    rx.set_passsec(false).unwrap();
+    assert!(!rx.passsec().unwrap());
+}
@@ -0,0 +1,190 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, IoSlice, IoSliceMut};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use af_unix::{MessageTruncated, SockType, UnixDatagram};
+
+#[test]
+fn pair() {
+    let (mut a, mut b) = UnixDatagram::pair().unwrap();
+    a.send(b"to b").unwrap();
+    b.send(b"to a").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(b.recv(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"to b");
+    assert_eq!(a.recv(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"to a");
+}
+
+#[test]
+fn recv_from_sender() {
+    let mut tx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    tx.sendto(b"named", rx.local_addr().unwrap()).unwrap();
+    let mut buf = [0; 8];
+    let (n, from) = rx.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"named");
+    assert_eq!(from.as_pathname(),
+               tx.local_addr().unwrap().as_pathname());
+}
+
+#[test]
+fn reply_to_sender() {
+    let server = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let client = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    client.send_to_addr(b"ping", server.local_addr().unwrap()).unwrap();
+
+    let mut buf = [0; 8];
+    let (_, from) = server.recv_from(&mut buf).unwrap();
+    server.send_to_addr(b"pong", from).unwrap();
+    assert_eq!(client.recv_from(&mut buf).unwrap().0, 4);
+    assert_eq!(&buf[..4], b"pong");
+}
+
+#[test]
+fn unbound_connect_to() {
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let mut tx = UnixDatagram::unbound().unwrap();
+    let err = tx.send(b"early").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    tx.connect_to(rx.local_addr().unwrap()).unwrap();
+    tx.send(b"anon").unwrap();
+    let mut buf = [0; 8];
+    let (n, from) = rx.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"anon");
+    assert!(from.is_unnamed());
+}
+
+#[test]
+fn disconnect() {
+    let first = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let second = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let mut tx = UnixDatagram::connect(first.local_addr().unwrap(),
+                                       SockType::Dgram).unwrap();
+    tx.send(b"one").unwrap();
+
+    tx.disconnect().unwrap();
+    let err = tx.send(b"two").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(tx.peer_addr().is_err());
+    tx.send_to_addr(b"three", second.local_addr().unwrap()).unwrap();
+
+    let mut buf = [0; 8];
+    assert_eq!(first.recv_from(&mut buf).unwrap().0, 3);
+    assert_eq!(second.recv_from(&mut buf).unwrap().0, 5);
+    assert_eq!(&buf[..5], b"three");
+}
+
+#[test]
+fn vectored() {
+    let (a, b) = UnixDatagram::pair().unwrap();
+    let bufs = [IoSlice::new(b"one "), IoSlice::new(b"datagram")];
+    assert_eq!(a.send_vectored(&bufs).unwrap(), 12);
+    a.send_vectored(&[IoSlice::new(b"next")]).unwrap();
+
+    let (mut head, mut tail) = ([0; 4], [0; 16]);
+    let mut bufs = [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)];
+    assert_eq!(b.recv_vectored(&mut bufs).unwrap(), 12);
+    assert_eq!((&*bufs[0], &bufs[1][..8]), (&b"one "[..], &b"datagram"[..]));
+    assert_eq!(b.recv_vectored(&mut bufs).unwrap(), 4);
+    assert_eq!(&*bufs[0], b"next");
+}
+
+#[test]
+fn peek_len() {
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    a.send(b"sized").unwrap();
+    a.send(b"").unwrap();
+
+    assert_eq!(b.peek_len().unwrap(), 5);
+    // peeking leaves the datagram queued
+    assert_eq!(b.peek_len().unwrap(), 5);
+    let mut buf = vec![0; b.peek_len().unwrap()];
+    assert_eq!(b.recv_from(&mut buf).unwrap().0, 5);
+    assert_eq!(b.peek_len().unwrap(), 0);
+
+    b.recv_from(&mut []).unwrap();
+    b.set_nonblocking(true).unwrap();
+    let err = b.peek_len().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn truncated() {
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    a.send(b"too long").unwrap();
+    a.send(b"next").unwrap();
+
+    let mut buf = [0; 3];
+    let err = b.recv_from(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let trunc = err.get_ref().unwrap()
+        .downcast_ref::<MessageTruncated>().unwrap();
+    assert_eq!(trunc.received(), 3);
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        assert_eq!(trunc.message_len(), Some(8));
+    }
+    assert_eq!(&buf, b"too");
+
+    let mut buf = [0; 8];
+    assert_eq!(b.recv_from(&mut buf).unwrap().0, 4);
+    assert_eq!(&buf[..4], b"next");
+}
+
+#[test]
+fn recv_deadline() {
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    let mut buf = [0; 8];
+
+    // a queued datagram is returned even once the deadline has passed
+    a.send(b"queued").unwrap();
+    assert_eq!(b.recv_deadline(&mut buf, Instant::now()).unwrap(), 6);
+    let err = b.recv_deadline(&mut buf, Instant::now()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    // one deadline bounds a series of receives
+    let sender = thread::spawn(move || {
+        a.send(b"one").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        a.send(b"two").unwrap();
+        a
+    });
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(200);
+    let mut received = 0;
+    let err = loop {
+        match b.recv_from_deadline(&mut buf, deadline) {
+            Ok(_) => received += 1,
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert_eq!(received, 2);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    sender.join().unwrap();
+}
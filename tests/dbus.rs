@@ -0,0 +1,116 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use af_unix::DbusAddr;
+
+#[test]
+fn parse() {
+    let cases: &[(&str, DbusAddr)] = &[
+        ("unix:path=/run/dbus/system_bus_socket",
+         DbusAddr::Path(PathBuf::from("/run/dbus/system_bus_socket"))),
+        ("unix:abstract=/tmp/dbus-XXXX",
+         DbusAddr::Abstract(b"/tmp/dbus-XXXX".to_vec())),
+        ("unix:tmpdir=/tmp", DbusAddr::Tmpdir(PathBuf::from("/tmp"))),
+        ("unix:dir=/run/user/1000",
+         DbusAddr::Dir(PathBuf::from("/run/user/1000"))),
+        ("unix:path=/run/my%20bus%2csock",
+         DbusAddr::Path(PathBuf::from("/run/my bus,sock"))),
+        ("unix:abstract=a%00b", DbusAddr::Abstract(b"a\0b".to_vec())),
+        ("unix:guid=0123456789abcdef,path=/run/bus",
+         DbusAddr::Path(PathBuf::from("/run/bus"))),
+        ("unix:path=/run/bus,", DbusAddr::Path(PathBuf::from("/run/bus"))),
+    ];
+    for &(addr, ref expected) in cases {
+        assert_eq!(&DbusAddr::parse(addr).unwrap(), expected, "{}", addr);
+        assert_eq!(&addr.parse::<DbusAddr>().unwrap(), expected);
+    }
+}
+
+#[test]
+fn parse_invalid() {
+    for addr in &["", "tcp:host=localhost,port=1234", "unix:",
+                  "unix:guid=0123", "unix:path", "unix:path=/a,path=/b",
+                  "unix:path=/a,abstract=b", "unix:runtime=yes",
+                  "unix:nonsense=1,path=/a", "unix:path=/run/%zz"] {
+        let err = DbusAddr::parse(addr).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", addr);
+    }
+}
+
+#[test]
+fn parse_list() {
+    let addrs = DbusAddr::parse_list(
+        "tcp:host=localhost,port=1234;unix:path=/run/bus;\
+         launchd:env=DBUS_LAUNCHD_SESSION_BUS_SOCKET;unix:abstract=bus;")
+        .unwrap();
+    assert_eq!(addrs, vec![DbusAddr::Path(PathBuf::from("/run/bus")),
+                           DbusAddr::Abstract(b"bus".to_vec())]);
+
+    assert_eq!(DbusAddr::parse_list("").unwrap(), vec![]);
+    assert_eq!(DbusAddr::parse_list("tcp:host=localhost").unwrap(), vec![]);
+    let err = DbusAddr::parse_list("unix:path=/a;unix:bad=1").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn display_round_trip() {
+    let addrs = [
+        DbusAddr::Path(PathBuf::from("/run/dbus/system_bus_socket")),
+        DbusAddr::Path(PathBuf::from("/run/my bus;sock,=%")),
+        DbusAddr::Abstract(b"\0\xff name".to_vec()),
+        DbusAddr::Tmpdir(PathBuf::from("/tmp")),
+        DbusAddr::Dir(PathBuf::from("/run/user/1000/a-b_c.d\\*")),
+    ];
+    for addr in &addrs {
+        let text = addr.to_string();
+        assert!(!text[5..].contains([';', ',', ' ']),
+                "{}", text);
+        assert_eq!(&DbusAddr::parse(&text).unwrap(), addr, "{}", text);
+    }
+    assert_eq!(addrs[1].to_string(),
+               "unix:path=/run/my%20bus%3bsock%2c%3d%25");
+}
+
+#[test]
+fn listen_in_tmpdir() {
+    let dir = std::env::temp_dir();
+    let (listener, addr) = DbusAddr::Tmpdir(dir.clone()).listen().unwrap();
+    let path = match DbusAddr::parse(&addr).unwrap() {
+        DbusAddr::Path(path) => path,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(path.parent(), Some(dir.as_path()));
+
+    let conn = DbusAddr::parse(&addr).unwrap().connect().unwrap();
+    listener.accept().unwrap();
+    drop(conn);
+
+    let err = DbusAddr::Tmpdir(dir).connect().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(listener);
+    assert!(!path.exists());
+}
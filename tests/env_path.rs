@@ -0,0 +1,109 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::env;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use af_unix::{expand_path, EnvPath, ToUnixAddr};
+
+// Each test uses its own variables, as tests run in parallel.
+fn expand(template: &str) -> PathBuf {
+    expand_path(template).unwrap()
+}
+
+#[test]
+fn plain_and_dollars() {
+    assert_eq!(expand("/run/app.sock"), PathBuf::from("/run/app.sock"));
+    assert_eq!(expand("/run/$$app"), PathBuf::from("/run/$app"));
+    assert_eq!(expand("$$$$"), PathBuf::from("$$"));
+    assert_eq!(expand("/run/$app/$"), PathBuf::from("/run/$app/$"));
+    assert_eq!(expand("/run/{app}"), PathBuf::from("/run/{app}"));
+}
+
+#[test]
+fn variables() {
+    env::set_var("AF_UNIX_TEST_VARS_DIR", "/run/user/1000");
+    env::set_var("AF_UNIX_TEST_VARS_EMPTY", "");
+    env::remove_var("AF_UNIX_TEST_VARS_UNSET");
+
+    assert_eq!(expand("${AF_UNIX_TEST_VARS_DIR}/app.sock"),
+               PathBuf::from("/run/user/1000/app.sock"));
+    assert_eq!(expand("${AF_UNIX_TEST_VARS_EMPTY}/app.sock"),
+               PathBuf::from("/app.sock"));
+
+    let err = expand_path("${AF_UNIX_TEST_VARS_UNSET}/app.sock").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    let err = expand_path("${}/app.sock").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn fallbacks() {
+    env::set_var("AF_UNIX_TEST_FALLBACK_DIR", "/run/user/1000");
+    env::set_var("AF_UNIX_TEST_FALLBACK_EMPTY", "");
+    env::remove_var("AF_UNIX_TEST_FALLBACK_UNSET");
+
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_DIR:-/tmp}/a"),
+               PathBuf::from("/run/user/1000/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-/tmp}/a"),
+               PathBuf::from("/tmp/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_EMPTY:-/tmp}/a"),
+               PathBuf::from("/tmp/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-}a"),
+               PathBuf::from("a"));
+
+    // a fallback may contain references, braces and escaped dollars
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-\
+                       ${AF_UNIX_TEST_FALLBACK_DIR}/x}/a"),
+               PathBuf::from("/run/user/1000/x/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-\
+                       ${AF_UNIX_TEST_FALLBACK_EMPTY:-/tmp}}/a"),
+               PathBuf::from("/tmp/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-{x}/a"),
+               PathBuf::from("{x/a"));
+    assert_eq!(expand("${AF_UNIX_TEST_FALLBACK_UNSET:-$${x}}/a"),
+               PathBuf::from("${x}/a"));
+
+    let err = expand_path("${AF_UNIX_TEST_FALLBACK_UNSET:-\
+                           ${AF_UNIX_TEST_FALLBACK_UNSET}}").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn unterminated() {
+    for template in &["${", "/run/${AF_UNIX_TEST", "${A:-${B}"] {
+        let err = expand_path(template).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", template);
+    }
+}
+
+#[test]
+fn env_path() {
+    env::set_var("AF_UNIX_TEST_ENV_PATH_DIR", "/run/app");
+    let addr = EnvPath("${AF_UNIX_TEST_ENV_PATH_DIR}/sock").to_unix_addr()
+        .unwrap();
+    assert_eq!(addr.as_pathname(),
+               Some(PathBuf::from("/run/app/sock").as_path()));
+}
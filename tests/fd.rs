@@ -0,0 +1,183 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use af_unix::{UnixDatagram, UnixListener, UnixSeqpacket, UnixStream};
+
+fn fd_flags(fd: RawFd) -> (bool, bool) {
+    let fd_flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    let fl_flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    assert!(fd_flags >= 0 && fl_flags >= 0);
+    (fd_flags & libc::FD_CLOEXEC != 0, fl_flags & libc::O_NONBLOCK != 0)
+}
+
+#[test]
+fn close_and_drop() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    a.close().unwrap();
+    assert_eq!(b.read(&mut [0; 1]).unwrap(), 0);
+
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    drop(a);
+    assert_eq!(b.recv(&mut [0; 1]).unwrap(), 0);
+
+    let (a, mut b) = UnixDatagram::pair().unwrap();
+    a.close().unwrap();
+    let err = b.send(b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn raw_fd_round_trip() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    let fd = a.as_raw_fd();
+    let raw = a.into_raw_fd();
+    assert_eq!(raw, fd);
+    let mut a = unsafe { UnixStream::from_raw_fd(raw) };
+    a.write_all(b"x").unwrap();
+    let mut buf = [0; 1];
+    b.read_exact(&mut buf).unwrap();
+
+    // a datagram socket adopted from a raw descriptor remembers its peer
+    let (a, b) = UnixDatagram::pair().unwrap();
+    let mut a = unsafe { UnixDatagram::from_raw_fd(a.into_raw_fd()) };
+    a.send(b"y").unwrap();
+    assert_eq!(b.recv_from(&mut buf).unwrap().0, 1);
+    assert_eq!(&buf, b"y");
+}
+
+#[test]
+fn owned_fd_round_trip() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    assert_eq!(listener.as_fd().as_raw_fd(), listener.as_raw_fd());
+
+    // converting away from the crate's type doesn't unlink the socket file
+    let fd = OwnedFd::from(listener);
+    let listener = UnixListener::from(fd);
+    let mut client = UnixStream::connect(addr).unwrap();
+    let (conn, _) = listener.accept().unwrap();
+
+    let mut conn = UnixStream::from(OwnedFd::from(conn));
+    conn.write_all(b"x").unwrap();
+    let mut buf = [0; 1];
+    client.read_exact(&mut buf).unwrap();
+
+    // nor does dropping it, as it no longer tracks the file
+    drop(listener);
+    let path = addr.as_pathname().unwrap();
+    fs::remove_file(path).unwrap();
+    fs::remove_dir(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn try_clone() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    let mut clone = a.try_clone().unwrap();
+    assert_ne!(clone.as_raw_fd(), a.as_raw_fd());
+    drop(a);
+    clone.write_all(b"x").unwrap();
+    let mut buf = [0; 1];
+    b.read_exact(&mut buf).unwrap();
+
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    a.try_clone().unwrap().send(b"y").unwrap();
+    assert_eq!(b.recv(&mut buf).unwrap(), 1);
+    assert_eq!(&buf, b"y");
+
+    let (a, b) = UnixDatagram::pair().unwrap();
+    a.try_clone().unwrap().send(b"z").unwrap();
+    assert_eq!(b.recv_from(&mut buf).unwrap().0, 1);
+    assert_eq!(&buf, b"z");
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let clone = listener.try_clone().unwrap();
+    let _client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    clone.accept().unwrap();
+}
+
+#[test]
+fn set_nonblocking() {
+    let (mut a, _b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let err = a.read(&mut [0; 1]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let (a, _b) = UnixSeqpacket::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let err = a.recv(&mut [0; 1]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let (a, _b) = UnixDatagram::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let err = a.recv_from(&mut [0; 1]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn creation_flags() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (conn, _) = listener.accept().unwrap();
+    let (a, b) = UnixDatagram::pair().unwrap();
+    for fd in &[listener.as_raw_fd(), client.as_raw_fd(), conn.as_raw_fd(),
+                a.as_raw_fd(), b.as_raw_fd()] {
+        assert_eq!(fd_flags(*fd), (true, false));
+    }
+
+    let (a, b) = UnixStream::pair_nonblocking().unwrap();
+    let (c, d) = UnixSeqpacket::pair_nonblocking().unwrap();
+    for fd in &[a.as_raw_fd(), b.as_raw_fd(), c.as_raw_fd(), d.as_raw_fd()] {
+        assert_eq!(fd_flags(*fd), (true, true));
+    }
+}
+
+#[test]
+fn descriptor_flags() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    assert!(a.cloexec().unwrap());
+    assert!(!a.nonblocking().unwrap());
+
+    a.set_cloexec(false).unwrap();
+    a.set_nonblocking(true).unwrap();
+    assert!(!a.cloexec().unwrap());
+    assert!(a.nonblocking().unwrap());
+    assert_eq!(fd_flags(a.as_raw_fd()), (false, true));
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    assert!(!listener.nonblocking().unwrap());
+    listener.set_nonblocking(true).unwrap();
+    assert!(listener.nonblocking().unwrap());
+}
@@ -0,0 +1,102 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Write};
+use std::os::unix::io::AsFd;
+use std::thread;
+use std::time::Duration;
+
+use af_unix::handoff::{self, HandoffInfo};
+use af_unix::{UnixListener, UnixStream};
+
+fn header(magic: &[u8; 4], version: u8) -> Vec<u8> {
+    let mut msg = magic.to_vec();
+    msg.extend_from_slice(&[version, 0, 0, 0]);
+    msg.extend_from_slice(&128i32.to_le_bytes());
+    msg.extend_from_slice(&0u32.to_le_bytes());
+    msg
+}
+
+fn recv_err(msg: &[u8]) -> std::io::Error {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    tx.send_fds(&[listener.as_fd()], &msg[..1]).unwrap();
+    (&tx).write_all(&msg[1..]).unwrap();
+    handoff::recv_listener::<UnixListener>(&rx).unwrap_err()
+}
+
+#[test]
+fn share_listener() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    handoff::share_listener(&tx, &listener).unwrap();
+
+    let (shared, info) =
+        handoff::recv_listener::<UnixListener>(&rx).unwrap();
+    assert_eq!(info, HandoffInfo { path: None, backlog: None });
+    UnixStream::connect(listener.local_addr().unwrap()).unwrap();
+    shared.accept().unwrap();
+}
+
+#[test]
+fn bad_header() {
+    let err = recv_err(&header(b"XXXX", 1));
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "not a listener handoff message");
+
+    let err = recv_err(&header(b"AFUH", 2));
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "unsupported listener handoff version");
+}
+
+#[test]
+fn missing_listener() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    (&tx).write_all(&header(b"AFUH", 1)).unwrap();
+    let err = handoff::recv_listener::<UnixListener>(&rx).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn share_polled_listener() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let shutdown = listener.shutdown_handle().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let conn = UnixStream::connect(addr).unwrap();
+    listener.accept().unwrap();
+    drop(conn);
+    handoff::share_listener(&tx, &listener).unwrap();
+
+    // the socket was left non-blocking, but the receiver still blocks
+    let (shared, _) = handoff::recv_listener::<UnixListener>(&rx).unwrap();
+    assert!(!shared.nonblocking().unwrap());
+    let client = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        UnixStream::connect(addr).unwrap()
+    });
+    shared.accept().unwrap();
+    client.join().unwrap();
+    shutdown.shutdown().unwrap();
+}
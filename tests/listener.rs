@@ -0,0 +1,87 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Read, Write};
+
+use af_unix::{SeqpacketListener, UnixListener, UnixSeqpacket,
+              UnixStream};
+
+#[test]
+fn incoming() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let clients: Vec<_> = (0..3u8).map(|i| {
+        let mut client = UnixStream::connect(addr).unwrap();
+        client.write_all(&[i]).unwrap();
+        client
+    }).collect();
+
+    let mut seen: Vec<u8> = listener.incoming().take(3).map(|conn| {
+        let mut buf = [0; 1];
+        conn.unwrap().read_exact(&mut buf).unwrap();
+        buf[0]
+    }).collect();
+    seen.sort();
+    assert_eq!(seen, [0, 1, 2]);
+
+    // a reference to the listener is itself iterable
+    let _client = UnixStream::connect(addr).unwrap();
+    (&listener).into_iter().next().unwrap().unwrap();
+    drop(clients);
+}
+
+#[test]
+fn accept_peer_addr() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let _client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (conn, addr) = listener.accept().unwrap();
+    assert!(addr.is_unnamed());
+    assert!(conn.peer_addr().unwrap().is_unnamed());
+    assert_eq!(conn.local_addr().unwrap().as_pathname(),
+               listener.local_addr().unwrap().as_pathname());
+
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let _client = UnixSeqpacket::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (_, addr) = listener.accept().unwrap();
+    assert!(addr.is_unnamed());
+}
+
+#[test]
+fn accept_nonblocking() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    let mut client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (mut conn, _) = listener.accept().unwrap();
+    client.write_all(b"x").unwrap();
+    let mut buf = [0; 1];
+    conn.read_exact(&mut buf).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
@@ -0,0 +1,88 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "mio")]
+
+extern crate af_unix;
+extern crate mio;
+
+use std::io::Write;
+use std::time::Duration;
+
+use mio::{Events, Interest, Poll, Token};
+
+use af_unix::{UnixDatagram, UnixListener, UnixSeqpacket, UnixStream};
+
+fn tokens(poll: &mut Poll, events: &mut Events) -> Vec<Token> {
+    poll.poll(events, Some(Duration::from_secs(5))).unwrap();
+    events.iter().map(|event| event.token()).collect()
+}
+
+#[test]
+fn listener_and_stream() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(8);
+    let mut listener = UnixListener::bind_in_tempdir().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    poll.registry().register(&mut listener, Token(0), Interest::READABLE)
+        .unwrap();
+
+    let mut client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(0)]);
+    let (mut conn, _) = listener.accept().unwrap();
+    conn.set_nonblocking(true).unwrap();
+    poll.registry().register(&mut conn, Token(1), Interest::READABLE)
+        .unwrap();
+
+    client.write_all(b"x").unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(1)]);
+
+    // once deregistered, a ready socket reports nothing
+    poll.registry().deregister(&mut conn).unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(50))).unwrap();
+    assert!(events.is_empty());
+
+    poll.registry().register(&mut conn, Token(2), Interest::WRITABLE)
+        .unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(2)]);
+    poll.registry().reregister(&mut conn, Token(3), Interest::READABLE)
+        .unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(3)]);
+}
+
+#[test]
+fn packet_sockets() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(8);
+    let (mut tx, mut rx) = UnixDatagram::pair().unwrap();
+    let (seq_tx, mut seq_rx) = UnixSeqpacket::pair().unwrap();
+    poll.registry().register(&mut rx, Token(0), Interest::READABLE)
+        .unwrap();
+    poll.registry().register(&mut seq_rx, Token(1), Interest::READABLE)
+        .unwrap();
+
+    tx.send(b"x").unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(0)]);
+    seq_tx.send(b"x").unwrap();
+    assert_eq!(tokens(&mut poll, &mut events), [Token(1)]);
+}
@@ -0,0 +1,359 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, SystemTime};
+
+use af_unix::{ControlTruncated, FromSocketFd, MsgFlags, SockType,
+              SocketAncillary, UnixDatagram, UnixListener, UnixSeqpacket,
+              UnixStream};
+
+#[test]
+fn recv_msg_scatter() {
+    let tx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    tx.send_to_addr(b"scatter", rx.local_addr().unwrap()).unwrap();
+
+    let mut head = [0; 4];
+    let mut tail = [0; 2];
+    let mut control = [0; 64];
+    for &flags in &[MsgFlags::PEEK, MsgFlags::empty()] {
+        let mut bufs = [IoSliceMut::new(&mut head),
+                        IoSliceMut::new(&mut tail)];
+        let msg = rx.recv_msg(&mut bufs, &mut control, flags).unwrap();
+        assert_eq!(msg.bytes(), 6);
+        assert!(msg.flags().contains(MsgFlags::TRUNC));
+        assert_eq!(msg.addr().as_pathname(),
+                   tx.local_addr().unwrap().as_pathname());
+        assert_eq!(msg.control().count(), 0);
+        assert_eq!((&head, &tail), (b"scat", b"te"));
+    }
+
+    // the peek left the message queued, and the second receive consumed it
+    let flags = MsgFlags::DONTWAIT;
+    let mut bufs = [IoSliceMut::new(&mut head)];
+    let err = rx.recv_msg(&mut bufs, &mut control, flags).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn fds_round_trip() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let (mut ours, theirs) = UnixStream::pair().unwrap();
+
+    assert_eq!(tx.send_fds(&[theirs.as_fd()], b"fd").unwrap(), 2);
+    drop(theirs);
+
+    let mut buf = [0; 8];
+    let mut fds = Vec::new();
+    assert_eq!(rx.recv_fds(&mut buf, &mut fds).unwrap(), 2);
+    assert_eq!(&buf[..2], b"fd");
+    assert_eq!(fds.len(), 1);
+
+    let mut received = UnixStream::from(fds.pop().unwrap());
+    assert!(received.cloexec().unwrap());
+    received.write_all(b"over").unwrap();
+    let mut buf = [0; 4];
+    ours.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"over");
+}
+
+#[test]
+fn control_truncated_closes_fds() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let mut ours = Vec::new();
+    let mut theirs = Vec::new();
+    for _ in 0..3 {
+        let (a, b) = UnixStream::pair().unwrap();
+        ours.push(a);
+        theirs.push(b);
+    }
+
+    let fds: Vec<_> = theirs.iter().map(|sock| sock.as_fd()).collect();
+    tx.send_fds(&fds, b"three").unwrap();
+    drop(fds);
+    drop(theirs);
+
+    // room for only some of the descriptors, since padding rounds the space
+    // for one up
+    let mut buf = [0; 8];
+    let mut control = vec![0; SocketAncillary::space_for_fds(1)];
+    let err = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          Default::default()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let trunc = err.get_ref().unwrap()
+        .downcast_ref::<ControlTruncated>().unwrap();
+    assert_eq!(trunc.bytes(), 5);
+
+    // with every copy of the far ends closed, each stream sees end of file
+    for mut sock in ours {
+        sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(sock.read(&mut [0; 1]).unwrap(), 0);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn recv_multiple_short_batch() {
+    use af_unix::MsgBuffer;
+
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    tx.send(b"one").unwrap();
+    tx.send(b"second").unwrap();
+
+    let mut bufs = [[0; 4]; 4];
+    let mut msgs: Vec<MsgBuffer> =
+        bufs.iter_mut().map(|buf| MsgBuffer::new(buf)).collect();
+    assert_eq!(rx.recv_multiple(&mut msgs, None).unwrap(), 2);
+    assert_eq!(msgs[0].data(), b"one");
+    assert!(!msgs[0].flags().contains(MsgFlags::TRUNC));
+    assert_eq!(msgs[1].data(), b"seco");
+    assert!(msgs[1].flags().contains(MsgFlags::TRUNC));
+    assert_eq!(msgs[2].bytes(), 0);
+    assert!(msgs[2].addr().is_none());
+
+    let timeout = Some(Duration::from_millis(10));
+    let err = rx.recv_multiple(&mut msgs, timeout).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn recv_msg_inheritable() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let (_ours, theirs) = UnixStream::pair().unwrap();
+    let mut control = vec![0; SocketAncillary::space_for_fds(1)];
+
+    for &cloexec in &[true, false] {
+        tx.send_fds(&[theirs.as_fd()], b"fd").unwrap();
+        let mut buf = [0; 8];
+        let mut bufs = [IoSliceMut::new(&mut buf)];
+        let msg = if cloexec {
+            rx.recv_msg(&mut bufs, &mut control, Default::default())
+        } else {
+            rx.recv_msg_inheritable(&mut bufs, &mut control,
+                                    Default::default())
+        }.unwrap();
+
+        let mut fds = msg.control().filter_map(|cmsg| cmsg.as_rights())
+            .flat_map(|rights| rights.collect::<Vec<_>>());
+        let fd = unsafe { OwnedFd::from_raw_fd(fds.next().unwrap()) };
+        assert!(fds.next().is_none());
+        assert_eq!(UnixStream::from(fd).cloexec().unwrap(), cloexec);
+    }
+}
+
+#[test]
+fn send_msg_gather() {
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let addr = rx.local_addr().unwrap();
+    let tx = UnixDatagram::unbound().unwrap();
+
+    let bufs = [IoSlice::new(b"gath"), IoSlice::new(b"ered")];
+    assert_eq!(tx.send_msg(&bufs, Some(&addr), &[], MsgFlags::empty())
+                   .unwrap(), 8);
+    let mut buf = [0; 16];
+    assert_eq!(rx.recv_from(&mut buf).unwrap().0, 8);
+    assert_eq!(&buf[..8], b"gathered");
+
+    // a connected socket needs no address
+    let (a, b) = UnixStream::pair().unwrap();
+    assert_eq!(a.send_msg(&bufs[..1], None, &[], MsgFlags::empty())
+                   .unwrap(), 4);
+    (&b).read_exact(&mut buf[..4]).unwrap();
+    assert_eq!(&buf[..4], b"gath");
+}
+
+#[test]
+fn ancillary_builder() {
+    let (_ours, theirs) = UnixStream::pair().unwrap();
+    let mut space = vec![0; SocketAncillary::space_for_fds(1) * 2];
+    let mut ancillary = SocketAncillary::new(&mut space);
+    assert!(ancillary.is_empty());
+    ancillary.add_fds(&[theirs.as_fd()]).unwrap();
+    ancillary.add_fds(&[theirs.as_fd()]).unwrap();
+    assert_eq!(ancillary.len(), ancillary.capacity());
+    let err = ancillary.add_fds(&[theirs.as_fd()]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let msgs: Vec<_> = ancillary.messages()
+        .map(|cmsg| cmsg.as_rights().unwrap().collect::<Vec<_>>())
+        .collect();
+    assert_eq!(msgs, [[theirs.as_raw_fd()], [theirs.as_raw_fd()]]);
+
+    ancillary.clear();
+    assert!(ancillary.is_empty());
+    ancillary.add_fds(&[theirs.as_fd()]).unwrap();
+
+    let (tx, rx) = UnixStream::pair().unwrap();
+    tx.send_msg(&[IoSlice::new(b"fd")], None, ancillary.as_bytes(),
+                MsgFlags::empty()).unwrap();
+    let mut buf = [0; 2];
+    let mut fds = Vec::new();
+    rx.recv_fds(&mut buf, &mut fds).unwrap();
+    assert_eq!(fds.len(), 1);
+}
+
+#[test]
+fn send_socket() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    tx.send_socket(&listener, b"l").unwrap();
+    let mut buf = [0; 1];
+    let (n, received) = rx.recv_socket::<UnixListener>(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"l");
+    let _client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    received.accept().unwrap();
+
+    // the received socket must be of the type asked for
+    tx.send_socket(&listener, b"l").unwrap();
+    let err = rx.recv_socket::<UnixStream>(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    tx.send_socket(&tx, b"s").unwrap();
+    let err = rx.recv_socket::<UnixSeqpacket>(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    (&tx).write_all(b"x").unwrap();
+    let err = rx.recv_socket::<UnixStream>(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let file = std::fs::File::open("/dev/null").unwrap();
+    let err = UnixStream::from_socket_fd(file.into()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn recv_fds_inheritable() {
+    let (tx, rx) = UnixStream::pair().unwrap();
+    let (_ours, theirs) = UnixStream::pair().unwrap();
+    tx.send_fds(&[theirs.as_fd()], b"fd").unwrap();
+
+    let mut buf = [0; 2];
+    let mut fds = Vec::new();
+    rx.recv_fds_inheritable(&mut buf, &mut fds).unwrap();
+    let fd = fds.pop().unwrap();
+    assert!(!UnixStream::from(fd).cloexec().unwrap());
+}
+
+#[test]
+fn timestamps() {
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    let mut buf = [0; 8];
+    let mut control = [0; 64];
+    tx.send(b"untimed").unwrap();
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    assert!(msg.timestamp().is_none());
+
+    rx.set_timestamping(true).unwrap();
+    assert!(rx.timestamping().unwrap());
+    // timestamps have microsecond precision
+    let before = SystemTime::now() - Duration::from_micros(1);
+    tx.send(b"timed").unwrap();
+    let after = SystemTime::now();
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    let stamp = msg.timestamp().unwrap();
+    assert!(before <= stamp && stamp <= after);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn timestamps_ns() {
+    let (mut tx, rx) = UnixDatagram::pair().unwrap();
+    rx.set_timestamping_ns(true).unwrap();
+    assert!(rx.timestamping_ns().unwrap());
+
+    let before = SystemTime::now();
+    tx.send(b"timed").unwrap();
+    let after = SystemTime::now();
+    let mut buf = [0; 8];
+    let mut control = [0; 64];
+    let msg = rx.recv_msg(&mut [IoSliceMut::new(&mut buf)], &mut control,
+                          MsgFlags::empty()).unwrap();
+    let stamp = msg.timestamp().unwrap();
+    assert!(before <= stamp && stamp <= after);
+}
+
+#[test]
+fn per_call_flags() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let mut buf = [0; 8];
+    let err = b.recv_with_flags(&mut buf, MsgFlags::DONTWAIT).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert!(!b.nonblocking().unwrap());
+
+    a.send_with_flags(b"peek", MsgFlags::empty()).unwrap();
+    assert_eq!(b.recv_with_flags(&mut buf, MsgFlags::PEEK).unwrap(), 4);
+    assert_eq!(b.recv_with_flags(&mut buf, MsgFlags::empty()).unwrap(), 4);
+    assert_eq!(&buf[..4], b"peek");
+
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let tx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    tx.send_to_with_flags(b"to", &rx.local_addr().unwrap(),
+                          MsgFlags::DONTWAIT).unwrap();
+    for &flags in &[MsgFlags::PEEK, MsgFlags::empty()] {
+        let (n, from) = rx.recv_from_with_flags(&mut buf, flags).unwrap();
+        assert_eq!(&buf[..n], b"to");
+        assert_eq!(from.as_pathname(),
+                   tx.local_addr().unwrap().as_pathname());
+    }
+    let err = rx.recv_from_with_flags(&mut buf, MsgFlags::DONTWAIT)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn send_multiple() {
+    use af_unix::OutgoingMsg;
+
+    let first = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let second = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let (first_addr, second_addr) =
+        (first.local_addr().unwrap(), second.local_addr().unwrap());
+    let tx = UnixDatagram::unbound().unwrap();
+
+    let one = [IoSlice::new(b"o"), IoSlice::new(b"ne")];
+    let two = [IoSlice::new(b"two")];
+    let msgs = [OutgoingMsg::new(&one, Some(&first_addr)),
+                OutgoingMsg::new(&two, Some(&second_addr)),
+                OutgoingMsg::new(&two, Some(&first_addr))];
+    assert_eq!(tx.send_multiple(&msgs).unwrap(), 3);
+
+    let mut buf = [0; 8];
+    assert_eq!(first.recv_from(&mut buf).unwrap().0, 3);
+    assert_eq!(&buf[..3], b"one");
+    assert_eq!(second.recv_from(&mut buf).unwrap().0, 3);
+    assert_eq!(&buf[..3], b"two");
+    assert_eq!(first.recv_from(&mut buf).unwrap().0, 3);
+    assert_eq!(&buf[..3], b"two");
+
+    let (_, unnamed) = UnixDatagram::pair().unwrap();
+    let unnamed = unnamed.local_addr().unwrap();
+    let err = tx.send_multiple(&[OutgoingMsg::new(&two, Some(&unnamed))])
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,147 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Read, Write};
+
+use af_unix::{AbstractName, SeqpacketListener, SockType, SocketAddr,
+              UnixDatagram, UnixListener, UnixSeqpacket, UnixStream};
+
+// A name no other test process is using
+fn name(test: &str) -> Vec<u8> {
+    format!("af_unix-test-{}-{}", test, std::process::id()).into_bytes()
+}
+
+#[test]
+fn abstract_addr() {
+    let addr = SocketAddr::from_abstract_name(b"a\0b").unwrap();
+    assert_eq!(addr.as_abstract_name(), Some(&b"a\0b"[..]));
+    assert!(addr.as_pathname().is_none());
+    assert!(!addr.is_unnamed());
+
+    // the leading null takes one byte of the 108 in sun_path
+    assert!(SocketAddr::from_abstract_name(vec![b'x'; 107]).is_ok());
+    let err = SocketAddr::from_abstract_name(vec![b'x'; 108]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn stream() {
+    let name = name("stream");
+    let listener = UnixListener::bind_abstract(&name).unwrap();
+    assert_eq!(listener.local_addr().unwrap().as_abstract_name(),
+               Some(&name[..]));
+    // the name is taken until the listener is closed
+    let err = UnixListener::bind_abstract(&name).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+    let mut client = UnixStream::connect_abstract(&name).unwrap();
+    let (mut conn, _) = listener.accept().unwrap();
+    client.write_all(b"hi").unwrap();
+    let mut buf = [0; 2];
+    conn.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hi");
+
+    drop(listener);
+    let err = UnixStream::connect_abstract(&name).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    UnixListener::bind_abstract(&name).unwrap();
+}
+
+#[test]
+fn seqpacket() {
+    let name = name("seqpacket");
+    let listener = SeqpacketListener::bind_abstract(&name).unwrap();
+    let client = UnixSeqpacket::connect_abstract(&name).unwrap();
+    let (conn, _) = listener.accept().unwrap();
+    client.send(b"packet").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(conn.recv(&mut buf).unwrap(), 6);
+}
+
+#[test]
+fn datagram() {
+    let name = name("datagram");
+    let rx = UnixDatagram::bind_abstract(&name, SockType::Dgram).unwrap();
+    let mut tx = UnixDatagram::connect_abstract(&name, SockType::Dgram)
+        .unwrap();
+    tx.send(b"dgram").unwrap();
+    let mut buf = [0; 8];
+    let (n, from) = rx.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"dgram");
+    assert!(from.is_unnamed());
+}
+
+#[test]
+fn autobind() {
+    // the kernel picks a unique name of five hex digits
+    let listener = UnixListener::autobind().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let name = addr.as_abstract_name().unwrap();
+    assert_eq!(name.len(), 5);
+    assert!(name.iter().all(|b| b.is_ascii_hexdigit()));
+    assert_ne!(SeqpacketListener::autobind().unwrap().local_addr().unwrap()
+                   .as_abstract_name(),
+               Some(name));
+
+    let _client = UnixStream::connect(addr).unwrap();
+    let (_, peer) = listener.accept().unwrap();
+    assert!(peer.is_unnamed());
+}
+
+#[test]
+fn reply_to_autobound_sender() {
+    let server = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    // binding to an unnamed address autobinds too
+    let unnamed = UnixDatagram::unbound().unwrap().local_addr().unwrap();
+    let client = UnixDatagram::bind_addr(&unnamed, SockType::Dgram).unwrap();
+    client.send_to_addr(b"ping", server.local_addr().unwrap()).unwrap();
+
+    let mut buf = [0; 8];
+    let (_, from) = server.recv_from(&mut buf).unwrap();
+    assert!(from.as_abstract_name().is_some());
+    assert_eq!(from.as_abstract_name(),
+               client.local_addr().unwrap().as_abstract_name());
+    server.send_to_addr(b"pong", from).unwrap();
+    assert_eq!(client.recv_from(&mut buf).unwrap().0, 4);
+    assert!(UnixDatagram::autobind(SockType::Dgram).unwrap().local_addr()
+                .unwrap().as_abstract_name().is_some());
+}
+
+#[test]
+fn generic_abstract_addr() {
+    // any function taking a ToUnixAddr takes an abstract name too
+    let name = name("generic");
+    let listener = UnixListener::bind(AbstractName(&name)).unwrap();
+    let _client = UnixStream::connect(AbstractName(&name)).unwrap();
+    listener.accept().unwrap();
+
+    let rx = UnixDatagram::bind(AbstractName(&name), SockType::Dgram)
+        .unwrap();
+    let tx = UnixDatagram::unbound().unwrap();
+    tx.send_to_addr(b"x", AbstractName(&name)).unwrap();
+    let mut buf = [0; 1];
+    assert_eq!(rx.recv_from(&mut buf).unwrap().0, 1);
+}
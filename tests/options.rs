@@ -0,0 +1,111 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+use std::io::Write;
+
+use af_unix::{UnixDatagram, UnixListener, UnixSeqpacket, UnixStream};
+
+#[test]
+fn buffer_sizes() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    a.set_send_buffer_size(64 << 10).unwrap();
+    a.set_recv_buffer_size(32 << 10).unwrap();
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        assert_eq!(a.send_buffer_size().unwrap(), 64 << 10);
+        assert_eq!(a.recv_buffer_size().unwrap(), 32 << 10);
+    } else {
+        assert!(a.send_buffer_size().unwrap() >= 64 << 10);
+        assert!(a.recv_buffer_size().unwrap() >= 32 << 10);
+    }
+}
+
+#[test]
+fn bytes_available() {
+    let (mut a, b) = UnixStream::pair().unwrap();
+    assert_eq!(b.bytes_available().unwrap(), 0);
+    a.write_all(b"hello").unwrap();
+    a.write_all(b"world").unwrap();
+    assert_eq!(b.bytes_available().unwrap(), 10);
+
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    a.send(b"packet").unwrap();
+    assert_eq!(b.bytes_available().unwrap(), 6);
+
+    // Linux reports only the next datagram
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    a.send(b"first").unwrap();
+    a.send(b"second").unwrap();
+    let available = b.bytes_available().unwrap();
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        assert_eq!(available, 5);
+    } else {
+        assert!(available >= 5);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos",
+          target_os = "ios"))]
+#[test]
+fn outgoing_queue_len() {
+    use std::io::Read;
+
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    assert_eq!(a.outgoing_queue_len().unwrap(), 0);
+    a.write_all(&[0; 1000]).unwrap();
+    // Linux counts the memory the data occupies, not just its payload
+    assert!(a.outgoing_queue_len().unwrap() >= 1000);
+
+    b.read_exact(&mut [0; 1000]).unwrap();
+    assert_eq!(a.outgoing_queue_len().unwrap(), 0);
+}
+
+#[test]
+fn raw_options() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let ty: libc::c_int = unsafe {
+        listener.get_option(libc::SOL_SOCKET, libc::SO_TYPE).unwrap()
+    };
+    assert_eq!(ty, libc::SOCK_STREAM);
+
+    let (a, _b) = UnixStream::pair().unwrap();
+    unsafe {
+        a.set_option(libc::SOL_SOCKET, libc::SO_RCVBUF,
+                     32768 as libc::c_int).unwrap();
+    }
+    let size: libc::c_int = unsafe {
+        a.get_option(libc::SOL_SOCKET, libc::SO_RCVBUF).unwrap()
+    };
+    assert_eq!(a.recv_buffer_size().unwrap(),
+               if cfg!(any(target_os = "linux", target_os = "android")) {
+                   size as usize / 2
+               } else {
+                   size as usize
+               });
+
+    let err = unsafe {
+        a.get_option::<libc::c_int>(libc::SOL_SOCKET, -1).unwrap_err()
+    };
+    assert!(err.raw_os_error().is_some());
+}
@@ -0,0 +1,133 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use af_unix::{Interest, PollSet, UnixDatagram, UnixListener, UnixStream};
+
+#[test]
+fn wait_readable() {
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let start = Instant::now();
+    assert!(!b.wait_readable(Some(Duration::from_millis(50))).unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        a.write_all(b"x").unwrap();
+        a
+    });
+    assert!(b.wait_readable(None).unwrap());
+    writer.join().unwrap();
+
+    // a hang-up counts too, as the next read returns at once
+    let (c, d) = UnixStream::pair().unwrap();
+    drop(c);
+    assert!(d.wait_readable(Some(Duration::from_secs(5))).unwrap());
+}
+
+#[test]
+fn wait_writable() {
+    let (mut a, b) = UnixDatagram::pair().unwrap();
+    assert!(a.wait_writable(Some(Duration::from_secs(5))).unwrap());
+
+    // fill the peer's queue so there's no room until it's drained
+    a.set_nonblocking(true).unwrap();
+    b.set_nonblocking(true).unwrap();
+    loop {
+        match a.send(&[0; 512]) {
+            Ok(_) => (),
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => panic!("send: {}", err),
+        }
+    }
+    assert!(!a.wait_writable(Some(Duration::from_millis(50))).unwrap());
+    let mut buf = [0; 512];
+    while b.recv_from(&mut buf).is_ok() {}
+    assert!(a.wait_writable(Some(Duration::from_secs(5))).unwrap());
+}
+
+#[test]
+fn listener_wait_readable() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    assert!(!listener.wait_readable(Some(Duration::from_millis(10)))
+                .unwrap());
+    let _client = UnixStream::connect(listener.local_addr().unwrap())
+        .unwrap();
+    assert!(listener.wait_readable(Some(Duration::from_secs(5))).unwrap());
+}
+
+fn ready(set: &mut PollSet, timeout: Duration) -> Vec<usize> {
+    let mut tokens = set.wait(Some(timeout)).unwrap()
+        .map(|event| event.token()).collect::<Vec<_>>();
+    tokens.sort();
+    tokens
+}
+
+#[test]
+fn poll_set() {
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let (mut c, d) = UnixStream::pair().unwrap();
+    let mut set = PollSet::new();
+    assert!(set.is_empty());
+    set.add(&b, 1, Interest::READABLE).unwrap();
+    set.add(&d, 2, Interest::READABLE).unwrap();
+    assert_eq!(set.len(), 2);
+    assert_eq!(set.add(&d, 2, Interest::READABLE).unwrap_err().kind(),
+               ErrorKind::AlreadyExists);
+
+    assert!(ready(&mut set, Duration::from_millis(10)).is_empty());
+    c.write_all(b"x").unwrap();
+    assert_eq!(ready(&mut set, Duration::from_secs(5)), [2]);
+    a.write_all(b"x").unwrap();
+    assert_eq!(ready(&mut set, Duration::from_secs(5)), [1, 2]);
+
+    set.modify(1, Interest::READABLE | Interest::WRITABLE).unwrap();
+    let event = set.wait(None).unwrap().find(|ev| ev.token() == 1).unwrap();
+    assert!(event.is_readable() && event.is_writable());
+
+    assert_eq!(set.remove(2).unwrap(), d.as_raw_fd());
+    assert_eq!(set.remove(2).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(set.modify(2, Interest::READABLE).unwrap_err().kind(),
+               ErrorKind::NotFound);
+    assert_eq!(ready(&mut set, Duration::from_secs(5)), [1]);
+    assert_eq!(format!("{:?}", Interest::READABLE | Interest::WRITABLE),
+               "READABLE | WRITABLE");
+}
+
+#[test]
+fn poll_set_hup() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let mut set = PollSet::new();
+    set.add(&b, 7, Interest::READABLE).unwrap();
+    drop(a);
+    let event = set.wait(Some(Duration::from_secs(5))).unwrap().next()
+        .unwrap();
+    assert_eq!(event.token(), 7);
+    assert!(event.is_hup());
+    assert!(!event.is_error());
+}
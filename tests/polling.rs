@@ -0,0 +1,74 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "polling")]
+
+extern crate af_unix;
+extern crate polling;
+
+use std::io::Write;
+use std::time::Duration;
+
+use polling::{Event, Events, PollMode, Poller};
+
+use af_unix::polling_ext::Registration;
+use af_unix::UnixStream;
+
+fn keys(poller: &Poller, timeout: Duration) -> Vec<usize> {
+    let mut events = Events::new();
+    poller.wait(&mut events, Some(timeout)).unwrap();
+    events.iter().map(|event| event.key).collect()
+}
+
+#[test]
+fn oneshot() {
+    let poller = Poller::new().unwrap();
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let reg = Registration::new(&poller, b, Event::readable(1)).unwrap();
+
+    a.write_all(b"x").unwrap();
+    assert_eq!(keys(&poller, Duration::from_secs(5)), [1]);
+    // disarmed until modified
+    assert!(keys(&poller, Duration::from_millis(10)).is_empty());
+    reg.modify(Event::readable(2)).unwrap();
+    assert_eq!(keys(&poller, Duration::from_secs(5)), [2]);
+}
+
+#[test]
+fn level_and_removal() {
+    let poller = Poller::new().unwrap();
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let reg = Registration::with_mode(&poller, b, Event::readable(1),
+                                      PollMode::Level).unwrap();
+    a.write_all(b"x").unwrap();
+    assert_eq!(keys(&poller, Duration::from_secs(5)), [1]);
+    assert_eq!(keys(&poller, Duration::from_secs(5)), [1]);
+
+    // once taken back out the socket is no longer watched, and can be
+    // registered afresh
+    let b = reg.into_inner().unwrap();
+    assert!(keys(&poller, Duration::from_millis(10)).is_empty());
+    let reg = Registration::new(&poller, b, Event::readable(3)).unwrap();
+    assert_eq!(keys(&poller, Duration::from_secs(5)), [3]);
+    drop(reg);
+    assert!(keys(&poller, Duration::from_millis(10)).is_empty());
+}
@@ -0,0 +1,141 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use af_unix::pool::Pool;
+use af_unix::UnixListener;
+
+#[test]
+fn keeps_connection_after_timeout() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let path = listener.local_addr().unwrap();
+    let path = path.as_pathname().unwrap();
+    let pool = Pool::new();
+
+    let mut conn = pool.get(path).unwrap();
+    let server = listener.accept().unwrap().0;
+    conn.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let err = conn.read(&mut [0; 1]).unwrap_err();
+    assert!(err.kind() == ErrorKind::WouldBlock
+            || err.kind() == ErrorKind::TimedOut);
+    drop(conn);
+    assert_eq!(pool.idle_count(), 1);
+
+    // a real error discards it
+    let mut conn = pool.get(path).unwrap();
+    assert_eq!(pool.idle_count(), 0);
+    drop(server);
+    let err = conn.write(b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    drop(conn);
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn max_idle_of_zero() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let path = listener.local_addr().unwrap();
+    let pool = Pool::new().max_idle(0);
+
+    drop(pool.get(path.as_pathname().unwrap()).unwrap());
+    listener.accept().unwrap();
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn reuses_idle_connections() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let other = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let path = addr.as_pathname().unwrap();
+    let pool = Pool::new().max_idle(1);
+
+    let mut conn = pool.get(path).unwrap();
+    let mut server = listener.accept().unwrap().0;
+    conn.write_all(b"one").unwrap();
+    drop(conn);
+    assert_eq!(pool.idle_count(), 1);
+
+    // the same connection comes back, so no new one is accepted
+    let mut conn = pool.get(path).unwrap();
+    assert_eq!(pool.idle_count(), 0);
+    listener.set_nonblocking(true).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    listener.set_nonblocking(false).unwrap();
+    conn.write_all(b"two").unwrap();
+    let mut buf = [0; 6];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"onetwo");
+
+    // a connection to another path isn't shared, and only one is kept
+    let second = pool.get(path).unwrap();
+    let _server2 = listener.accept().unwrap();
+    let elsewhere = pool.get(other.local_addr().unwrap().as_pathname()
+                             .unwrap()).unwrap();
+    other.accept().unwrap();
+    drop(conn);
+    drop(second);
+    drop(elsewhere);
+    assert_eq!(pool.idle_count(), 2);
+
+    pool.clear();
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn drops_unusable_connections() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let path = addr.as_pathname().unwrap();
+    let pool = Pool::new().max_idle_time(Duration::from_millis(20));
+
+    // the server hung up while it was idle
+    drop(pool.get(path).unwrap());
+    drop(listener.accept().unwrap());
+    let conn = pool.get(path).unwrap();
+    let mut server = listener.accept().unwrap().0;
+
+    // the server sent something unasked for
+    drop(conn);
+    server.write_all(b"?").unwrap();
+    let conn = pool.get(path).unwrap();
+    let _server = listener.accept().unwrap();
+
+    // it sat idle for too long
+    drop(conn);
+    thread::sleep(Duration::from_millis(40));
+    let conn = pool.get(path).unwrap();
+    let _server = listener.accept().unwrap();
+
+    conn.discard();
+    assert_eq!(pool.idle_count(), 0);
+    let conn = pool.get(path).unwrap();
+    listener.accept().unwrap();
+    let _stream = conn.into_inner();
+    assert_eq!(pool.idle_count(), 0);
+}
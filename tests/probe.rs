@@ -0,0 +1,105 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+mod common;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use af_unix::{probe, ProbeStatus, SeqpacketListener, SockType, UnixDatagram,
+              UnixListener, UnixSeqpacket, UnixStream};
+
+use common::test_dir;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn probe_live_sockets() {
+    let stream = UnixListener::bind_in_tempdir().unwrap();
+    let seqpacket = SeqpacketListener::bind_in_tempdir().unwrap();
+    let dgram = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+
+    // the probe connects as a stream, which the others refuse by type
+    for addr in &[stream.local_addr().unwrap(),
+                  seqpacket.local_addr().unwrap(),
+                  dgram.local_addr().unwrap()] {
+        assert_eq!(probe(addr, TIMEOUT).unwrap(), ProbeStatus::Listening);
+    }
+    stream.accept().unwrap();
+}
+
+#[test]
+fn probe_dead_paths() {
+    let dir = test_dir("probe-dead");
+    let stale = dir.join("stale");
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+    let file = dir.join("file");
+    fs::write(&file, b"").unwrap();
+
+    assert_eq!(probe(&stale, TIMEOUT).unwrap(),
+               ProbeStatus::RefusingConnections);
+    assert_eq!(probe(&file, TIMEOUT).unwrap(),
+               ProbeStatus::RefusingConnections);
+    assert_eq!(probe(dir.join("missing"), TIMEOUT).unwrap(),
+               ProbeStatus::PathMissing);
+    assert_eq!(probe(file.join("sock"), TIMEOUT).unwrap(),
+               ProbeStatus::PathMissing);
+
+    fs::remove_file(&stale).unwrap();
+    fs::remove_file(&file).unwrap();
+    fs::remove_dir(&dir).unwrap();
+}
+
+// A live socket of another type must not be taken for a stale one.
+#[test]
+fn bind_reusing_other_type() {
+    let dir = test_dir("reusing-other-type");
+    let path = dir.join("sock");
+
+    let live = SeqpacketListener::bind(&path).unwrap();
+    let err = UnixListener::bind_reusing(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    let err = UnixDatagram::bind_reusing(&path, SockType::Dgram)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    UnixSeqpacket::connect(&path).unwrap();
+    live.accept().unwrap();
+    drop(live);
+
+    let live = UnixDatagram::bind(&path, SockType::Dgram).unwrap();
+    let err = UnixListener::bind_reusing(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    let err = SeqpacketListener::bind_reusing(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    UnixDatagram::connect(&path, SockType::Dgram).unwrap();
+    drop(live);
+
+    let listener = UnixListener::bind_reusing(&path).unwrap();
+    UnixStream::connect(&path).unwrap();
+    listener.accept().unwrap();
+    drop(listener);
+    let _ = fs::remove_file(&path);
+    fs::remove_dir(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(feature = "reactor")]
+
+extern crate af_unix;
+
+use std::io::Write;
+use std::time::Duration;
+
+use af_unix::reactor::{Reactor, Trigger};
+use af_unix::{Interest, UnixStream};
+
+fn tokens(reactor: &mut Reactor, timeout: Duration) -> Vec<usize> {
+    let mut tokens = Vec::new();
+    let n = reactor.run_once(Some(timeout), |event| {
+        assert!(event.is_readable());
+        tokens.push(event.token());
+    }).unwrap();
+    assert_eq!(n, tokens.len());
+    tokens.sort();
+    tokens
+}
+
+#[test]
+fn level_triggered() {
+    let mut reactor = Reactor::new().unwrap();
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let (mut c, d) = UnixStream::pair().unwrap();
+    reactor.register(&b, 1, Interest::READABLE, Trigger::Level).unwrap();
+    reactor.register(&d, 2, Interest::READABLE, Trigger::Level).unwrap();
+
+    assert!(tokens(&mut reactor, Duration::from_millis(10)).is_empty());
+    a.write_all(b"x").unwrap();
+    c.write_all(b"x").unwrap();
+    assert_eq!(tokens(&mut reactor, Duration::from_secs(5)), [1, 2]);
+    // still unread, so reported again
+    assert_eq!(tokens(&mut reactor, Duration::from_secs(5)), [1, 2]);
+
+    reactor.reregister(&b, 3, Interest::READABLE, Trigger::Level).unwrap();
+    reactor.deregister(&d).unwrap();
+    assert_eq!(tokens(&mut reactor, Duration::from_secs(5)), [3]);
+}
+
+#[test]
+fn edge_triggered() {
+    let mut reactor = Reactor::new().unwrap();
+    let (mut a, b) = UnixStream::pair().unwrap();
+    reactor.register(&b, 1, Interest::READABLE, Trigger::Edge).unwrap();
+
+    a.write_all(b"x").unwrap();
+    assert_eq!(tokens(&mut reactor, Duration::from_secs(5)), [1]);
+    assert!(tokens(&mut reactor, Duration::from_millis(10)).is_empty());
+    a.write_all(b"y").unwrap();
+    assert_eq!(tokens(&mut reactor, Duration::from_secs(5)), [1]);
+}
+
+#[test]
+fn hang_up() {
+    let mut reactor = Reactor::new().unwrap();
+    let (a, b) = UnixStream::pair().unwrap();
+    reactor.register(&b, 1, Interest::READABLE, Trigger::Level).unwrap();
+    drop(a);
+    let mut hup = false;
+    reactor.run_once(Some(Duration::from_secs(5)), |event| {
+        hup = event.is_hup();
+    }).unwrap();
+    assert!(hup);
+}
@@ -0,0 +1,172 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::ErrorKind;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use af_unix::{MessageTruncated, SeqpacketListener, UnixSeqpacket};
+
+#[test]
+fn packet_boundaries() {
+    let (tx, rx) = UnixSeqpacket::pair().unwrap();
+    tx.send(b"first").unwrap();
+    tx.send(b"second").unwrap();
+    tx.send(b"").unwrap();
+
+    let mut buf = [0; 16];
+    assert_eq!(rx.recv(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"first");
+    assert_eq!(rx.recv(&mut buf).unwrap(), 6);
+    assert_eq!(&buf[..6], b"second");
+    assert_eq!(rx.recv(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn pair() {
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    a.send(b"to b").unwrap();
+    b.send(b"to a").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(b.recv(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"to b");
+    assert_eq!(a.recv(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"to a");
+}
+
+#[test]
+fn listener_accept() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let client = UnixSeqpacket::connect(listener.local_addr().unwrap())
+        .unwrap();
+    let (conn, addr) = listener.accept().unwrap();
+    assert!(addr.is_unnamed());
+
+    client.send(b"hello").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(conn.recv(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+    conn.send(b"bye").unwrap();
+    assert_eq!(client.recv(&mut buf).unwrap(), 3);
+    drop(conn);
+    assert_eq!(client.recv(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn connect_timeout() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let timeout = Duration::from_millis(20);
+    UnixSeqpacket::connect_timeout(addr, timeout).unwrap();
+
+    // with no room in the backlog, the attempt times out
+    listener.listen(0).unwrap();
+    let mut queued = Vec::new();
+    let err = loop {
+        match UnixSeqpacket::connect_timeout(addr, timeout) {
+            Ok(conn) => queued.push(conn),
+            Err(err) => break err,
+        }
+        assert!(queued.len() < 10);
+    };
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    listener.accept().unwrap();
+    UnixSeqpacket::connect_timeout(addr, timeout).unwrap();
+}
+
+#[test]
+fn peek_len() {
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    a.send(b"sized").unwrap();
+    assert_eq!(b.peek_len().unwrap(), 5);
+    let mut buf = vec![0; b.peek_len().unwrap()];
+    assert_eq!(b.recv(&mut buf).unwrap(), 5);
+}
+
+#[test]
+fn packet_truncated() {
+    let (tx, rx) = UnixSeqpacket::pair().unwrap();
+    tx.send(b"too long").unwrap();
+    tx.send(b"next").unwrap();
+
+    let mut buf = [0; 3];
+    let err = rx.recv(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let trunc = err.get_ref().unwrap()
+        .downcast_ref::<MessageTruncated>().unwrap();
+    assert_eq!(trunc.received(), 3);
+    assert_eq!(&buf, b"too");
+
+    // the rest of the packet was discarded
+    let mut buf = [0; 8];
+    assert_eq!(rx.recv(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"next");
+}
+
+#[test]
+fn start_connect_backlog_full() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.listen(0).unwrap();
+
+    // with the backlog full the attempt stays pending until there's room
+    let mut queued = Vec::new();
+    let mut pending = loop {
+        let pending = UnixSeqpacket::start_connect(addr).unwrap();
+        match pending.finish_connect() {
+            Ok(conn) => queued.push(conn),
+            Err((pending, err)) => {
+                assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                break pending;
+            }
+        }
+        assert!(queued.len() < 10);
+    };
+    listener.accept().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let conn = loop {
+        match pending.finish_connect() {
+            Ok(conn) => break conn,
+            Err((again, err)) => {
+                assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                assert!(Instant::now() < deadline);
+                pending = again;
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+    conn.send(b"x").unwrap();
+}
+
+#[test]
+fn recv_deadline() {
+    let (a, b) = UnixSeqpacket::pair().unwrap();
+    let mut buf = [0; 8];
+    a.send(b"packet").unwrap();
+    let deadline = Instant::now() + Duration::from_millis(50);
+    assert_eq!(b.recv_deadline(&mut buf, deadline).unwrap(), 6);
+    let err = b.recv_deadline(&mut buf, deadline).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
@@ -0,0 +1,312 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use af_unix::server::{ErrorAction, ErrorSource, LimitMode, LimitedListener,
+                      Server};
+use af_unix::{SeqpacketListener, UnixListener, UnixSeqpacket, UnixStream};
+
+#[test]
+fn limit_of_zero() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let err = LimitedListener::new(listener, 0, LimitMode::Wait)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn shutdown_at_limit() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = listener.shutdown_handle().unwrap();
+    let limited = LimitedListener::new(listener, 1, LimitMode::Wait)
+        .unwrap();
+
+    let _client = UnixStream::connect(addr).unwrap();
+    let (conn, _) = limited.accept().unwrap();
+    let waiter = thread::spawn(move || {
+        let err = limited.accept().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+        drop(conn);
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    shutdown.shutdown().unwrap();
+    waiter.join().unwrap();
+}
+
+#[test]
+fn server_shutdown_at_limit() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(listener).unwrap()
+        .max_connections(1, LimitMode::Wait);
+    let shutdown = server.shutdown_handle();
+
+    let (release, held) = mpsc::channel::<()>();
+    let held = Arc::new(Mutex::new(held));
+    let (started, handling) = mpsc::channel();
+    let serving = thread::spawn(move || {
+        server.serve(move |_, _| {
+            started.send(()).unwrap();
+            let _ = held.lock().unwrap().recv();
+            Ok(())
+        })
+    });
+
+    let _first = UnixStream::connect(addr).unwrap();
+    handling.recv_timeout(Duration::from_secs(5)).unwrap();
+    let _second = UnixStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    shutdown.shutdown().unwrap();
+    serving.join().unwrap().unwrap();
+    drop(release);
+}
+
+#[test]
+fn worker_pool_of_zero() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let server = Server::new(listener).unwrap().worker_pool(0, 1);
+    let err = server.serve(|_, _| Ok(())).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn server_shutdown_with_full_queue() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(listener).unwrap().worker_pool(1, 0);
+    let shutdown = server.shutdown_handle();
+
+    let (release, held) = mpsc::channel::<()>();
+    let held = Arc::new(Mutex::new(held));
+    let (started, handling) = mpsc::channel();
+    let serving = thread::spawn(move || {
+        server.serve(move |_, _| {
+            started.send(()).unwrap();
+            let _ = held.lock().unwrap().recv();
+            Ok(())
+        })
+    });
+
+    // the one worker is busy and the queue has no room, so the server
+    // waits to hand over the second connection
+    let _first = UnixStream::connect(addr).unwrap();
+    handling.recv_timeout(Duration::from_secs(5)).unwrap();
+    let _second = UnixStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    shutdown.shutdown().unwrap();
+    serving.join().unwrap().unwrap();
+    drop(release);
+}
+
+#[test]
+fn worker_pool_without_queue() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(listener).unwrap().worker_pool(2, 0);
+    let shutdown = server.shutdown_handle();
+    let serving = thread::spawn(move || {
+        server.serve(|mut conn, _| conn.write_all(b"x"))
+    });
+
+    for _ in 0..10 {
+        let mut client = UnixStream::connect(addr).unwrap();
+        let mut buf = [0; 1];
+        client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"x");
+    }
+
+    shutdown.shutdown().unwrap();
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn limit_counts_open_connections() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let limited = LimitedListener::new(listener, 2, LimitMode::Wait)
+        .unwrap();
+    assert_eq!(limited.max(), 2);
+
+    let clients = (0..3).map(|_| UnixStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+    let (mut first, _) = limited.accept().unwrap();
+    let (second, _) = limited.accept().unwrap();
+    assert_eq!(limited.active(), 2);
+    first.write_all(b"x").unwrap();
+
+    // the third waits until a place is given back
+    let limited = Arc::new(limited);
+    let waiter = {
+        let limited = limited.clone();
+        thread::spawn(move || limited.accept().map(|_| ()))
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!waiter.is_finished());
+    drop(first);
+    waiter.join().unwrap().unwrap();
+    assert_eq!(limited.active(), 1);
+    drop(second);
+    assert_eq!(limited.active(), 0);
+    drop(clients);
+}
+
+#[test]
+fn limit_rejects_over_limit() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let limited = LimitedListener::new(listener, 1, LimitMode::Reject)
+        .unwrap();
+
+    let _kept = UnixStream::connect(addr).unwrap();
+    let (conn, _) = limited.accept().unwrap();
+    let mut rejected = UnixStream::connect(addr).unwrap();
+
+    // while the limit is reached, connections are accepted and closed
+    let limited = Arc::new(limited);
+    let (done, accepted) = mpsc::channel();
+    let acceptor = {
+        let limited = limited.clone();
+        thread::spawn(move || {
+            let res = limited.accept().map(|(mut conn, _)| {
+                conn.write_all(b"ok").unwrap();
+            });
+            done.send(()).unwrap();
+            res
+        })
+    };
+    let mut buf = Vec::new();
+    assert_eq!(rejected.read_to_end(&mut buf).unwrap(), 0);
+    assert!(accepted.recv_timeout(Duration::from_millis(50)).is_err());
+
+    // once the place is free, the next connection is served
+    drop(conn);
+    let mut served = UnixStream::connect(addr).unwrap();
+    acceptor.join().unwrap().unwrap();
+    let mut buf = [0; 2];
+    served.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ok");
+}
+
+#[test]
+fn server_thread_per_connection() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(listener).unwrap();
+    let shutdown = server.shutdown_handle();
+    let (started, handling) = mpsc::channel();
+    let started = Mutex::new(started);
+    let serving = thread::spawn(move || {
+        server.serve(move |mut conn, _| {
+            started.lock().unwrap().send(()).unwrap();
+            let mut buf = Vec::new();
+            conn.read_to_end(&mut buf)?;
+            conn.write_all(&buf)
+        })
+    });
+
+    // both handlers run at once, each still waiting for its client
+    let mut clients = (0..2).map(|_| UnixStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+    for _ in 0..2 {
+        handling.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    for client in &mut clients {
+        client.write_all(b"echo").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"echo");
+    }
+
+    shutdown.shutdown().unwrap();
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_stopped_by_handler_error() {
+    let listener = SeqpacketListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (errors, reported) = mpsc::channel();
+    let errors = Mutex::new(errors);
+    let server = Server::new(listener).unwrap()
+        .on_error(move |err, source| {
+            errors.lock().unwrap().send((err.kind(), source)).unwrap();
+            ErrorAction::Stop
+        });
+    let serving = thread::spawn(move || {
+        server.serve(|conn: UnixSeqpacket, _| {
+            conn.send(b"bye")?;
+            Err(std::io::Error::new(ErrorKind::InvalidData, "bad request"))
+        })
+    });
+
+    let client = UnixSeqpacket::connect(addr).unwrap();
+    let mut buf = [0; 3];
+    client.recv(&mut buf).unwrap();
+    // the hook stops the server, which then returns cleanly
+    serving.join().unwrap().unwrap();
+    assert_eq!(reported.recv().unwrap(), (ErrorKind::InvalidData,
+                                          ErrorSource::Handler));
+}
+
+#[test]
+fn worker_pool_bounds_concurrency() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(listener).unwrap().worker_pool(2, 4);
+    let shutdown = server.shutdown_handle();
+    let (started, handling) = mpsc::channel();
+    let started = Mutex::new(started);
+    let serving = thread::spawn(move || {
+        server.serve(move |mut conn, _| {
+            started.lock().unwrap().send(()).unwrap();
+            let mut buf = Vec::new();
+            conn.read_to_end(&mut buf).map(|_| ())
+        })
+    });
+
+    // only two workers, so the third connection waits in the queue until
+    // one of the first two is closed
+    let mut clients = (0..3).map(|_| UnixStream::connect(addr).unwrap())
+        .collect::<Vec<_>>();
+    for _ in 0..2 {
+        handling.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    assert!(handling.recv_timeout(Duration::from_millis(50)).is_err());
+    drop(clients.remove(0));
+    handling.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    drop(clients);
+    shutdown.shutdown().unwrap();
+    serving.join().unwrap().unwrap();
+}
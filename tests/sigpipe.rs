@@ -0,0 +1,57 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The test harness ignores SIGPIPE, so this restores the default action,
+// which would kill the process if a send raised it. It is the only test in
+// this file, so nothing else runs with the default in place.
+
+extern crate af_unix;
+extern crate libc;
+
+use std::io::{ErrorKind, IoSlice, Write};
+use std::os::unix::io::AsFd;
+
+use af_unix::{MsgFlags, UnixSeqpacket, UnixStream};
+
+#[test]
+fn send_to_closed_peer() {
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+    let (mut a, b) = UnixStream::pair().unwrap();
+    drop(b);
+    let errs = vec![
+        a.write(b"x").unwrap_err(),
+        a.write_vectored(&[IoSlice::new(b"x")]).unwrap_err(),
+        a.send_all(b"x").unwrap_err(),
+        a.send_with_flags(b"x", MsgFlags::empty()).unwrap_err(),
+        a.send_msg(&[IoSlice::new(b"x")], None, &[], MsgFlags::empty())
+            .unwrap_err(),
+        a.send_fds(&[a.as_fd()], b"x").unwrap_err(),
+    ];
+
+    let (c, d) = UnixSeqpacket::pair().unwrap();
+    drop(d);
+    let errs = errs.into_iter().chain(Some(c.send(b"x").unwrap_err()));
+    for err in errs {
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::FromRawFd;
+
+use af_unix::UnixStream;
+
+use common::test_dir;
+
+#[test]
+fn splice_to_nonblocking_socket() {
+    let (mut tx, rx) = UnixStream::pair().unwrap();
+    let (out, mut sink) = UnixStream::pair_nonblocking().unwrap();
+    tx.write_all(b"hello").unwrap();
+
+    // A socket isn't a pipe, so this takes the copying path
+    let err = rx.splice_to(&out, 5).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    // Nothing was taken off the socket
+    out.set_nonblocking(false).unwrap();
+    assert_eq!(rx.splice_to(&out, 5).unwrap(), 5);
+    let mut buf = [0; 5];
+    sink.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+fn pipe() -> (File, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+#[test]
+fn splice_through_pipe() {
+    let (mut tx, rx) = UnixStream::pair().unwrap();
+    let (out, mut sink) = UnixStream::pair().unwrap();
+    let (mut pipe_rx, mut pipe_tx) = pipe();
+
+    tx.write_all(b"relayed").unwrap();
+    assert_eq!(rx.splice_to(&pipe_tx, 64).unwrap(), 7);
+    let mut buf = [0; 7];
+    pipe_rx.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"relayed");
+
+    pipe_tx.write_all(b"back").unwrap();
+    assert_eq!(out.splice_from(&pipe_rx, 64).unwrap(), 4);
+    sink.read_exact(&mut buf[..4]).unwrap();
+    assert_eq!(&buf[..4], b"back");
+
+    // end of file on either side is a zero-length move
+    drop(pipe_tx);
+    assert_eq!(out.splice_from(&pipe_rx, 64).unwrap(), 0);
+    drop(tx);
+    let (_pipe_rx, pipe_tx) = pipe();
+    assert_eq!(rx.splice_to(&pipe_tx, 64).unwrap(), 0);
+}
+
+#[test]
+fn send_file() {
+    let dir = test_dir("send-file");
+    let path = dir.join("data");
+    fs::write(&path, b"0123456789").unwrap();
+    let mut file = File::open(&path).unwrap();
+    file.seek(SeekFrom::Start(1)).unwrap();
+    let (tx, mut rx) = UnixStream::pair().unwrap();
+
+    assert_eq!(tx.send_file(&file, 2, 5).unwrap(), 5);
+    let mut buf = [0; 5];
+    rx.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"23456");
+
+    // the file ending first gives a short count, past the end none at all
+    assert_eq!(tx.send_file(&file, 7, 100).unwrap(), 3);
+    rx.read_exact(&mut buf[..3]).unwrap();
+    assert_eq!(&buf[..3], b"789");
+    assert_eq!(tx.send_file(&file, 10, 100).unwrap(), 0);
+
+    assert_eq!(file.stream_position().unwrap(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
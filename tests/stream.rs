@@ -0,0 +1,319 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+extern crate af_unix;
+extern crate libc;
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::io::{FromRawFd, OwnedFd};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use af_unix::{RetryPolicy, UnixDatagram, UnixListener, UnixStream};
+
+mod common;
+use common::test_dir;
+
+#[test]
+fn read_write() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut stream = UnixStream::connect(addr).unwrap();
+        stream.write_all(b"ping").unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        buf
+    });
+
+    let (mut conn, _) = listener.accept().unwrap();
+    let mut buf = [0; 4];
+    conn.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    conn.write_all(b"pong").unwrap();
+    drop(conn);
+    assert_eq!(client.join().unwrap(), b"pong");
+}
+
+#[test]
+fn pair() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    a.write_all(b"to b").unwrap();
+    b.write_all(b"to a").unwrap();
+    let mut buf = [0; 4];
+    b.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"to b");
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"to a");
+}
+
+#[test]
+fn shutdown() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    a.shutdown(Shutdown::Write).unwrap();
+    assert_eq!(b.read(&mut [0; 1]).unwrap(), 0);
+    let err = a.write(b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+
+    // the other half still works
+    b.write_all(b"y").unwrap();
+    let mut buf = [0; 1];
+    a.read_exact(&mut buf).unwrap();
+
+    b.shutdown(Shutdown::Both).unwrap();
+    assert_eq!(b.read(&mut buf).unwrap(), 0);
+    assert_eq!(a.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn addrs() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let path = addr.as_pathname().unwrap();
+    let client = UnixStream::connect(addr).unwrap();
+    let (conn, _) = listener.accept().unwrap();
+
+    assert!(client.local_addr().unwrap().is_unnamed());
+    assert_eq!(client.peer_addr().unwrap().as_pathname(), Some(path));
+    assert_eq!(conn.local_addr().unwrap().as_pathname(), Some(path));
+    assert!(conn.peer_addr().unwrap().is_unnamed());
+
+    let (a, _b) = UnixStream::pair().unwrap();
+    assert!(a.local_addr().unwrap().is_unnamed());
+    assert!(a.peer_addr().unwrap().is_unnamed());
+}
+
+#[test]
+fn timeouts() {
+    let (mut a, _b) = UnixStream::pair().unwrap();
+    assert_eq!(a.read_timeout().unwrap(), None);
+    assert_eq!(a.write_timeout().unwrap(), None);
+
+    let timeout = Duration::from_millis(20);
+    a.set_read_timeout(Some(timeout)).unwrap();
+    a.set_write_timeout(Some(timeout)).unwrap();
+    assert_eq!(a.read_timeout().unwrap(), Some(timeout));
+    assert_eq!(a.write_timeout().unwrap(), Some(timeout));
+
+    let start = Instant::now();
+    let err = a.read(&mut [0; 1]).unwrap_err();
+    assert!(err.kind() == ErrorKind::WouldBlock
+            || err.kind() == ErrorKind::TimedOut);
+    assert!(start.elapsed() >= timeout);
+
+    let err = a.set_read_timeout(Some(Duration::new(0, 0))).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    a.set_read_timeout(None).unwrap();
+    assert_eq!(a.read_timeout().unwrap(), None);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn take_error() {
+    let (mut a, b) = UnixStream::pair().unwrap();
+    assert!(a.take_error().unwrap().is_none());
+
+    // closing with unread data resets the connection
+    a.write_all(b"unread").unwrap();
+    drop(b);
+    let err = a.take_error().unwrap().unwrap();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    assert!(a.take_error().unwrap().is_none());
+}
+
+#[test]
+fn short_write() {
+    let (mut a, _b) = UnixStream::pair_nonblocking().unwrap();
+    let buf = vec![0; 4 << 20];
+    let n = a.write(&buf).unwrap();
+    assert!(n > 0 && n < buf.len());
+    let err = a.write(&buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn send_all() {
+    // a non-blocking socket waits for room instead of giving up
+    let (a, mut b) = UnixStream::pair_nonblocking().unwrap();
+    b.set_nonblocking(false).unwrap();
+    let data: Vec<u8> = (0..4 << 20).map(|i| i as u8).collect();
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        b.read_to_end(&mut buf).unwrap();
+        buf
+    });
+    a.send_all(&data).unwrap();
+    drop(a);
+    assert!(reader.join().unwrap() == data);
+
+    // with nobody reading, the write timeout ends the wait
+    let (a, _b) = UnixStream::pair().unwrap();
+    a.set_write_timeout(Some(Duration::from_millis(20))).unwrap();
+    let err = a.send_all(&data).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn vectored() {
+    let (mut a, mut b) = UnixStream::pair().unwrap();
+    assert!(a.is_write_vectored());
+    let bufs = [IoSlice::new(b"gat"), IoSlice::new(b""), IoSlice::new(b"her")];
+    assert_eq!(a.write_vectored(&bufs).unwrap(), 6);
+
+    assert!(b.is_read_vectored());
+    let (mut head, mut tail) = ([0; 2], [0; 8]);
+    let mut bufs = [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)];
+    assert_eq!(b.read_vectored(&mut bufs).unwrap(), 6);
+    assert_eq!(&head, b"ga");
+    assert_eq!(&tail[..4], b"ther");
+}
+
+#[test]
+fn start_connect() {
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+    let conn = UnixStream::start_connect(addr).unwrap().finish_connect()
+        .unwrap();
+    assert!(conn.nonblocking().unwrap());
+    assert_eq!(conn.peer_addr().unwrap().as_pathname(),
+               addr.as_pathname());
+
+    let err = UnixStream::start_connect(addr.as_pathname().unwrap()
+                                            .with_extension("missing"))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn recv_deadline() {
+    let (mut a, b) = UnixStream::pair().unwrap();
+    let start = Instant::now();
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        a.write_all(b"late").unwrap();
+        a
+    });
+    let mut buf = [0; 8];
+    let deadline = start + Duration::from_secs(5);
+    assert_eq!(b.recv_deadline(&mut buf, deadline).unwrap(), 4);
+    let _a = writer.join().unwrap();
+
+    let deadline = Instant::now() + Duration::from_millis(50);
+    let err = b.recv_deadline(&mut buf, deadline).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(Instant::now() >= deadline);
+}
+
+// Set in the child process that `from_stdio` runs `UnixStream::from_stdio()`
+// in, to the outcome expected for what its standard input is.
+const STDIO_VAR: &str = "AF_UNIX_TEST_STDIO";
+
+// Standard input has to be replaced, so each case runs in a child.
+#[test]
+fn from_stdio() {
+    if let Ok(expected) = env::var(STDIO_VAR) {
+        return stdio_child(&expected);
+    }
+
+    let run = |stdin: Stdio, expected: &str| {
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "from_stdio", "--test-threads=1", "-q"])
+            .env(STDIO_VAR, expected)
+            .stdin(stdin)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} child failed: {}", expected, status);
+    };
+
+    let (mut ours, theirs) = UnixStream::pair().unwrap();
+    run(OwnedFd::from(theirs).into(), "connected");
+    let mut buf = Vec::new();
+    ours.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"pong");
+
+    let unconnected = unsafe {
+        OwnedFd::from_raw_fd(libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0))
+    };
+    run(unconnected.into(), "NotConnected");
+
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    run(OwnedFd::from(listener.try_clone().unwrap()).into(), "InvalidInput");
+    let (_, datagram) = UnixDatagram::pair().unwrap();
+    run(OwnedFd::from(datagram).into(), "InvalidInput");
+    run(Stdio::null(), "InvalidInput");
+}
+
+fn stdio_child(expected: &str) {
+    if expected != "connected" {
+        let err = UnixStream::from_stdio().unwrap_err();
+        assert_eq!(format!("{:?}", err.kind()), expected);
+        return;
+    }
+    let mut stream = UnixStream::from_stdio().unwrap();
+    stream.write_all(b"po").unwrap();
+    drop(stream);
+    // standard input is left open, and still the connection
+    let mut stdin = UnixStream::from_stdio().unwrap();
+    stdin.write_all(b"ng").unwrap();
+}
+
+#[test]
+fn connect_with_retry() {
+    let dir = test_dir("connect_with_retry");
+    let path = dir.join("sock");
+    let policy = RetryPolicy::new()
+        .backoff(Duration::from_millis(10), Duration::from_millis(20));
+
+    // the server starts after the client
+    let bind_path = path.clone();
+    let server = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        let mut listener = UnixListener::bind(bind_path).unwrap();
+        listener.set_cleanup(false);
+        listener.accept().unwrap();
+    });
+    UnixStream::connect_with_retry(&path, &policy).unwrap();
+    server.join().unwrap();
+
+    // the socket file is left behind, with nothing listening
+    let start = Instant::now();
+    let err = UnixStream::connect_with_retry(&path, &policy.clone()
+                                             .attempts(3))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert!(start.elapsed() >= Duration::from_millis(30));
+
+    // errors that aren't retried fail at once
+    let start = Instant::now();
+    let policy = RetryPolicy::new()
+        .backoff(Duration::from_secs(10), Duration::from_secs(10))
+        .retry_on(&[ErrorKind::NotFound]);
+    let err = UnixStream::connect_with_retry(&path, &policy).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,114 @@
+// Copyright © 2015 Geoffroy Couprie
+// Copyright © 2015 Andy Grover
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// “Software”), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#![cfg(all(feature = "io-uring", target_os = "linux"))]
+
+extern crate af_unix;
+extern crate libc;
+
+use std::io::{ErrorKind, Read, Write};
+
+use af_unix::uring::{Completion, Ring};
+use af_unix::{SockType, UnixDatagram, UnixListener, UnixStream};
+
+// Kernels and sandboxes may refuse io_uring; the tests then have nothing to
+// check.
+fn ring() -> Option<Ring> {
+    match Ring::new(8) {
+        Ok(ring) => Some(ring),
+        Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS)
+            || err.kind() == ErrorKind::PermissionDenied => None,
+        Err(err) => panic!("io_uring_setup: {}", err),
+    }
+}
+
+fn wait(ring: &mut Ring) -> Completion {
+    ring.submit_and_wait(1).unwrap();
+    ring.completion().unwrap()
+}
+
+#[test]
+fn accept_recv_send() {
+    let mut ring = match ring() {
+        Some(ring) => ring,
+        None => return,
+    };
+    let listener = UnixListener::bind_in_tempdir().unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    ring.accept(&listener, 1).unwrap();
+    assert_eq!(ring.pending(), 1);
+    let mut client = UnixStream::connect(addr).unwrap();
+    let conn = match wait(&mut ring) {
+        Completion::Accept { token: 1, result } => result.unwrap().0,
+        other => panic!("unexpected completion {:?}", other),
+    };
+
+    client.write_all(b"ping").unwrap();
+    ring.recv(&conn, vec![0; 16], 2).unwrap();
+    match wait(&mut ring) {
+        Completion::Recv { token: 2, result, buf } => {
+            assert_eq!(&buf[..result.unwrap()], b"ping");
+        }
+        other => panic!("unexpected completion {:?}", other),
+    }
+
+    // the queued operation keeps its own descriptor for the socket
+    ring.send(&conn, b"pong".to_vec(), 3).unwrap();
+    drop(conn);
+    match wait(&mut ring) {
+        Completion::Send { token: 3, result, buf } => {
+            assert_eq!(result.unwrap(), 4);
+            assert_eq!(buf, b"pong");
+        }
+        other => panic!("unexpected completion {:?}", other),
+    }
+    let mut reply = Vec::new();
+    client.read_to_end(&mut reply).unwrap();
+    assert_eq!(reply, b"pong");
+    assert_eq!(ring.pending(), 0);
+    assert!(ring.completion().is_none());
+}
+
+#[test]
+fn send_msg_to_addr() {
+    let mut ring = match ring() {
+        Some(ring) => ring,
+        None => return,
+    };
+    let rx = UnixDatagram::bind_in_tempdir(SockType::Dgram).unwrap();
+    let addr = rx.local_addr().unwrap();
+    let tx = UnixDatagram::unbound().unwrap();
+
+    ring.send_msg(&tx, b"hello".to_vec(), Some(&addr), Vec::new(), 7)
+        .unwrap();
+    match wait(&mut ring) {
+        Completion::SendMsg { token, result, .. } => {
+            assert_eq!(token, 7);
+            assert_eq!(result.unwrap(), 5);
+        }
+        other => panic!("unexpected completion {:?}", other),
+    }
+    let mut buf = [0; 8];
+    assert_eq!(rx.recv_from(&mut buf).unwrap().0, 5);
+    assert_eq!(&buf[..5], b"hello");
+}